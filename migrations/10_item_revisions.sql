@@ -0,0 +1,17 @@
+-- Hash of the content we compare against when a feed sends an item we already have
+ALTER TABLE items ADD COLUMN content_hash TEXT;
+-- Set when the feed changed an item after we first ingested it
+ALTER TABLE items ADD COLUMN content_updated_at DATETIME;
+
+-- What items looked like before the feed changed them, only the last few are kept
+CREATE TABLE item_revisions (
+	id INTEGER PRIMARY KEY NOT NULL,
+	item_id INTEGER NOT NULL REFERENCES items(id) ON DELETE CASCADE,
+	link TEXT NOT NULL,
+	title TEXT,
+	description TEXT,
+	author TEXT,
+	created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX item_revisions_item_id ON item_revisions(item_id);
@@ -0,0 +1,4 @@
+-- Extra headers (a JSON object of names to values) and basic auth sent when fetching a feed
+ALTER TABLE sources ADD COLUMN headers TEXT;
+ALTER TABLE sources ADD COLUMN auth_username TEXT;
+ALTER TABLE sources ADD COLUMN auth_password TEXT;
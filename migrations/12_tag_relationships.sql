@@ -0,0 +1,75 @@
+-- tag_id was declared INTEGER even though it holds tag names, so numeric names were stored as
+-- numbers. Rebuild both join tables with TEXT ids, dropping rows that point at nothing.
+CREATE TABLE items_to_tags_new (
+	item_id INTEGER NOT NULL REFERENCES items(id) ON DELETE CASCADE,
+	tag_id TEXT NOT NULL REFERENCES tags(name) ON DELETE CASCADE ON UPDATE CASCADE,
+	created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	UNIQUE(item_id, tag_id) ON CONFLICT REPLACE
+);
+
+INSERT INTO items_to_tags_new (item_id, tag_id, created_at)
+SELECT item_id, CAST(tag_id AS TEXT), created_at
+FROM items_to_tags
+WHERE item_id IN (SELECT id FROM items) AND CAST(tag_id AS TEXT) IN (SELECT name FROM tags);
+
+DROP TABLE items_to_tags;
+ALTER TABLE items_to_tags_new RENAME TO items_to_tags;
+
+CREATE TABLE sources_to_tags_new (
+	source_id INTEGER NOT NULL REFERENCES sources(id) ON DELETE CASCADE,
+	tag_id TEXT NOT NULL REFERENCES tags(name) ON DELETE CASCADE ON UPDATE CASCADE,
+	created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	UNIQUE(source_id, tag_id) ON CONFLICT REPLACE
+);
+
+INSERT INTO sources_to_tags_new (source_id, tag_id, created_at)
+SELECT source_id, CAST(tag_id AS TEXT), created_at
+FROM sources_to_tags
+WHERE source_id IN (SELECT id FROM sources) AND CAST(tag_id AS TEXT) IN (SELECT name FROM tags);
+
+DROP TABLE sources_to_tags;
+ALTER TABLE sources_to_tags_new RENAME TO sources_to_tags;
+
+-- The unique constraints cover lookups by item, source and user, these cover the other side
+CREATE INDEX items_to_tags_tag_id ON items_to_tags(tag_id);
+CREATE INDEX sources_to_tags_tag_id ON sources_to_tags(tag_id);
+CREATE INDEX user_items_to_tags_item_id ON user_items_to_tags(item_id);
+CREATE INDEX user_items_to_tags_tag_id ON user_items_to_tags(tag_id);
+CREATE INDEX user_items_item_id ON user_items(item_id);
+CREATE INDEX subscriptions_source_id ON subscriptions(source_id);
+
+-- update_sources and update_items updated tags instead of their own table. Setting updated_at
+-- explicitly now keeps that value.
+DROP TRIGGER update_sources;
+DROP TRIGGER update_items;
+DROP TRIGGER update_tags;
+
+CREATE TRIGGER update_sources
+AFTER UPDATE ON sources
+FOR EACH ROW
+WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE sources
+    SET updated_at = CURRENT_TIMESTAMP
+    WHERE id = OLD.id;
+END;
+
+CREATE TRIGGER update_items
+AFTER UPDATE ON items
+FOR EACH ROW
+WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE items
+    SET updated_at = CURRENT_TIMESTAMP
+    WHERE id = OLD.id;
+END;
+
+CREATE TRIGGER update_tags
+AFTER UPDATE ON tags
+FOR EACH ROW
+WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE tags
+    SET updated_at = CURRENT_TIMESTAMP
+    WHERE name = NEW.name;
+END;
@@ -0,0 +1,8 @@
+-- Categories from feeds that get tagged with another tag instead of becoming their own
+CREATE TABLE tag_aliases (
+	alias TEXT PRIMARY KEY NOT NULL,
+	tag_id TEXT NOT NULL REFERENCES tags(name) ON DELETE CASCADE ON UPDATE CASCADE,
+	created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX tag_aliases_tag_id ON tag_aliases(tag_id);
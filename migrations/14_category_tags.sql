@@ -0,0 +1,2 @@
+-- Per source override of how feed categories become tags: 'all', 'existing' or 'ignore'
+ALTER TABLE sources ADD COLUMN category_tags TEXT;
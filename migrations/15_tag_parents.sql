@@ -0,0 +1,4 @@
+-- Tags nest under a parent like folders, deleting a tag moves its children to the top level
+ALTER TABLE tags ADD COLUMN parent TEXT REFERENCES tags(name) ON DELETE SET NULL ON UPDATE CASCADE;
+
+CREATE INDEX tags_parent ON tags(parent);
@@ -0,0 +1,20 @@
+CREATE TABLE smart_feeds (
+	id INTEGER PRIMARY KEY NOT NULL,
+	user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	name TEXT NOT NULL,
+	-- JSON definition of which items are in the feed
+	query TEXT NOT NULL,
+	created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	UNIQUE(user_id, name)
+);
+
+CREATE TRIGGER update_smart_feeds
+AFTER UPDATE ON smart_feeds
+FOR EACH ROW
+WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE smart_feeds
+    SET updated_at = CURRENT_TIMESTAMP
+    WHERE id = OLD.id;
+END;
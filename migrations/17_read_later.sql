@@ -0,0 +1,28 @@
+-- Each user's queue of items to read, in the order they put them
+CREATE TABLE read_later (
+	user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	item_id INTEGER NOT NULL REFERENCES items(id) ON DELETE CASCADE,
+	-- Lower comes first
+	position INTEGER NOT NULL,
+	-- Percent of the item read
+	progress INTEGER NOT NULL DEFAULT 0,
+	-- Words in the item's extracted content, NULL if none could be extracted
+	word_count INTEGER,
+	-- Set once the item is finished
+	archived_at DATETIME,
+	created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	PRIMARY KEY (user_id, item_id)
+);
+
+CREATE INDEX read_later_item_id ON read_later(item_id);
+
+CREATE TRIGGER update_read_later
+AFTER UPDATE ON read_later
+FOR EACH ROW
+WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE read_later
+    SET updated_at = CURRENT_TIMESTAMP
+    WHERE user_id = OLD.user_id AND item_id = OLD.item_id;
+END;
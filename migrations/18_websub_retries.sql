@@ -0,0 +1,3 @@
+-- When we last asked the hub to subscribe, and how many requests in a row it hasn't verified
+ALTER TABLE sources ADD COLUMN websub_requested_at DATETIME;
+ALTER TABLE sources ADD COLUMN websub_attempts INTEGER NOT NULL DEFAULT 0;
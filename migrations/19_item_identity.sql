@@ -0,0 +1,62 @@
+-- items.link was unique across every source, so a new guid reusing an old link was rejected and
+-- then overwrote the older item, and no two feeds could link the same page. Items from feeds are
+-- now the same item when their source and guid match, or their source and link if they have no
+-- guid. Links stay unique among items added by hand.
+--
+-- SQLite can't drop a column's constraint, so items is rebuilt (db::migrate runs this with
+-- foreign keys off so dropping the old table doesn't cascade).
+
+CREATE TABLE items_new (
+	id INTEGER PRIMARY KEY NOT NULL,
+	link TEXT NOT NULL,
+	title TEXT,
+	description TEXT,
+	author TEXT,
+	published DATETIME,
+	source_link TEXT,
+	image TEXT,
+	created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	source_id INTEGER,
+	archived_at DATETIME,
+	guid TEXT,
+	guid_is_permalink BOOLEAN NOT NULL DEFAULT FALSE,
+	content_hash TEXT,
+	content_updated_at DATETIME
+);
+
+INSERT INTO items_new (
+	id, link, title, description, author, published, source_link, image, created_at, updated_at,
+	source_id, archived_at, guid, guid_is_permalink, content_hash, content_updated_at
+)
+SELECT
+	id, link, title, description, author, published, source_link, image, created_at, updated_at,
+	source_id, archived_at, guid, guid_is_permalink, content_hash, content_updated_at
+FROM items;
+
+DROP TABLE items;
+ALTER TABLE items_new RENAME TO items;
+
+CREATE INDEX items_created_at ON items(created_at);
+CREATE UNIQUE INDEX items_source_guid ON items(source_id, guid);
+CREATE UNIQUE INDEX items_source_link ON items(source_id, link) WHERE guid IS NULL;
+CREATE UNIQUE INDEX items_link ON items(link) WHERE source_id IS NULL;
+
+CREATE TRIGGER bury_items
+AFTER DELETE ON items
+FOR EACH ROW
+WHEN OLD.source_id IN (SELECT id FROM sources)
+BEGIN
+	INSERT INTO tombstones (source_id, link, guid)
+	VALUES (OLD.source_id, OLD.link, OLD.guid);
+END;
+
+CREATE TRIGGER update_items
+AFTER UPDATE ON items
+FOR EACH ROW
+WHEN NEW.updated_at IS OLD.updated_at
+BEGIN
+    UPDATE items
+    SET updated_at = CURRENT_TIMESTAMP
+    WHERE id = OLD.id;
+END;
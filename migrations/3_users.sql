@@ -0,0 +1,65 @@
+CREATE TABLE users (
+	id INTEGER PRIMARY KEY NOT NULL,
+	name TEXT NOT NULL UNIQUE,
+	password_hash TEXT,
+	admin BOOLEAN NOT NULL DEFAULT FALSE,
+	created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- The owner of the instance, authenticated with the configured password
+INSERT INTO users (id, name, admin) VALUES (1, 'admin', TRUE);
+
+CREATE TABLE subscriptions (
+	user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	source_id INTEGER NOT NULL REFERENCES sources(id) ON DELETE CASCADE,
+	created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	UNIQUE(user_id, source_id) ON CONFLICT IGNORE
+);
+
+CREATE TABLE user_items (
+	user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	item_id INTEGER NOT NULL REFERENCES items(id) ON DELETE CASCADE,
+	done BOOLEAN NOT NULL DEFAULT FALSE,
+	favorite BOOLEAN NOT NULL DEFAULT FALSE,
+	created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	PRIMARY KEY (user_id, item_id)
+);
+
+CREATE TABLE user_items_to_tags (
+	user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	item_id INTEGER NOT NULL REFERENCES items(id) ON DELETE CASCADE,
+	tag_id TEXT NOT NULL REFERENCES tags(name) ON DELETE CASCADE,
+	created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	UNIQUE(user_id, item_id, tag_id) ON CONFLICT IGNORE
+);
+
+CREATE TRIGGER update_users
+AFTER UPDATE ON users
+FOR EACH ROW
+BEGIN
+    UPDATE users
+    SET updated_at = CURRENT_TIMESTAMP
+    WHERE id = OLD.id;
+END;
+
+CREATE TRIGGER update_user_items
+AFTER UPDATE OF done, favorite ON user_items
+FOR EACH ROW
+BEGIN
+    UPDATE user_items
+    SET updated_at = CURRENT_TIMESTAMP
+    WHERE user_id = OLD.user_id AND item_id = OLD.item_id;
+END;
+
+-- Everything that existed before users belongs to the owner
+INSERT INTO subscriptions (user_id, source_id)
+SELECT 1, id FROM sources;
+
+INSERT INTO user_items (user_id, item_id, done, favorite)
+SELECT 1, id, done, favorite FROM items
+WHERE done OR favorite OR source_id IS NULL;
+
+ALTER TABLE items DROP COLUMN done;
+ALTER TABLE items DROP COLUMN favorite;
@@ -0,0 +1,45 @@
+CREATE TABLE webhooks (
+	id INTEGER PRIMARY KEY NOT NULL,
+	user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	url TEXT NOT NULL,
+	secret TEXT,
+	-- Comma separated filters, NULL matches everything
+	tags TEXT,
+	source_ids TEXT,
+	created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE webhook_deliveries (
+	id INTEGER PRIMARY KEY NOT NULL,
+	webhook_id INTEGER NOT NULL REFERENCES webhooks(id) ON DELETE CASCADE,
+	item_id INTEGER NOT NULL REFERENCES items(id) ON DELETE CASCADE,
+	attempts INTEGER NOT NULL DEFAULT 0,
+	status_code INTEGER,
+	error TEXT,
+	-- NULL once delivered or given up on
+	next_attempt_at DATETIME,
+	delivered_at DATETIME,
+	created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX webhook_deliveries_next_attempt_at ON webhook_deliveries(next_attempt_at);
+
+CREATE TRIGGER update_webhooks
+AFTER UPDATE ON webhooks
+FOR EACH ROW
+BEGIN
+    UPDATE webhooks
+    SET updated_at = CURRENT_TIMESTAMP
+    WHERE id = OLD.id;
+END;
+
+CREATE TRIGGER update_webhook_deliveries
+AFTER UPDATE ON webhook_deliveries
+FOR EACH ROW
+BEGIN
+    UPDATE webhook_deliveries
+    SET updated_at = CURRENT_TIMESTAMP
+    WHERE id = OLD.id;
+END;
@@ -0,0 +1,4 @@
+ALTER TABLE sources ADD COLUMN websub_hub TEXT;
+ALTER TABLE sources ADD COLUMN websub_topic TEXT;
+ALTER TABLE sources ADD COLUMN websub_secret TEXT;
+ALTER TABLE sources ADD COLUMN websub_lease_expires_at DATETIME;
@@ -0,0 +1,8 @@
+-- Expired items are hidden from feeds instead of deleted when archiving is enabled
+ALTER TABLE items ADD COLUMN archived_at DATETIME;
+
+-- Per source overrides of the retention policy in seconds, 0 keeps items forever
+ALTER TABLE sources ADD COLUMN retain_done_for INTEGER;
+ALTER TABLE sources ADD COLUMN retain_unread_for INTEGER;
+
+CREATE INDEX items_created_at ON items(created_at);
@@ -0,0 +1,19 @@
+-- Links of items that were deleted, so polling their source doesn't bring them back
+CREATE TABLE tombstones (
+	source_id INTEGER NOT NULL REFERENCES sources(id) ON DELETE CASCADE,
+	link TEXT NOT NULL,
+	created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	UNIQUE(source_id, link) ON CONFLICT REPLACE
+);
+
+CREATE INDEX tombstones_created_at ON tombstones(created_at);
+
+-- Every way an item gets deleted leaves a tombstone, as long as its source is still around
+CREATE TRIGGER bury_items
+AFTER DELETE ON items
+FOR EACH ROW
+WHEN OLD.source_id IN (SELECT id FROM sources)
+BEGIN
+	INSERT INTO tombstones (source_id, link)
+	VALUES (OLD.source_id, OLD.link);
+END;
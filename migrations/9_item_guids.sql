@@ -0,0 +1,18 @@
+ALTER TABLE items ADD COLUMN guid TEXT;
+ALTER TABLE items ADD COLUMN guid_is_permalink BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Items with a guid are the same item as long as their source says so, even if the link changed
+CREATE UNIQUE INDEX items_source_guid ON items(source_id, guid);
+
+ALTER TABLE tombstones ADD COLUMN guid TEXT;
+
+DROP TRIGGER bury_items;
+
+CREATE TRIGGER bury_items
+AFTER DELETE ON items
+FOR EACH ROW
+WHEN OLD.source_id IN (SELECT id FROM sources)
+BEGIN
+	INSERT INTO tombstones (source_id, link, guid)
+	VALUES (OLD.source_id, OLD.link, OLD.guid);
+END;
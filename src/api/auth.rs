@@ -1,18 +1,232 @@
-use axum::extract;
-use http::{HeaderMap, StatusCode};
+use std::sync::LazyLock;
 
-use crate::{config::Config, ApiError};
+use argon2::{
+	password_hash::{
+		rand_core::{OsRng, RngCore},
+		PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+	},
+	Argon2,
+};
+use axum::{extract, middleware::Next, response::Response, Json};
+use chrono::Utc;
+use http::{header::AUTHORIZATION, HeaderMap};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use crate::{
+	db::{user::OWNER_ID, ApiToken, User},
+	ApiError,
+};
 
 use super::State;
 
-pub async fn is_authorized(config: &Config, headers: &HeaderMap) -> Result<(), ApiError> {
-	if headers.get("x-auth").is_some_and(|h| h.as_bytes() == config.password.as_bytes()) {
-		Ok(())
+static DUMMY_HASH: LazyLock<String> = LazyLock::new(|| hash_password("dummy").unwrap());
+
+/// What a request is allowed to do. Password logins can do everything, API tokens only what they
+/// were created with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scope {
+	#[serde(rename = "read")]
+	Read,
+	#[serde(rename = "items:write")]
+	ItemsWrite,
+	#[serde(rename = "sources:write")]
+	SourcesWrite,
+	#[serde(rename = "tags:write")]
+	TagsWrite,
+	#[serde(rename = "webhooks:write")]
+	WebhooksWrite,
+	/// Managing users and tokens, never granted to tokens
+	#[serde(rename = "account")]
+	Account,
+}
+
+impl Scope {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Scope::Read => "read",
+			Scope::ItemsWrite => "items:write",
+			Scope::SourcesWrite => "sources:write",
+			Scope::TagsWrite => "tags:write",
+			Scope::WebhooksWrite => "webhooks:write",
+			Scope::Account => "account",
+		}
+	}
+}
+
+/// Authenticates the request and checks that it may act with `scope`.
+///
+/// Accepts `Authorization: Bearer <token>` for API tokens, or an `x-auth` header which is either
+/// the instance password (for the owner) or `name:password` for any other user.
+pub async fn authorize(state: &State, headers: &HeaderMap, scope: Scope) -> Result<User, ApiError> {
+	if let Some(token) = headers
+		.get(AUTHORIZATION)
+		.and_then(|h| h.to_str().ok())
+		.and_then(|h| h.strip_prefix("Bearer "))
+	{
+		let api_token = ApiToken::get_by_hash(&hash_token(token.trim()), &state.sqlite)
+			.await?
+			.ok_or(ApiError::Unauthorized)?;
+		// Every token can read
+		if scope != Scope::Read && !api_token.scopes.split(',').any(|s| s == scope.as_str()) {
+			return Err(ApiError::Forbidden);
+		}
+		ApiToken::touch(api_token.id, &state.sqlite).await?;
+		return User::get_by_id(api_token.user_id, &state.sqlite)
+			.await?
+			.ok_or(ApiError::Unauthorized);
+	}
+
+	let auth = headers
+		.get("x-auth")
+		.and_then(|h| h.to_str().ok())
+		.ok_or(ApiError::Unauthorized)?;
+
+	// Compare digests so the comparison doesn't leak the password's length either
+	let is_owner = Sha256::digest(auth.as_bytes())
+		.ct_eq(&Sha256::digest(state.config.password.as_bytes()));
+	if is_owner.into() {
+		return User::get_by_id(OWNER_ID, &state.sqlite)
+			.await?
+			.ok_or(ApiError::Unauthorized);
+	}
+
+	let (name, password) = auth.split_once(':').ok_or(ApiError::Unauthorized)?;
+	let user = User::get_by_name(name, &state.sqlite).await?;
+	let hash = user
+		.as_ref()
+		.and_then(|user| user.password_hash.as_deref())
+		// Still verify when there is no user so the response time doesn't reveal which names exist
+		.unwrap_or(&DUMMY_HASH);
+
+	let verified = PasswordHash::new(hash)
+		.is_ok_and(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok());
+	match user {
+		Some(user) if verified => Ok(user),
+		_ => Err(ApiError::Unauthorized),
+	}
+}
+
+/// Like [`authorize`] for reading, but anonymous requests see the owner's feed (unless the instance is
+/// private, in which case [`require_auth`] already rejected them)
+pub async fn user_or_owner(state: &State, headers: &HeaderMap) -> Result<User, ApiError> {
+	if headers.contains_key("x-auth") || headers.contains_key(AUTHORIZATION) {
+		authorize(state, headers, Scope::Read).await
+	} else {
+		User::get_by_id(OWNER_ID, &state.sqlite)
+			.await?
+			.ok_or(ApiError::NotFound)
+	}
+}
+
+/// Layer for private instances that rejects unauthenticated requests to anything not in
+/// `Config::public_paths`
+pub async fn require_auth(
+	extract::State(state): extract::State<State>,
+	req: extract::Request,
+	next: Next,
+) -> Result<Response, ApiError> {
+	let path = req.uri().path();
+	let is_public = state.config.public_paths.iter().any(|public| {
+		path.strip_prefix(&**public)
+			.is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+	});
+
+	if !is_public {
+		authorize(&state, req.headers(), Scope::Read).await?;
+	}
+	Ok(next.run(req).await)
+}
+
+pub async fn authorize_admin(state: &State, headers: &HeaderMap) -> Result<User, ApiError> {
+	let user = authorize(state, headers, Scope::Account).await?;
+	if user.admin {
+		Ok(user)
 	} else {
-		Err(ApiError::Unauthorized)
+		Err(ApiError::Forbidden)
 	}
 }
 
-pub async fn login(extract::State(state): extract::State<State>, headers: HeaderMap) -> Result<(), ApiError> {
-	is_authorized(&state.config, &headers).await
+pub fn hash_password(password: &str) -> Result<String, ApiError> {
+	let salt = SaltString::generate(&mut OsRng);
+	Ok(Argon2::default()
+		.hash_password(password.as_bytes(), &salt)
+		.map_err(|err| ApiError::BadRequest(err.to_string()))?
+		.to_string())
+}
+
+/// Tokens are random so a fast hash is enough to keep them useless if the db leaks
+fn hash_token(token: &str) -> String {
+	hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+pub async fn login(extract::State(state): extract::State<State>, headers: HeaderMap) -> Result<Json<User>, ApiError> {
+	Ok(Json(authorize(&state, &headers, Scope::Read).await?))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateToken {
+	name: String,
+	scopes: Vec<Scope>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreateTokenReturn {
+	#[serde(flatten)]
+	api_token: ApiToken,
+	/// Only ever returned here
+	token: String,
+}
+
+pub async fn create_token(
+	extract::State(state): extract::State<State>,
+	headers: HeaderMap,
+	Json(new_token): Json<CreateToken>,
+) -> Result<Json<CreateTokenReturn>, ApiError> {
+	let user = authorize(&state, &headers, Scope::Account).await?;
+	if new_token.scopes.contains(&Scope::Account) {
+		return Err(ApiError::BadRequest("tokens can't have the account scope".into()));
+	}
+
+	let mut bytes = [0u8; 32];
+	OsRng.fill_bytes(&mut bytes);
+	let token = format!("mf_{}", hex::encode(bytes));
+
+	let mut api_token = ApiToken {
+		// Filled in by db
+		id: 0,
+		created_at: Utc::now().naive_utc(),
+		last_used_at: None,
+
+		user_id: user.id,
+		name: new_token.name,
+		token_hash: hash_token(&token),
+		scopes: new_token
+			.scopes
+			.iter()
+			.map(Scope::as_str)
+			.collect::<Vec<_>>()
+			.join(","),
+	};
+	api_token.insert(&state.sqlite).await?;
+	Ok(Json(CreateTokenReturn { api_token, token }))
+}
+
+pub async fn get_tokens(
+	extract::State(state): extract::State<State>,
+	headers: HeaderMap,
+) -> Result<Json<Vec<ApiToken>>, ApiError> {
+	let user = authorize(&state, &headers, Scope::Account).await?;
+	Ok(Json(ApiToken::get_for_user(user.id, &state.sqlite).await?))
+}
+
+pub async fn delete_token(
+	extract::State(state): extract::State<State>,
+	headers: HeaderMap,
+	extract::Path(id): extract::Path<i64>,
+) -> Result<(), ApiError> {
+	let user = authorize(&state, &headers, Scope::Account).await?;
+	ApiToken::delete(id, user.id, &state.sqlite).await?;
+	Ok(())
 }
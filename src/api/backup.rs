@@ -0,0 +1,160 @@
+//! Copies of the database. Downloads and scheduled snapshots are SQLite files, restore one by
+//! stopping the server and putting it in place of `data_dir/db`. Exports are JSON that can be
+//! imported into a running instance, even one on a newer schema.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use axum::{
+    extract::State,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Utc;
+use http::{
+    header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    HeaderMap,
+};
+use sqlx::{Pool, Sqlite};
+
+use crate::{
+    config::Config,
+    db::{
+        self,
+        export::{Export, ImportCounts, EXPORT_VERSION},
+    },
+    ApiError,
+};
+
+use super::auth::authorize_admin;
+
+/// Exports include every item, so they can be much bigger than other request bodies
+pub const MAX_IMPORT_BYTES: usize = 256 * 1024 * 1024;
+const SNAPSHOT_PREFIX: &str = "my-feed-";
+const SNAPSHOT_SUFFIX: &str = ".db";
+
+fn backups_dir(config: &Config) -> PathBuf {
+    config.data_dir.join("backups")
+}
+
+/// Starts the task that writes a snapshot every `backup_interval`, if it's set
+pub fn start_backups(config: Arc<Config>, sqlite: Pool<Sqlite>) {
+    let Some(interval) = config.backup_interval else {
+        return;
+    };
+
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            match snapshot(&config, &sqlite).await {
+                Ok(path) => tracing::info!("Backed up db to {}", path.display()),
+                Err(err) => tracing::error!("Error backing up db: {err:?}"),
+            }
+        }
+    });
+}
+
+async fn backup_into(path: &Path, sqlite: &Pool<Sqlite>) -> Result<(), ApiError> {
+    let path = path
+        .to_str()
+        .ok_or_else(|| io::Error::other("backup path isn't UTF-8"))?;
+    Ok(db::backup_into(path, sqlite).await?)
+}
+
+/// Writes a timestamped snapshot to the backups dir and deletes the oldest ones past
+/// `backups_kept`
+pub async fn snapshot(config: &Config, sqlite: &Pool<Sqlite>) -> Result<PathBuf, ApiError> {
+    let dir = backups_dir(config);
+    tokio::fs::create_dir_all(&dir).await?;
+    let path = dir.join(format!(
+        "{SNAPSHOT_PREFIX}{}{SNAPSHOT_SUFFIX}",
+        Utc::now().format("%Y%m%d-%H%M%S%.3f")
+    ));
+    backup_into(&path, sqlite).await?;
+
+    let mut snapshots = Vec::new();
+    let mut entries = tokio::fs::read_dir(&dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with(SNAPSHOT_PREFIX) && name.ends_with(SNAPSHOT_SUFFIX) {
+            snapshots.push(entry.path());
+        }
+    }
+    // The timestamps sort oldest first
+    snapshots.sort();
+    let excess = snapshots.len().saturating_sub(config.backups_kept.max(1));
+    for old in &snapshots[..excess] {
+        tokio::fs::remove_file(old).await?;
+    }
+
+    Ok(path)
+}
+
+/// The whole database as a SQLite file
+pub async fn download_backup(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    authorize_admin(&state, &headers).await?;
+
+    let dir = backups_dir(&state.config);
+    tokio::fs::create_dir_all(&dir).await?;
+    let path = dir.join(format!("download-{}.tmp", OsRng.next_u64()));
+    let backup = async {
+        backup_into(&path, &state.sqlite).await?;
+        Ok::<_, ApiError>(tokio::fs::read(&path).await?)
+    }
+    .await;
+    tokio::fs::remove_file(&path).await.ok();
+
+    let filename = format!(
+        "{SNAPSHOT_PREFIX}{}{SNAPSHOT_SUFFIX}",
+        Utc::now().format("%Y%m%d-%H%M%S")
+    );
+    Ok((
+        [
+            (CONTENT_TYPE, String::from("application/vnd.sqlite3")),
+            (
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{filename}\""),
+            ),
+        ],
+        backup?,
+    )
+        .into_response())
+}
+
+pub async fn export(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+) -> Result<Json<Export>, ApiError> {
+    let user = authorize_admin(&state, &headers).await?;
+    Ok(Json(Export::load(user.id, &state.sqlite).await?))
+}
+
+/// Adds everything in an export that isn't already here, the importing user is subscribed to its
+/// sources
+pub async fn import(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+    Json(export): Json<Export>,
+) -> Result<Json<ImportCounts>, ApiError> {
+    let user = authorize_admin(&state, &headers).await?;
+    if export.version > EXPORT_VERSION {
+        return Err(ApiError::BadRequest(format!(
+            "Export version {} is newer than this server supports",
+            export.version
+        )));
+    }
+
+    let counts = export.import(user.id, &state.sqlite).await?;
+    if counts.sources > 0 {
+        state.poll_send.send(()).await.ok();
+    }
+    Ok(Json(counts))
+}
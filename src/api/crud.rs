@@ -1,37 +1,74 @@
-use std::time::Duration;
+use std::{collections::BTreeMap, time::Duration};
 
 use axum::{
     extract::{Path, Query, State},
+    response::{IntoResponse, Response},
     Json,
 };
 use chrono::Utc;
-use http::HeaderMap;
+use http::{HeaderMap, HeaderName, HeaderValue};
+use itertools::Itertools;
 use rustc_hash::FxHashSet;
 use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
 
 use crate::{
-    db::{Item, Source, Tag},
+    config::Config,
+    db::{
+        item::{ItemRevision, ItemWTags},
+        tag::{TagNode, TagUsage},
+        user::OWNER_ID,
+        CategoryTags, FeedSort, Item, Source, Tag, TagAlias, User,
+    },
     ApiError,
 };
 
-use super::{auth::is_authorized, rss::get_channel_for_source};
+use super::{
+    auth::{authorize, authorize_admin, hash_password, user_or_owner, Scope},
+    dates, images,
+    rss::get_channel_for_source,
+};
 
 pub async fn create_tag(
     State(state): State<super::State>,
     headers: HeaderMap,
     Json(mut tag): Json<Tag>,
 ) -> Result<Json<Tag>, ApiError> {
-    is_authorized(&state.config, &headers).await?;
+    authorize(&state, &headers, Scope::TagsWrite).await?;
+    check_parent(&tag, &state).await?;
     tag.insert(&state.sqlite).await?;
     Ok(Json(tag))
 }
 
+/// A tag's parent has to exist and can't be the tag itself or nested under it
+async fn check_parent(tag: &Tag, state: &super::State) -> Result<(), ApiError> {
+    let Some(parent) = &tag.parent else {
+        return Ok(());
+    };
+    if Tag::get_by_name(parent, &state.sqlite).await?.is_none() {
+        return Err(ApiError::BadRequest(format!(
+            "Parent tag {parent} doesn't exist"
+        )));
+    }
+    if *parent == tag.name
+        || Tag::descendants(&tag.name, &state.sqlite)
+            .await?
+            .contains(parent)
+    {
+        return Err(ApiError::BadRequest(format!(
+            "Tag {} can't be nested under itself",
+            tag.name
+        )));
+    }
+    Ok(())
+}
+
 pub async fn delete_tag(
     State(state): State<super::State>,
     headers: HeaderMap,
     Path(name): Path<String>,
 ) -> Result<(), ApiError> {
-    is_authorized(&state.config, &headers).await?;
+    authorize(&state, &headers, Scope::TagsWrite).await?;
     Tag::delete(&name, &state.sqlite).await?;
     Ok(())
 }
@@ -52,13 +89,168 @@ pub async fn update_tag(
     headers: HeaderMap,
     Json(mut tag): Json<Tag>,
 ) -> Result<(), ApiError> {
-    is_authorized(&state.config, &headers).await?;
+    authorize(&state, &headers, Scope::TagsWrite).await?;
+    check_parent(&tag, &state).await?;
     tag.update(&state.sqlite).await?;
     Ok(())
 }
 
-pub async fn get_tags(State(state): State<super::State>) -> Result<Json<Vec<Tag>>, ApiError> {
-    Ok(Json(Tag::get_all(&state.sqlite).await?))
+#[derive(Debug, Deserialize)]
+pub struct GetTagsQuery {
+    /// Nest tags under their parents instead of listing them
+    #[serde(default)]
+    tree: bool,
+}
+
+pub async fn get_tags(
+    State(state): State<super::State>,
+    Query(query): Query<GetTagsQuery>,
+) -> Result<Response, ApiError> {
+    let tags = Tag::get_all(&state.sqlite).await?;
+    Ok(if query.tree {
+        Json(TagNode::tree(tags)).into_response()
+    } else {
+        Json(tags).into_response()
+    })
+}
+
+pub async fn get_tag_usage(
+    State(state): State<super::State>,
+) -> Result<Json<Vec<TagUsage>>, ApiError> {
+    Ok(Json(Tag::usage(&state.sqlite).await?))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PruneTags {
+    /// Tags on more items than this are kept
+    #[serde(default)]
+    max_items: i64,
+    /// Only tags at least this old are deleted, so new categories get a chance to catch on
+    #[serde(default, with = "humantime_serde")]
+    older_than: Option<Duration>,
+}
+
+/// Deletes rarely used tags that no source or alias needs, returns their names
+pub async fn prune_tags(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+    Json(body): Json<PruneTags>,
+) -> Result<Json<Vec<String>>, ApiError> {
+    authorize(&state, &headers, Scope::TagsWrite).await?;
+    let older_than = chrono::Duration::from_std(body.older_than.unwrap_or_default())
+        .map_err(|_| ApiError::BadRequest("older_than is too long".into()))?;
+    let created_before = Utc::now().naive_utc() - older_than;
+    Ok(Json(
+        Tag::prune(body.max_items, created_before, &state.sqlite).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RenameTag {
+    name: String,
+}
+
+pub async fn rename_tag(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+    Json(body): Json<RenameTag>,
+) -> Result<Json<Tag>, ApiError> {
+    authorize(&state, &headers, Scope::TagsWrite).await?;
+    if body.name.is_empty() {
+        return Err(ApiError::BadRequest("Tag name can't be empty".into()));
+    }
+    Tag::get_by_name(&name, &state.sqlite)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    if Tag::get_by_name(&body.name, &state.sqlite).await?.is_some() {
+        return Err(ApiError::BadRequest(format!(
+            "Tag {} already exists, merge into it instead",
+            body.name
+        )));
+    }
+
+    Tag::rename(&name, &body.name, &state.sqlite).await?;
+    Ok(Json(
+        Tag::get_by_name(&body.name, &state.sqlite)
+            .await?
+            .ok_or(ApiError::NotFound)?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MergeTags {
+    tags: Vec<String>,
+    into: String,
+}
+
+/// Retags everything tagged with `tags` with `into` and deletes them
+pub async fn merge_tags(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+    Json(body): Json<MergeTags>,
+) -> Result<Json<Tag>, ApiError> {
+    authorize(&state, &headers, Scope::TagsWrite).await?;
+    if body.tags.is_empty() {
+        return Err(ApiError::BadRequest("No tags to merge".into()));
+    }
+    let into = Tag::get_by_name(&body.into, &state.sqlite)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    let tags = body
+        .tags
+        .iter()
+        .map(String::as_str)
+        .unique()
+        .collect::<Vec<_>>();
+    for name in &tags {
+        if Tag::get_by_name(name, &state.sqlite).await?.is_none() {
+            return Err(ApiError::BadRequest(format!("Tag {name} doesn't exist")));
+        }
+    }
+    Tag::merge(&tags, &into.name, &state.sqlite).await?;
+    Ok(Json(into))
+}
+
+pub async fn get_tag_aliases(
+    State(state): State<super::State>,
+) -> Result<Json<Vec<TagAlias>>, ApiError> {
+    Ok(Json(TagAlias::get_all(&state.sqlite).await?))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTagAlias {
+    alias: String,
+    tag: String,
+}
+
+/// Makes future items with the `alias` category get tagged with `tag` instead
+pub async fn create_tag_alias(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+    Json(body): Json<CreateTagAlias>,
+) -> Result<(), ApiError> {
+    authorize(&state, &headers, Scope::TagsWrite).await?;
+    // Categories are lowercased before they're matched
+    let alias = body.alias.to_ascii_lowercase();
+    if alias.is_empty() || alias == body.tag {
+        return Err(ApiError::BadRequest("Invalid alias".into()));
+    }
+    Tag::get_by_name(&body.tag, &state.sqlite)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    TagAlias::insert(&alias, &body.tag, &state.sqlite).await?;
+    Ok(())
+}
+
+pub async fn delete_tag_alias(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+    Path(alias): Path<String>,
+) -> Result<(), ApiError> {
+    authorize(&state, &headers, Scope::TagsWrite).await?;
+    TagAlias::delete(&alias, &state.sqlite).await?;
+    Ok(())
 }
 
 pub async fn create_item(
@@ -66,9 +258,30 @@ pub async fn create_item(
     headers: HeaderMap,
     Json(mut item): Json<Item>,
 ) -> Result<Json<Item>, ApiError> {
-    is_authorized(&state.config, &headers).await?;
-    item.insert(&state.sqlite).await?;
-    Ok(Json(item))
+    let user = authorize(&state, &headers, Scope::ItemsWrite).await?;
+    // Only polling puts items in a source, otherwise anyone could post into feeds they don't
+    // follow or claim a guid before the real entry shows up
+    item.source_id = None;
+    item.guid = None;
+    item.guid_is_permalink = false;
+    Ok(Json(add_item(item, user.id, &state.sqlite).await?))
+}
+
+/// Adds an item by hand to `user_id`'s feed, or the existing one if someone already added its link
+pub async fn add_item(
+    mut item: Item,
+    user_id: i64,
+    sqlite: &Pool<Sqlite>,
+) -> Result<Item, ApiError> {
+    if let Err(err) = item.insert(sqlite).await {
+        // Someone already added this link, add it to this user's feed instead
+        item = match Item::get_by_link(&item.link, sqlite).await? {
+            Some(existing) => existing,
+            None => return Err(err.into()),
+        };
+    }
+    Item::track(item.id, user_id, sqlite).await?;
+    Ok(item)
 }
 
 pub async fn delete_item(
@@ -76,19 +289,36 @@ pub async fn delete_item(
     headers: HeaderMap,
     Path(id): Path<i64>,
 ) -> Result<(), ApiError> {
-    is_authorized(&state.config, &headers).await?;
-    Item::delete(id, &state.sqlite).await?;
+    let user = authorize(&state, &headers, Scope::ItemsWrite).await?;
+    let item = Item::get_for_user(id, user.id, &state.sqlite)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+
+    if user.admin {
+        Item::delete(id, &state.sqlite).await?;
+    } else if item.item.source_id.is_none() {
+        // Items added by hand are only removed once nobody has them anymore
+        Item::untrack(id, user.id, &state.sqlite).await?;
+        Item::delete_if_orphaned(id, &state.sqlite).await?;
+    } else {
+        return Err(ApiError::Forbidden);
+    }
     Ok(())
 }
 
 pub async fn get_item(
     State(state): State<super::State>,
+    headers: HeaderMap,
     Path(id): Path<i64>,
-) -> Result<Json<Item>, ApiError> {
+) -> Result<Json<GetItemsReturn>, ApiError> {
+    let user = user_or_owner(&state, &headers).await?;
     Ok(Json(
-        Item::get_by_id(id, &state.sqlite)
-            .await?
-            .ok_or(ApiError::NotFound)?,
+        GetItemsReturn::from(
+            Item::get_for_user(id, user.id, &state.sqlite)
+                .await?
+                .ok_or(ApiError::NotFound)?,
+        )
+        .with_proxied_image(&state.config),
     ))
 }
 
@@ -98,92 +328,401 @@ pub struct GetItemsQuery {
     from_last: Duration,
     #[serde(default)]
     include_done: bool,
+    #[serde(default)]
+    sort: FeedSort,
+    /// Comma separated, only items with all of these tags (or tags nested under them)
+    #[serde(default)]
+    tags: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct GetItemsReturn {
     #[serde(flatten)]
     pub item: Item,
+    pub done: bool,
+    pub favorite: bool,
     pub tags: FxHashSet<String>,
+    /// Whether the feed changed the item after it was first ingested
+    pub updated: bool,
+}
+
+impl From<ItemWTags> for GetItemsReturn {
+    fn from(item_w_tags: ItemWTags) -> Self {
+        Self {
+            updated: item_w_tags.item.content_updated_at.is_some(),
+            item: item_w_tags.item,
+            done: item_w_tags.done,
+            favorite: item_w_tags.favorite,
+            tags: item_w_tags
+                .tags
+                .unwrap_or_default()
+                .split(",")
+                .filter(|s| !s.is_empty())
+                .map(ToString::to_string)
+                .collect(),
+        }
+    }
+}
+
+impl GetItemsReturn {
+    /// Points `image` at our image proxy instead of the publisher
+    pub fn with_proxied_image(mut self, config: &Config) -> Self {
+        if let Some(image) = &self.item.image {
+            self.item.image = Some(images::proxied_url(config, self.item.id, image));
+        }
+        self
+    }
 }
 
 pub async fn get_items(
     State(state): State<super::State>,
+    headers: HeaderMap,
     Query(query): Query<GetItemsQuery>,
 ) -> Result<Json<Vec<GetItemsReturn>>, ApiError> {
+    let user = user_or_owner(&state, &headers).await?;
+    let tags = query
+        .tags
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .filter(|tag| !tag.is_empty())
+        .collect::<Vec<_>>();
     Ok(Json(
-        Item::feed(query.from_last, query.include_done, &state.sqlite)
-            .await?
-            .into_iter()
-            .map(|item_w_tags| GetItemsReturn {
-                item: item_w_tags.item,
-                tags: item_w_tags
-                    .tags
-                    .unwrap_or_default()
-                    .split(",")
-                    .filter(|s| !s.is_empty())
-                    .map(ToString::to_string)
-                    .collect(),
-            })
-            .collect(),
+        Item::feed(
+            user.id,
+            query.from_last,
+            query.include_done,
+            query.sort,
+            &tags,
+            &state.sqlite,
+        )
+        .await?
+        .into_iter()
+        .map(|item| GetItemsReturn::from(item).with_proxied_image(&state.config))
+        .collect(),
     ))
 }
 
+pub async fn get_item_revisions(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+) -> Result<Json<Vec<ItemRevision>>, ApiError> {
+    let user = user_or_owner(&state, &headers).await?;
+    Item::get_for_user(id, user.id, &state.sqlite)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    Ok(Json(Item::revisions(id, &state.sqlite).await?))
+}
+
 pub async fn done(
     State(state): State<super::State>,
     Path(id): Path<i64>,
     headers: HeaderMap,
 ) -> Result<(), ApiError> {
-    is_authorized(&state.config, &headers).await?;
-    Item::set_done(id, true, &state.sqlite).await?;
+    let user = authorize(&state, &headers, Scope::ItemsWrite).await?;
+    Item::get_for_user(id, user.id, &state.sqlite)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    Item::set_done(id, user.id, true, &state.sqlite).await?;
+    Ok(())
+}
+
+pub async fn favorite(
+    State(state): State<super::State>,
+    Path(id): Path<i64>,
+    headers: HeaderMap,
+) -> Result<(), ApiError> {
+    let user = authorize(&state, &headers, Scope::ItemsWrite).await?;
+    Item::get_for_user(id, user.id, &state.sqlite)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    Item::set_favorite(id, user.id, true, &state.sqlite).await?;
+    Ok(())
+}
+
+pub async fn unfavorite(
+    State(state): State<super::State>,
+    Path(id): Path<i64>,
+    headers: HeaderMap,
+) -> Result<(), ApiError> {
+    let user = authorize(&state, &headers, Scope::ItemsWrite).await?;
+    Item::get_for_user(id, user.id, &state.sqlite)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    Item::set_favorite(id, user.id, false, &state.sqlite).await?;
     Ok(())
 }
 
+pub async fn add_item_tags(
+    State(state): State<super::State>,
+    Path(id): Path<i64>,
+    headers: HeaderMap,
+    Json(tags): Json<Vec<String>>,
+) -> Result<(), ApiError> {
+    let user = authorize(&state, &headers, Scope::ItemsWrite).await?;
+    Item::get_for_user(id, user.id, &state.sqlite)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    Item::add_user_tags(
+        id,
+        user.id,
+        &tags.iter().map(String::as_str).collect::<Vec<_>>(),
+        &state.sqlite,
+    )
+    .await?;
+    Ok(())
+}
+
+pub async fn remove_item_tag(
+    State(state): State<super::State>,
+    Path((id, name)): Path<(i64, String)>,
+    headers: HeaderMap,
+) -> Result<(), ApiError> {
+    let user = authorize(&state, &headers, Scope::ItemsWrite).await?;
+    Item::get_for_user(id, user.id, &state.sqlite)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    Item::remove_user_tag(id, user.id, &name, &state.sqlite).await?;
+    Ok(())
+}
+
+/// Headers and basic auth for feeds that need them. Never sent back since they're usually secrets.
+#[derive(Debug, Default, Deserialize)]
+pub struct SourceCredentials {
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    #[serde(default)]
+    username: Option<String>,
+    #[serde(default)]
+    password: Option<String>,
+}
+
+impl SourceCredentials {
+    fn apply(self, source: &mut Source) -> Result<(), ApiError> {
+        for (name, value) in &self.headers {
+            if HeaderName::from_bytes(name.as_bytes()).is_err()
+                || HeaderValue::from_str(value).is_err()
+            {
+                return Err(ApiError::BadRequest(format!("Invalid header {name}")));
+            }
+        }
+
+        source.headers =
+            (!self.headers.is_empty()).then(|| serde_json::to_string(&self.headers).unwrap());
+        source.auth_username = self.username;
+        source.auth_password = self.password;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateSource {
+    #[serde(flatten)]
+    source: Source,
+    #[serde(flatten)]
+    credentials: SourceCredentials,
+}
+
 pub async fn create_source(
     State(state): State<super::State>,
     headers: HeaderMap,
-    Json(mut source): Json<Source>,
+    Json(CreateSource {
+        mut source,
+        credentials,
+    }): Json<CreateSource>,
 ) -> Result<Json<Source>, ApiError> {
-    is_authorized(&state.config, &headers).await?;
+    let user = authorize(&state, &headers, Scope::SourcesWrite).await?;
+    credentials.apply(&mut source)?;
+
+    // Sources are shared, so just subscribe to it if someone already added it. Only with the
+    // credentials it was added with though, otherwise anyone could read a private feed by its url.
+    if let Some(existing) = Source::get_by_url(&source.url, &state.sqlite).await? {
+        let same_credentials = (
+            &existing.headers,
+            &existing.auth_username,
+            &existing.auth_password,
+        ) == (
+            &source.headers,
+            &source.auth_username,
+            &source.auth_password,
+        );
+        if !same_credentials {
+            return Err(if existing.has_credentials() {
+                ApiError::Forbidden
+            } else {
+                ApiError::BadRequest(
+                    "This feed was added without credentials, an admin can set them".into(),
+                )
+            });
+        }
+        User::subscribe(user.id, existing.id, &state.sqlite).await?;
+        return Ok(Json(existing));
+    }
+
+    // Retention and category tags affect everyone subscribed, so only admins get to override them
+    if !user.admin {
+        source.retain_done_for = None;
+        source.retain_unread_for = None;
+        source.category_tags = None;
+    }
+    if let Some(category_tags) = &source.category_tags {
+        category_tags
+            .parse::<CategoryTags>()
+            .map_err(ApiError::BadRequest)?;
+    }
 
     // Check that the channel actual exists and populate last_pub and ttl
-    let channel = get_channel_for_source(&state.client, &source).await?;
+    let channel = get_channel_for_source(&state.fetcher, &source).await?;
     source.last_pub = channel
         .pub_date
-        .and_then(|pub_date| {
-            chrono::DateTime::parse_from_rfc2822(&pub_date)
-                .ok()
-                .map(|dt| dt.naive_utc())
-        })
+        .as_deref()
+        .and_then(dates::parse)
+        .map(|dt| dt.naive_utc())
         .unwrap_or(Utc::now().naive_utc());
     source.ttl = channel.ttl.and_then(|ttl| ttl.parse().ok());
 
     source.insert(&state.sqlite).await?;
+    User::subscribe(user.id, source.id, &state.sqlite).await?;
     state.poll_send.send(()).await.ok();
     Ok(Json(source))
 }
 
+/// Sources are shared, so only admins can change credentials once a source exists
+pub async fn set_source_credentials(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+    Json(credentials): Json<SourceCredentials>,
+) -> Result<(), ApiError> {
+    authorize_admin(&state, &headers).await?;
+    let mut source = Source::get_by_id(id, &state.sqlite)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    credentials.apply(&mut source)?;
+    Source::set_credentials(
+        id,
+        source.headers.as_deref(),
+        source.auth_username.as_deref(),
+        source.auth_password.as_deref(),
+        &state.sqlite,
+    )
+    .await?;
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceCategoryTags {
+    #[serde(default)]
+    category_tags: Option<CategoryTags>,
+}
+
+/// Overrides how the source's categories become tags, or goes back to the configured default
+pub async fn set_source_category_tags(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+    Json(body): Json<SourceCategoryTags>,
+) -> Result<(), ApiError> {
+    authorize_admin(&state, &headers).await?;
+    Source::get_by_id(id, &state.sqlite)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    Source::set_category_tags(id, body.category_tags, &state.sqlite).await?;
+    Ok(())
+}
+
 pub async fn delete_source(
     State(state): State<super::State>,
     headers: HeaderMap,
     Path(id): Path<i64>,
 ) -> Result<(), ApiError> {
-    is_authorized(&state.config, &headers).await?;
-    Source::delete(id, &state.sqlite).await?;
+    let user = authorize(&state, &headers, Scope::SourcesWrite).await?;
+    Source::unsubscribe(
+        id,
+        user.id,
+        state.config.source_items_on_delete,
+        &state.sqlite,
+    )
+    .await?;
     Ok(())
 }
 
 pub async fn get_source(
     State(state): State<super::State>,
+    headers: HeaderMap,
     Path(id): Path<i64>,
 ) -> Result<Json<Source>, ApiError> {
+    let user = user_or_owner(&state, &headers).await?;
     Ok(Json(
-        Source::get_by_id(id, &state.sqlite)
+        Source::get_by_id_for_user(id, user.id, &state.sqlite)
             .await?
             .ok_or(ApiError::NotFound)?,
     ))
 }
 
-pub async fn get_sources(State(state): State<super::State>) -> Result<Json<Vec<Source>>, ApiError> {
-    Ok(Json(Source::get_all(&state.sqlite).await?))
+pub async fn get_sources(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<Source>>, ApiError> {
+    let user = user_or_owner(&state, &headers).await?;
+    Ok(Json(Source::get_for_user(user.id, &state.sqlite).await?))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateUser {
+    name: String,
+    password: String,
+    #[serde(default)]
+    admin: bool,
+}
+
+pub async fn create_user(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+    Json(new_user): Json<CreateUser>,
+) -> Result<Json<User>, ApiError> {
+    authorize_admin(&state, &headers).await?;
+    let now = Utc::now().naive_utc();
+    let mut user = User {
+        // Filled in by db
+        id: 0,
+        created_at: now,
+        updated_at: now,
+
+        name: new_user.name,
+        password_hash: Some(hash_password(&new_user.password)?),
+        admin: new_user.admin,
+    };
+    user.insert(&state.sqlite).await?;
+    Ok(Json(user))
+}
+
+pub async fn delete_user(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+) -> Result<(), ApiError> {
+    authorize_admin(&state, &headers).await?;
+    if id == OWNER_ID {
+        return Err(ApiError::BadRequest("can't delete the owner".into()));
+    }
+    User::delete(id, &state.sqlite).await?;
+    Ok(())
+}
+
+pub async fn get_users(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<User>>, ApiError> {
+    authorize_admin(&state, &headers).await?;
+    Ok(Json(User::get_all(&state.sqlite).await?))
+}
+
+pub async fn get_me(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+) -> Result<Json<User>, ApiError> {
+    Ok(Json(authorize(&state, &headers, Scope::Read).await?))
 }
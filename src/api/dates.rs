@@ -0,0 +1,356 @@
+//! Lenient parsing of the dates feeds put in `pubDate` and friends. RFC 2822 is what RSS asks for,
+//! but feeds in the wild also use RFC 3339, skip or get the weekday wrong, name their time zone,
+//! use two-digit years or leave the time zone out entirely.
+
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime};
+
+/// Formats tried once the weekday and time zone have been stripped
+const DATE_TIME_FORMATS: &[&str] = &[
+    "%d %b %Y %H:%M:%S%.f",
+    "%d %b %Y %H:%M",
+    "%b %d %Y %H:%M:%S%.f",
+    "%b %d %Y %H:%M",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%d %H:%M",
+    "%Y/%m/%d %H:%M:%S%.f",
+    "%Y/%m/%d %H:%M",
+];
+
+const DATE_FORMATS: &[&str] = &["%d %b %Y", "%b %d %Y", "%Y-%m-%d", "%Y/%m/%d"];
+
+const MONTHS: &[&str] = &[
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+const WEEKDAYS: &[&str] = &[
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+    "sunday",
+];
+
+/// Abbreviations feeds commonly use, in minutes east of UTC. Where one is ambiguous the most common
+/// meaning in English-language feeds wins.
+const NAMED_ZONES: &[(&str, i32)] = &[
+    ("UT", 0),
+    ("UTC", 0),
+    ("GMT", 0),
+    ("Z", 0),
+    ("WET", 0),
+    ("WEST", 60),
+    ("BST", 60),
+    ("CET", 60),
+    ("CEST", 120),
+    ("EET", 120),
+    ("EEST", 180),
+    ("MSK", 180),
+    ("IST", 330),
+    ("AWST", 480),
+    ("JST", 540),
+    ("KST", 540),
+    ("ACST", 570),
+    ("AEST", 600),
+    ("ACDT", 630),
+    ("AEDT", 660),
+    ("NZST", 720),
+    ("NZDT", 780),
+    ("HST", -600),
+    ("AKST", -540),
+    ("AKDT", -480),
+    ("PST", -480),
+    ("PDT", -420),
+    ("MST", -420),
+    ("MDT", -360),
+    ("CST", -360),
+    ("CDT", -300),
+    ("EST", -300),
+    ("EDT", -240),
+    ("AST", -240),
+    ("ADT", -180),
+];
+
+/// Parses a feed date, assuming UTC when it has no time zone
+pub fn parse(s: &str) -> Option<DateTime<FixedOffset>> {
+    let s = s.trim();
+    if let Ok(date) = DateTime::parse_from_rfc2822(s) {
+        return Some(date);
+    }
+    if let Ok(date) = DateTime::parse_from_rfc3339(s) {
+        return Some(date);
+    }
+
+    let normalized = normalize_separators(s);
+    let mut tokens = normalized.split_whitespace().collect::<Vec<_>>();
+    // The weekday adds nothing and feeds often get it wrong, which the strict parsers reject
+    if tokens.first().is_some_and(|token| is_weekday(token)) {
+        tokens.remove(0);
+    }
+    let offset = take_offset(&mut tokens)?;
+
+    let rest = tokens
+        .iter()
+        .map(|token| month_abbreviation(token).unwrap_or(token))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let naive = DATE_TIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(&rest, format).ok())
+        .or_else(|| {
+            DATE_FORMATS.iter().find_map(|format| {
+                NaiveDate::parse_from_str(&rest, format)
+                    .ok()
+                    .map(|date| date.and_time(NaiveTime::MIN))
+            })
+        })?;
+
+    naive_with_full_year(naive)?
+        .checked_sub_offset(offset)
+        .map(|utc| DateTime::from_naive_utc_and_offset(utc, offset))
+}
+
+/// Turns commas and the `T` between an ISO 8601 date and time into spaces
+fn normalize_separators(s: &str) -> String {
+    let bytes = s.as_bytes();
+    s.char_indices()
+        .map(|(i, c)| match c {
+            ',' => ' ',
+            'T' | 't'
+                if i > 0
+                    && bytes[i - 1].is_ascii_digit()
+                    && bytes.get(i + 1).is_some_and(u8::is_ascii_digit) =>
+            {
+                ' '
+            }
+            c => c,
+        })
+        .collect()
+}
+
+fn is_weekday(token: &str) -> bool {
+    let token = token.to_ascii_lowercase();
+    token.len() >= 3 && WEEKDAYS.iter().any(|day| day.starts_with(&token))
+}
+
+/// Removes the time zone from the end of `tokens`, either on its own or stuck to the time. Returns
+/// `None` if there's something that looks like a zone but isn't one we know.
+fn take_offset(tokens: &mut Vec<&str>) -> Option<FixedOffset> {
+    let Some(last) = tokens.last().copied() else {
+        return Some(FixedOffset::east_opt(0).unwrap());
+    };
+
+    // `GMT+0100`, `UTC-5`
+    let upper = last.to_ascii_uppercase();
+    let without_prefix = ["GMT", "UTC"]
+        .iter()
+        .find_map(|prefix| upper.strip_prefix(prefix))
+        .filter(|rest| rest.starts_with(['+', '-']));
+    if let Some(rest) = without_prefix {
+        tokens.pop();
+        return parse_numeric_offset(rest);
+    }
+
+    if last.starts_with(['+', '-']) {
+        tokens.pop();
+        return parse_numeric_offset(last);
+    }
+
+    if last.chars().all(|c| c.is_ascii_alphabetic()) && !is_month(last) {
+        tokens.pop();
+        let minutes = NAMED_ZONES
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(last))?
+            .1;
+        return FixedOffset::east_opt(minutes * 60);
+    }
+
+    // Zones stuck to the time, like `10:00:00Z` or `10:00:00+01:00`
+    if last.contains(':') {
+        if let Some(time) = last.strip_suffix(['Z', 'z']) {
+            *tokens.last_mut().unwrap() = time;
+            return FixedOffset::east_opt(0);
+        }
+        if let Some(index) = last.find(['+', '-']) {
+            let (time, offset) = last.split_at(index);
+            *tokens.last_mut().unwrap() = time;
+            return parse_numeric_offset(offset);
+        }
+    }
+
+    Some(FixedOffset::east_opt(0).unwrap())
+}
+
+/// Parses `+hh`, `+h`, `+hhmm` and `+hh:mm`
+fn parse_numeric_offset(s: &str) -> Option<FixedOffset> {
+    let sign = match s.as_bytes().first()? {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let digits = s[1..].replace(':', "");
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let (hours, minutes) = match digits.len() {
+        1 | 2 => (digits.parse::<i32>().ok()?, 0),
+        4 => (digits[..2].parse().ok()?, digits[2..].parse::<i32>().ok()?),
+        _ => return None,
+    };
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+fn is_month(token: &str) -> bool {
+    month_abbreviation(token).is_some()
+}
+
+/// The three letter name chrono understands for full or shortened (like `Sept`) month names
+fn month_abbreviation(token: &str) -> Option<&'static str> {
+    let token = token.to_ascii_lowercase();
+    if token.len() < 3 {
+        return None;
+    }
+    MONTHS
+        .iter()
+        .find(|month| month.starts_with(&token))
+        .map(|month| &month[..3])
+}
+
+/// Two-digit years are read the way RFC 2822 says to: 00-49 are 2000-2049, 50-99 are 1950-1999
+fn naive_with_full_year(naive: NaiveDateTime) -> Option<NaiveDateTime> {
+    match naive.year() {
+        0..50 => naive.with_year(naive.year() + 2000),
+        50..100 => naive.with_year(naive.year() + 1900),
+        _ => Some(naive),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Dates seen in real feeds and what they should parse to, in RFC 3339
+    const CORPUS: &[(&str, &str)] = &[
+        // RFC 2822, as the spec intends
+        (
+            "Sat, 01 Mar 2025 12:00:00 +0000",
+            "2025-03-01T12:00:00+00:00",
+        ),
+        ("Tue, 10 Jun 2003 04:00:00 GMT", "2003-06-10T04:00:00+00:00"),
+        ("Mon, 3 Feb 2025 9:05:00 -0500", "2025-02-03T09:05:00-05:00"),
+        ("Wed, 02 Oct 2002 08:00:00 EST", "2002-10-02T08:00:00-05:00"),
+        ("Wed, 02 Oct 2002 15:00:00 PDT", "2002-10-02T15:00:00-07:00"),
+        ("Fri, 21 Nov 1997 09:55 -0600", "1997-11-21T09:55:00-06:00"),
+        // Missing or wrong weekdays
+        ("01 Mar 2025 12:00:00 +0000", "2025-03-01T12:00:00+00:00"),
+        (
+            "Mon, 01 Mar 2025 12:00:00 +0000",
+            "2025-03-01T12:00:00+00:00",
+        ),
+        (
+            "Saturday, 01 March 2025 12:00:00 GMT",
+            "2025-03-01T12:00:00+00:00",
+        ),
+        (
+            "Thurs, 06 Mar 2025 08:30:00 +0100",
+            "2025-03-06T08:30:00+01:00",
+        ),
+        // Named zones chrono doesn't know
+        ("Thu, 06 Mar 2025 08:30:00 UTC", "2025-03-06T08:30:00+00:00"),
+        ("Thu, 06 Mar 2025 08:30:00 CET", "2025-03-06T08:30:00+01:00"),
+        (
+            "Thu, 06 Mar 2025 08:30:00 AEDT",
+            "2025-03-06T08:30:00+11:00",
+        ),
+        ("Thu, 06 Mar 2025 08:30:00 IST", "2025-03-06T08:30:00+05:30"),
+        ("Thu, 06 Mar 2025 08:30:00 est", "2025-03-06T08:30:00-05:00"),
+        (
+            "Thu, 06 Mar 2025 08:30:00 GMT+0100",
+            "2025-03-06T08:30:00+01:00",
+        ),
+        (
+            "Thu, 06 Mar 2025 08:30:00 UTC-5",
+            "2025-03-06T08:30:00-05:00",
+        ),
+        // Odd offsets
+        (
+            "Thu, 06 Mar 2025 08:30:00 +01:00",
+            "2025-03-06T08:30:00+01:00",
+        ),
+        ("Thu, 06 Mar 2025 08:30:00 +01", "2025-03-06T08:30:00+01:00"),
+        // Two-digit years
+        ("Thu, 06 Mar 25 08:30:00 +0000", "2025-03-06T08:30:00+00:00"),
+        ("Tue, 10 Jun 03 04:00:00 GMT", "2003-06-10T04:00:00+00:00"),
+        ("21 Nov 97 09:55:06 GMT", "1997-11-21T09:55:06+00:00"),
+        // Full and unusual month names
+        (
+            "Thu, 06 March 2025 08:30:00 +0000",
+            "2025-03-06T08:30:00+00:00",
+        ),
+        (
+            "Tue, 09 Sept 2025 08:30:00 +0000",
+            "2025-09-09T08:30:00+00:00",
+        ),
+        ("March 6, 2025 08:30:00 +0000", "2025-03-06T08:30:00+00:00"),
+        // RFC 3339 and other ISO 8601 shapes
+        ("2025-03-06T08:30:00Z", "2025-03-06T08:30:00+00:00"),
+        (
+            "2025-03-06T08:30:00.123+02:00",
+            "2025-03-06T08:30:00.123+02:00",
+        ),
+        ("2025-03-06T08:30:00+0200", "2025-03-06T08:30:00+02:00"),
+        ("2025-03-06 08:30:00 +02:00", "2025-03-06T08:30:00+02:00"),
+        ("2025-03-06T08:30:00", "2025-03-06T08:30:00+00:00"),
+        ("2025-03-06 08:30", "2025-03-06T08:30:00+00:00"),
+        ("2025/03/06 08:30:00", "2025-03-06T08:30:00+00:00"),
+        // Dates without times
+        ("2025-03-06", "2025-03-06T00:00:00+00:00"),
+        ("06 Mar 2025", "2025-03-06T00:00:00+00:00"),
+        ("Thu, 06 Mar 2025", "2025-03-06T00:00:00+00:00"),
+        // Whitespace
+        (
+            "  Thu,  06 Mar 2025\t08:30:00 +0000 \n",
+            "2025-03-06T08:30:00+00:00",
+        ),
+    ];
+
+    #[test]
+    fn parses_corpus() {
+        for (input, expected) in CORPUS {
+            let expected = DateTime::parse_from_rfc3339(expected).unwrap();
+            let parsed = parse(input);
+            assert_eq!(parsed, Some(expected), "parsing {input:?}");
+            assert_eq!(
+                parsed.unwrap().offset(),
+                expected.offset(),
+                "offset of {input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        for input in [
+            "",
+            "yesterday",
+            "Thu, 06 Mar 2025 08:30:00 XYZ",
+            "32 Mar 2025",
+            "2025-13-01",
+        ] {
+            assert_eq!(parse(input), None, "parsing {input:?}");
+        }
+    }
+}
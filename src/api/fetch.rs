@@ -0,0 +1,424 @@
+//! Fetching pages and images that feeds link to. Those links are untrusted, so bodies are capped
+//! while streaming (`Content-Length` can be missing or lie) and, unless `allow_private_addresses`
+//! is set, hosts that resolve to private or loopback addresses are refused so a feed can't point
+//! us at services on our own network.
+
+use std::{
+    borrow::Cow,
+    error::Error as StdError,
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use encoding_rs::{Encoding, UTF_8};
+use http::header::CONTENT_TYPE;
+use mime::Mime;
+use reqwest::{
+    dns::{Addrs, Name, Resolve, Resolving},
+    redirect, Url,
+};
+use thiserror::Error;
+
+use crate::{config::Config, ApiError};
+
+/// How far into a page to look for a `<meta>` charset, same as browsers
+const META_PRESCAN_BYTES: usize = 1024;
+
+#[derive(Debug, Error)]
+pub enum FetchError {
+    #[error("{0} is a private address")]
+    PrivateAddress(String),
+    #[error("Response is over {0} bytes")]
+    TooLarge(usize),
+    #[error("{0:?}")]
+    Reqwest(reqwest::Error),
+}
+
+impl From<reqwest::Error> for FetchError {
+    /// Our resolver and redirect policy can only fail requests with reqwest errors, dig the
+    /// reason back out of them
+    fn from(err: reqwest::Error) -> Self {
+        let mut source = err.source();
+        while let Some(inner) = source {
+            if let Some(PrivateAddress(host)) = inner.downcast_ref::<PrivateAddress>() {
+                return Self::PrivateAddress(host.clone());
+            }
+            source = inner.source();
+        }
+        Self::Reqwest(err)
+    }
+}
+
+impl From<FetchError> for ApiError {
+    fn from(err: FetchError) -> Self {
+        match err {
+            FetchError::Reqwest(err) => Self::Reqwest(err),
+            err => Self::BadRequest(err.to_string()),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PrivateAddress(String);
+
+impl fmt::Display for PrivateAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} is a private address", self.0)
+    }
+}
+
+impl StdError for PrivateAddress {}
+
+/// Whether `ip` is reachable on the public internet
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_v4(ip),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // "This network", shared address space (CGNAT), benchmarking and reserved
+        || a == 0
+        || (a == 100 && (64..128).contains(&b))
+        || (a == 198 && (18..20).contains(&b))
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let [a, b, c, d, e, f, ..] = ip.segments();
+    // NAT64 (64:ff9b::/96) reaches the IPv4 address in the last 32 bits, so it's only as public
+    // as that address. DNS64 hands these out for every IPv4-only host, so they can't all go.
+    if [a, b, c, d, e, f] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        let [.., w, x, y, z] = ip.octets();
+        return is_public_v4(Ipv4Addr::new(w, x, y, z));
+    }
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // Unique local, link local, documentation and local use NAT64
+        || (a & 0xfe00) == 0xfc00
+        || (a & 0xffc0) == 0xfe80
+        || (a == 0x2001 && b == 0x0db8)
+        || (a == 0x64 && b == 0xff9b && c == 1))
+}
+
+/// Whether `url` names a private address directly, the resolver never sees those
+fn is_private_literal(url: &Url) -> bool {
+    url.host_str()
+        .map(|host| host.trim_start_matches('[').trim_end_matches(']'))
+        .and_then(|host| host.parse::<IpAddr>().ok())
+        .is_some_and(|ip| !is_public(ip))
+}
+
+/// Resolves with the system resolver, dropping private addresses. Checking here rather than
+/// before the request means a host can't pass the check and then resolve somewhere else.
+#[derive(Debug)]
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            let addrs = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect::<Vec<SocketAddr>>();
+            if addrs.is_empty() {
+                return Err(Box::new(PrivateAddress(host)) as Box<_>);
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// A page or image that was fetched in full
+#[derive(Debug)]
+pub struct Fetched {
+    pub mime: Mime,
+    pub body: Vec<u8>,
+}
+
+pub fn is_html(mime: &Mime) -> bool {
+    mime.essence_str() == "text/html" || mime.essence_str() == "application/xhtml+xml"
+}
+
+impl Fetched {
+    /// Decodes the body using (in order) a byte order mark, the `Content-Type` charset, a
+    /// `<meta>` charset for HTML, or UTF-8. Invalid sequences are replaced.
+    pub fn text(&self) -> Cow<'_, str> {
+        let encoding = self
+            .mime
+            .get_param(mime::CHARSET)
+            .and_then(|charset| Encoding::for_label(charset.as_str().as_bytes()))
+            .or_else(|| {
+                is_html(&self.mime)
+                    .then(|| meta_charset(&self.body))
+                    .flatten()
+            })
+            .unwrap_or(UTF_8);
+        encoding.decode(&self.body).0
+    }
+}
+
+/// Finds the charset declared by `<meta charset>` or `<meta http-equiv="Content-Type">` near
+/// the start of a page
+fn meta_charset(body: &[u8]) -> Option<&'static Encoding> {
+    let head = body[..body.len().min(META_PRESCAN_BYTES)].to_ascii_lowercase();
+    let mut rest = head.as_slice();
+    while let Some(start) = find(rest, b"<meta") {
+        let tag = &rest[start..];
+        let tag = &tag[..find(tag, b">").unwrap_or(tag.len())];
+        if let Some(charset) = find(tag, b"charset") {
+            let value = tag[charset + b"charset".len()..].trim_ascii_start();
+            if let Some(value) = value.strip_prefix(b"=") {
+                let value = value.trim_ascii_start();
+                let value = value
+                    .strip_prefix(b"\"")
+                    .or_else(|| value.strip_prefix(b"'"))
+                    .unwrap_or(value);
+                let end = value
+                    .iter()
+                    .position(|b| {
+                        matches!(b, b'"' | b'\'' | b';' | b'/') || b.is_ascii_whitespace()
+                    })
+                    .unwrap_or(value.len());
+                if let Some(encoding) = Encoding::for_label(&value[..end]) {
+                    return Some(encoding);
+                }
+            }
+        }
+        rest = &rest[start + b"<meta".len()..];
+    }
+    None
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Reads a response's body, giving up as soon as it goes over `max_bytes`
+pub async fn read_capped(
+    mut res: reqwest::Response,
+    max_bytes: usize,
+) -> Result<Vec<u8>, FetchError> {
+    if res
+        .content_length()
+        .is_some_and(|len| len > max_bytes as u64)
+    {
+        return Err(FetchError::TooLarge(max_bytes));
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = res.chunk().await? {
+        if body.len() + chunk.len() > max_bytes {
+            return Err(FetchError::TooLarge(max_bytes));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+/// A client builder with the configured user agent, timeouts, redirect limit and proxy
+fn client_builder(config: &Config) -> reqwest::Result<reqwest::ClientBuilder> {
+    let mut builder = reqwest::Client::builder()
+        .user_agent(&*config.user_agent)
+        .connect_timeout(Duration::from_secs(5))
+        .timeout(config.request_timeout)
+        .redirect(redirect::Policy::limited(config.max_redirects));
+    if let Some(proxy) = &config.proxy {
+        builder = builder.proxy(reqwest::Proxy::all(&**proxy)?);
+    }
+    Ok(builder)
+}
+
+/// Client for urls that came from feeds
+#[derive(Debug, Clone)]
+pub struct Fetcher {
+    client: reqwest::Client,
+    allow_private_addresses: bool,
+}
+
+impl Fetcher {
+    pub fn new(config: &Config) -> reqwest::Result<Self> {
+        let allow_private_addresses = config.allow_private_addresses;
+        let max_redirects = config.max_redirects;
+        let mut builder =
+            client_builder(config)?.redirect(redirect::Policy::custom(move |attempt| {
+                if attempt.previous().len() >= max_redirects {
+                    attempt.error("too many redirects")
+                } else if !allow_private_addresses && is_private_literal(attempt.url()) {
+                    let host = attempt.url().host_str().unwrap_or_default().to_string();
+                    attempt.error(PrivateAddress(host))
+                } else {
+                    attempt.follow()
+                }
+            }));
+        if !allow_private_addresses {
+            builder = builder.dns_resolver(Arc::new(PublicResolver));
+            if config.proxy.is_some() {
+                tracing::warn!(
+                    "The proxy resolves hostnames itself, so feeds can still reach private \
+                    addresses by name through it. Have the proxy refuse them."
+                );
+            }
+        }
+
+        Ok(Self {
+            client: builder.build()?,
+            allow_private_addresses,
+        })
+    }
+
+    /// Errors if `url` names a private address and those aren't allowed
+    pub fn check_url(&self, url: &str) -> Result<(), FetchError> {
+        if !self.allow_private_addresses
+            && Url::parse(url).is_ok_and(|url| is_private_literal(&url))
+        {
+            return Err(FetchError::PrivateAddress(url.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Starts a request to `url` for callers that add their own headers or body, like feeds with
+    /// credentials, users' webhooks and hubs that feeds name
+    pub fn request(
+        &self,
+        method: reqwest::Method,
+        url: &str,
+    ) -> Result<reqwest::RequestBuilder, FetchError> {
+        self.check_url(url)?;
+        Ok(self.client.request(method, url))
+    }
+
+    pub fn post(&self, url: &str) -> Result<reqwest::RequestBuilder, FetchError> {
+        self.request(reqwest::Method::POST, url)
+    }
+
+    /// Fetches `url` if the response's type passes `accept`, returning `None` for other types
+    /// without reading the body
+    pub async fn get(
+        &self,
+        url: &str,
+        max_bytes: usize,
+        accept: impl FnOnce(&Mime) -> bool,
+    ) -> Result<Option<Fetched>, FetchError> {
+        self.check_url(url)?;
+        let res = self.client.get(url).send().await?.error_for_status()?;
+        // Servers that don't say what they sent get treated like browsers would
+        let mime = res
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .and_then(|content_type| content_type.parse::<Mime>().ok())
+            .unwrap_or(mime::APPLICATION_OCTET_STREAM);
+        if !accept(&mime) {
+            return Ok(None);
+        }
+
+        Ok(Some(Fetched {
+            mime,
+            body: read_capped(res, max_bytes).await?,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fetched(content_type: &str, body: &[u8]) -> Fetched {
+        Fetched {
+            mime: content_type.parse().unwrap(),
+            body: body.to_vec(),
+        }
+    }
+
+    #[test]
+    fn private_addresses_are_not_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "64:ff9b::7f00:1",
+            "64:ff9b::a9fe:a9fe",
+            "64:ff9b:1::1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{ip} is private");
+        }
+        for ip in [
+            "1.1.1.1",
+            "93.184.216.34",
+            "2606:4700:4700::1111",
+            "64:ff9b::101:101",
+        ] {
+            assert!(is_public(ip.parse().unwrap()), "{ip} is public");
+        }
+    }
+
+    #[test]
+    fn html_with_parameters_is_html() {
+        assert!(is_html(&"text/html; charset=utf-8".parse().unwrap()));
+        assert!(is_html(&"TEXT/HTML".parse().unwrap()));
+        assert!(!is_html(&"text/plain".parse().unwrap()));
+    }
+
+    #[test]
+    fn decodes_with_header_charset() {
+        let page = fetched("text/html; charset=ISO-8859-1", b"caf\xe9");
+        assert_eq!(page.text(), "café");
+    }
+
+    #[test]
+    fn decodes_with_meta_charset() {
+        let page = fetched(
+            "text/html",
+            b"<html><head><meta charset=\"windows-1252\"></head>caf\xe9",
+        );
+        assert!(page.text().ends_with("</head>café"));
+
+        let page = fetched(
+            "text/html",
+            b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset=iso-8859-1\">caf\xe9",
+        );
+        assert!(page.text().ends_with("\">café"));
+    }
+
+    #[test]
+    fn header_charset_wins_over_meta() {
+        let page = fetched(
+            "text/html; charset=utf-8",
+            "<meta charset=\"iso-8859-1\">café".as_bytes(),
+        );
+        assert_eq!(page.text(), "<meta charset=\"iso-8859-1\">café");
+    }
+
+    #[test]
+    fn defaults_to_utf8() {
+        assert_eq!(fetched("text/html", "café".as_bytes()).text(), "café");
+    }
+}
@@ -0,0 +1,192 @@
+//! Serves item images from our own server so readers don't hit publishers directly and images keep
+//! working after they disappear upstream. Each image is fetched once, shrunk to a thumbnail and
+//! stored under `Config::data_dir`, named after a hash of its url so an item that changes its
+//! image (or a new item reusing a deleted one's id) never gets the old one.
+
+use std::{collections::HashSet, io::Cursor, path::PathBuf};
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use axum::{
+    extract::{Path, Query, State},
+    response::{IntoResponse, Response},
+};
+use hmac::{Hmac, Mac};
+use http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use image::{codecs::jpeg::JpegEncoder, ImageReader, Limits};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Sqlite};
+
+use crate::{config::Config, db::Item, ApiError};
+
+use super::fetch::{FetchError, Fetcher};
+
+/// Images bigger than this aren't worth making thumbnails from
+const MAX_IMAGE_BYTES: usize = 8 * 1024 * 1024;
+/// Guards against images that are small files but decompress to huge bitmaps
+const MAX_IMAGE_DIMENSION: u32 = 10_000;
+const MAX_DECODE_ALLOC: u64 = 256 * 1024 * 1024;
+const THUMBNAIL_SIZE: u32 = 480;
+const THUMBNAIL_QUALITY: u8 = 80;
+/// Hex characters of the url signature
+const SIGNATURE_LEN: usize = 32;
+
+/// Where item images are served from. Images are fetched with `<img>` tags that can't send our
+/// auth header, so the url carries a signature instead. It covers the image url too, so the
+/// proxied url changes whenever the image does and browsers can cache it forever.
+pub fn proxied_url(config: &Config, item_id: i64, image: &str) -> String {
+    format!(
+        "{}/api/images/{item_id}?sig={}",
+        config.domain.to_string().trim_end_matches('/'),
+        signature(config, item_id, image)
+    )
+}
+
+fn mac(config: &Config, item_id: i64, image: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(config.password.as_bytes())
+        .expect("hmac accepts keys of any length");
+    mac.update(b"images/");
+    mac.update(&item_id.to_le_bytes());
+    mac.update(image.as_bytes());
+    mac
+}
+
+fn signature(config: &Config, item_id: i64, image: &str) -> String {
+    let mut signature = hex::encode(mac(config, item_id, image).finalize().into_bytes());
+    signature.truncate(SIGNATURE_LEN);
+    signature
+}
+
+fn verify_signature(config: &Config, item_id: i64, image: &str, signature: &str) -> bool {
+    match hex::decode(signature) {
+        Ok(signature) if signature.len() * 2 == SIGNATURE_LEN => mac(config, item_id, image)
+            .verify_truncated_left(&signature)
+            .is_ok(),
+        _ => false,
+    }
+}
+
+fn images_dir(config: &Config) -> PathBuf {
+    config.data_dir.join("images")
+}
+
+/// Cached thumbnails are shared by every item with the same image
+fn thumbnail_name(image: &str) -> String {
+    format!("{}.jpg", hex::encode(Sha256::digest(image.as_bytes())))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetImageQuery {
+    sig: String,
+}
+
+pub async fn get_image(
+    State(state): State<super::State>,
+    Path(item_id): Path<i64>,
+    Query(query): Query<GetImageQuery>,
+) -> Result<Response, ApiError> {
+    let url = Item::get_by_id(item_id, &state.sqlite)
+        .await?
+        .and_then(|item| item.image)
+        .ok_or(ApiError::NotFound)?;
+    if !verify_signature(&state.config, item_id, &url, &query.sig) {
+        return Err(ApiError::NotFound);
+    }
+
+    let path = images_dir(&state.config).join(thumbnail_name(&url));
+    let thumbnail = match tokio::fs::read(&path).await {
+        Ok(thumbnail) => thumbnail,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            let thumbnail = fetch_thumbnail(&state.fetcher, &url).await?;
+
+            // Write somewhere else first so a concurrent request never reads half a file
+            tokio::fs::create_dir_all(path.parent().unwrap()).await?;
+            let tmp_path = path.with_extension(format!("{}.tmp", OsRng.next_u64()));
+            tokio::fs::write(&tmp_path, &thumbnail).await?;
+            tokio::fs::rename(&tmp_path, &path).await?;
+            thumbnail
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    Ok((
+        [
+            (CONTENT_TYPE, "image/jpeg"),
+            // The signature changes with the image, so this url always serves the same one
+            (CACHE_CONTROL, "public, max-age=31536000, immutable"),
+        ],
+        thumbnail,
+    )
+        .into_response())
+}
+
+/// Deletes cached thumbnails no item has the image of anymore, returns how many
+pub async fn remove_unused(config: &Config, sqlite: &Pool<Sqlite>) -> Result<usize, ApiError> {
+    let mut entries = match tokio::fs::read_dir(images_dir(config)).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err.into()),
+    };
+    let used = Item::images(sqlite)
+        .await?
+        .iter()
+        .map(|image| thumbnail_name(image))
+        .collect::<HashSet<_>>();
+
+    let mut removed = 0;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name();
+        // Leaves alone half written thumbnails, they're renamed once done
+        let Some(name) = name.to_str().filter(|name| name.ends_with(".jpg")) else {
+            continue;
+        };
+        if !used.contains(name) {
+            tokio::fs::remove_file(entry.path()).await?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// Downloads an image and shrinks it to a JPEG thumbnail, rejecting anything that isn't an image
+/// or is too big
+async fn fetch_thumbnail(fetcher: &Fetcher, url: &str) -> Result<Vec<u8>, ApiError> {
+    let bytes = match fetcher
+        .get(url, MAX_IMAGE_BYTES, |mime| mime.type_() == mime::IMAGE)
+        .await
+    {
+        Ok(Some(image)) => image.body,
+        Ok(None) => {
+            tracing::debug!("{url} is not an image");
+            return Err(ApiError::NotFound);
+        }
+        Err(err @ (FetchError::TooLarge(_) | FetchError::PrivateAddress(_))) => {
+            tracing::debug!("Not making a thumbnail of {url}: {err}");
+            return Err(ApiError::NotFound);
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    // Decoding and resizing is CPU heavy, keep it off the async workers
+    tokio::task::spawn_blocking(move || {
+        let mut reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format()?;
+        let mut limits = Limits::default();
+        limits.max_image_width = Some(MAX_IMAGE_DIMENSION);
+        limits.max_image_height = Some(MAX_IMAGE_DIMENSION);
+        limits.max_alloc = Some(MAX_DECODE_ALLOC);
+        reader.limits(limits);
+
+        let thumbnail = reader
+            .decode()
+            .map_err(|_| ApiError::NotFound)?
+            .thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+            .into_rgb8();
+        let mut jpeg = Vec::new();
+        JpegEncoder::new_with_quality(&mut jpeg, THUMBNAIL_QUALITY)
+            .encode_image(&thumbnail)
+            .map_err(|err| std::io::Error::other(err.to_string()))?;
+        Ok(jpeg)
+    })
+    .await
+    .map_err(std::io::Error::other)?
+}
@@ -0,0 +1,473 @@
+//! Turning feed items into our `Item`s. Every way a feed's content reaches us (polling, WebSub
+//! pushes, previews) goes through here so they can't drift apart.
+
+use std::{error::Error, ops::Deref, sync::Arc};
+
+use futures::{stream::FuturesUnordered, StreamExt};
+use rustc_hash::{FxHashMap, FxHashSet};
+use sqlx::{Pool, Sqlite};
+use tokio::sync::mpsc;
+
+use crate::{
+    db::{CategoryTags, Item, Source, Tag, TagAlias, Tombstone},
+    ApiError,
+};
+
+use super::{dates, fetch::Fetcher, rss::get_image_from_link, webhooks};
+
+/// Whether ingesting only builds the items or also stores them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Build the items without storing anything, for previews
+    DryRun,
+    /// Store new items, their tags and queue webhooks for them
+    Commit,
+}
+
+#[derive(Debug)]
+pub struct Ingested {
+    pub item: Item,
+    pub tags: FxHashSet<Arc<str>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ingest<'a> {
+    pub fetcher: &'a Fetcher,
+    pub sqlite: &'a Pool<Sqlite>,
+    pub deliver_send: &'a mpsc::Sender<()>,
+    /// Used for sources that don't override it
+    pub category_tags: CategoryTags,
+}
+
+impl Ingest<'_> {
+    /// Builds, fetches images for and (depending on `mode`) commits a channel's items. Returns the
+    /// items that were built, or only the ones that were new when committing.
+    pub async fn run(
+        &self,
+        source: &Source,
+        channel: rss::Channel,
+        mode: Mode,
+    ) -> Result<Vec<Ingested>, ApiError> {
+        let built = self
+            .skip_buried(source, build_items(source, channel))
+            .await?;
+        let category_tags = source.category_tags_or(self.category_tags);
+        let built = self
+            .filter_categories(category_tags, self.apply_aliases(built).await?)
+            .await?;
+        let items = fetch_images(self.fetcher, built).await;
+
+        match mode {
+            Mode::DryRun => Ok(items),
+            Mode::Commit => self.commit(source, items, category_tags).await,
+        }
+    }
+
+    /// Drops the categories `category_tags` says shouldn't become tags
+    async fn filter_categories(
+        &self,
+        category_tags: CategoryTags,
+        mut items: Vec<Ingested>,
+    ) -> Result<Vec<Ingested>, ApiError> {
+        match category_tags {
+            CategoryTags::All => {}
+            CategoryTags::Existing => {
+                let existing = Tag::get_all(self.sqlite)
+                    .await?
+                    .into_iter()
+                    .map(|tag| tag.name)
+                    .collect::<FxHashSet<_>>();
+                for ingested in &mut items {
+                    ingested.tags.retain(|tag| existing.contains(&**tag));
+                }
+            }
+            CategoryTags::Ignore => {
+                for ingested in &mut items {
+                    ingested.tags.clear();
+                }
+            }
+        }
+        Ok(items)
+    }
+
+    /// Swaps categories that are aliases for the tag they stand for
+    async fn apply_aliases(&self, mut items: Vec<Ingested>) -> Result<Vec<Ingested>, ApiError> {
+        let aliases = TagAlias::get_all(self.sqlite).await?;
+        if aliases.is_empty() {
+            return Ok(items);
+        }
+
+        let aliases = aliases
+            .into_iter()
+            .map(|alias| (alias.alias, Arc::<str>::from(alias.tag_id)))
+            .collect::<FxHashMap<_, _>>();
+        for ingested in &mut items {
+            ingested.tags = ingested
+                .tags
+                .drain()
+                .map(|tag| aliases.get(&*tag).cloned().unwrap_or(tag))
+                .collect();
+        }
+        Ok(items)
+    }
+
+    /// Leaves out items that were deleted, so they don't come back while the feed still has them
+    async fn skip_buried(
+        &self,
+        source: &Source,
+        mut items: Vec<Ingested>,
+    ) -> Result<Vec<Ingested>, ApiError> {
+        let links = items
+            .iter()
+            .map(|ingested| ingested.item.link.as_str())
+            .collect::<Vec<_>>();
+        let guids = items
+            .iter()
+            .filter_map(|ingested| ingested.item.guid.as_deref())
+            .collect::<Vec<_>>();
+        let buried = Tombstone::buried(source.id, &links, &guids, self.sqlite).await?;
+        // Like items, tombstones with a guid are only matched by it
+        let buried_links = buried
+            .iter()
+            .filter(|tombstone| tombstone.guid.is_none())
+            .map(|tombstone| tombstone.link.as_str())
+            .collect::<FxHashSet<_>>();
+        let buried_guids = buried
+            .iter()
+            .filter_map(|tombstone| tombstone.guid.as_deref())
+            .collect::<FxHashSet<_>>();
+
+        if !buried.is_empty() {
+            tracing::debug!(
+                "Skipping {} deleted items from {}",
+                buried.len(),
+                source.name
+            );
+            items.retain(|ingested| match ingested.item.guid.as_deref() {
+                Some(guid) => !buried_guids.contains(guid),
+                None => !buried_links.contains(ingested.item.link.as_str()),
+            });
+        }
+        Ok(items)
+    }
+
+    /// Inserts the items we don't have yet and tags them with their categories and the source's tags
+    async fn commit(
+        &self,
+        source: &Source,
+        items: Vec<Ingested>,
+        category_tags: CategoryTags,
+    ) -> Result<Vec<Ingested>, ApiError> {
+        let now = chrono::Utc::now().naive_utc();
+        let source_tags = Source::tags(source.id, self.sqlite).await?;
+
+        // Try to create tags for each category we found in the items, the other policies only
+        // leave categories that are tags already
+        if category_tags == CategoryTags::All {
+            let new_tags = items
+                .iter()
+                .flat_map(|ingested| ingested.tags.iter())
+                .collect::<FxHashSet<_>>()
+                .into_iter()
+                .map(|category| Tag {
+                    created_at: now,
+                    updated_at: now,
+                    text_color: None,
+                    background_color: None,
+                    border_color: None,
+                    parent: None,
+
+                    name: category.to_string(),
+                })
+                .collect::<Vec<_>>();
+            if let Err(err) = Tag::insert_many(&new_tags, self.sqlite).await {
+                tracing::error!("Failed to create tags from categories: {err:?}");
+            };
+        }
+
+        let mut inserted = Vec::new();
+        for Ingested { mut item, mut tags } in items {
+            match item.insert(self.sqlite).await {
+                Ok(_) => {
+                    tracing::info!("Inserted new item for {}", item.link);
+                    // Add tags from the source
+                    for source_tag in &source_tags {
+                        tags.insert(Arc::from(source_tag.name.as_str()));
+                    }
+
+                    // Now add the tags to the item
+                    let item_tags = tags.iter().map(Deref::deref).collect::<Vec<_>>();
+                    if let Err(err) = Item::add_tags(item.id, &item_tags, self.sqlite).await {
+                        tracing::error!("Failed to add tags to {}: {err:?}", item.link);
+                    };
+
+                    match webhooks::enqueue(&item, &item_tags, self.sqlite).await {
+                        // Wake up the worker, if it's already awake this is a no-op
+                        Ok(queued) if queued > 0 => drop(self.deliver_send.try_send(())),
+                        Ok(_) => {}
+                        Err(err) => {
+                            tracing::error!("Failed to queue webhooks for {}: {err:?}", item.link)
+                        }
+                    }
+
+                    inserted.push(Ingested { item, tags });
+                }
+                Err(err) => match err.into_sqlx_error() {
+                    sqlx::Error::Database(db_err)
+                        if db_err.kind() == sqlx::error::ErrorKind::UniqueViolation =>
+                    {
+                        if let Err(err) = self.apply_update(&item).await {
+                            tracing::error!("Error updating {}: {err:?}", item.link);
+                        }
+                    }
+                    err => {
+                        tracing::error!("Error while adding item: {err:?}");
+                    }
+                },
+            }
+        }
+
+        Ok(inserted)
+    }
+
+    /// Updates the stored version of an item if the feed changed it since we ingested it
+    async fn apply_update(&self, item: &Item) -> Result<(), ApiError> {
+        let Some(existing) = item.get_existing(self.sqlite).await? else {
+            return Ok(());
+        };
+        let stored_hash = existing
+            .content_hash
+            .clone()
+            .unwrap_or_else(|| existing.compute_content_hash());
+        if stored_hash == item.compute_content_hash() {
+            tracing::debug!("Tried to insert link {} that already exists", item.link);
+            return Ok(());
+        }
+
+        tracing::info!("Updating changed item {}", item.link);
+        item.update_content(existing.id, self.sqlite).await?;
+        Ok(())
+    }
+}
+
+/// Converts a channel's items to `Item`s without touching the network or db. Items without a link
+/// (or permalink guid) or published before the source's `min_date` are left out.
+pub fn build_items(source: &Source, channel: rss::Channel) -> Vec<Ingested> {
+    let now = chrono::Utc::now().naive_utc();
+
+    channel
+        .items
+        .into_iter()
+        .filter_map(|channel_item| {
+            let guid = channel_item.guid.filter(|guid| !guid.value.is_empty());
+            // A permalink guid is as good as a link
+            let permalink = guid
+                .as_ref()
+                .filter(|guid| guid.is_permalink())
+                .map(|guid| guid.value.clone());
+            let Some(link) = channel_item.link.or(permalink) else {
+                tracing::error!(
+                    "Error while creating item from {}: item has no link",
+                    source.name
+                );
+                return None;
+            };
+            let published = channel_item
+                .pub_date
+                .as_deref()
+                .and_then(dates::parse)
+                .map(|pub_date| pub_date.naive_utc());
+            if let Some(min_date) = source.min_date {
+                if let Some(published) = published {
+                    if published < min_date {
+                        // If item is older than the min_date for this source, ignore it
+                        tracing::debug!("Ignoring {link} because its too old.");
+                        return None;
+                    }
+                }
+            }
+
+            let item = Item {
+                // Filled in by db
+                id: 0,
+                created_at: now,
+                updated_at: now,
+
+                title: channel_item.title,
+                link,
+                author: channel_item.author,
+                description: channel_item.description,
+                published,
+                // Filled in by fetch_images
+                image: None,
+                source_id: Some(source.id),
+                source_link: Some(source.url.clone()),
+                archived_at: None,
+                guid_is_permalink: guid.as_ref().is_some_and(|guid| guid.is_permalink()),
+                guid: guid.map(|guid| guid.value),
+                content_hash: None,
+                content_updated_at: None,
+            };
+            let tags = categories_to_tags(channel_item.categories);
+            Some(Ingested { item, tags })
+        })
+        .collect()
+}
+
+pub fn categories_to_tags(categories: Vec<rss::Category>) -> FxHashSet<Arc<str>> {
+    categories
+        .into_iter()
+        .filter(|c| !c.name.is_empty())
+        .map(|c| Arc::<str>::from(c.name.to_ascii_lowercase().as_str()))
+        .collect()
+}
+
+/// Finds a thumbnail for every item from its page. Items whose page can't be fetched are dropped so
+/// the next poll tries them again.
+async fn fetch_images(fetcher: &Fetcher, items: Vec<Ingested>) -> Vec<Ingested> {
+    let mut futures = items
+        .into_iter()
+        .map(|mut ingested| async move {
+            ingested.item.image = get_image_from_link(fetcher, &ingested.item.link)
+                .await
+                .map_err(|err| (ingested.item.link.clone(), err))?;
+            Ok::<_, (String, Box<dyn Error + 'static>)>(ingested)
+        })
+        .collect::<FuturesUnordered<_>>();
+
+    let mut items = Vec::with_capacity(futures.len());
+    while let Some(result) = futures.next().await {
+        match result {
+            Ok(ingested) => items.push(ingested),
+            Err((link, err)) => tracing::error!("Error getting image for {link}: {err:?}"),
+        }
+    }
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, NaiveDate};
+
+    use super::*;
+
+    fn source() -> Source {
+        let epoch = DateTime::UNIX_EPOCH.naive_utc();
+        Source {
+            id: 1,
+            name: "fixture".into(),
+            url: "http://localhost/feed.xml".into(),
+            last_pub: epoch,
+            last_poll: None,
+            ttl: None,
+            favorite: false,
+            min_date: None,
+            created_at: epoch,
+            updated_at: epoch,
+            websub_hub: None,
+            websub_topic: None,
+            websub_secret: None,
+            websub_lease_expires_at: None,
+            websub_requested_at: None,
+            websub_attempts: 0,
+            retain_done_for: None,
+            retain_unread_for: None,
+            headers: None,
+            auth_username: None,
+            auth_password: None,
+            category_tags: None,
+        }
+    }
+
+    fn fixture(name: &str) -> rss::Channel {
+        let path = format!("{}/tests/fixtures/{name}", env!("CARGO_MANIFEST_DIR"));
+        rss::Channel::read_from(&*std::fs::read(path).unwrap()).unwrap()
+    }
+
+    fn tags(ingested: &Ingested) -> Vec<&str> {
+        let mut tags = ingested.tags.iter().map(Deref::deref).collect::<Vec<_>>();
+        tags.sort();
+        tags
+    }
+
+    #[test]
+    fn builds_items_with_lowercased_tags() {
+        let items = build_items(&source(), fixture("basic.rss"));
+
+        assert_eq!(items.len(), 3);
+        let first = items
+            .iter()
+            .find(|i| i.item.title.as_deref() == Some("First"))
+            .unwrap();
+        assert_eq!(first.item.link, "http://localhost/articles/first.html");
+        assert_eq!(first.item.source_id, Some(1));
+        assert_eq!(
+            first.item.source_link.as_deref(),
+            Some("http://localhost/feed.xml")
+        );
+        // Empty categories are dropped, the channel's are only shown in previews
+        assert_eq!(tags(first), ["rust", "web assembly"]);
+    }
+
+    #[test]
+    fn skips_items_without_links() {
+        let items = build_items(&source(), fixture("basic.rss"));
+        assert!(items
+            .iter()
+            .all(|i| i.item.title.as_deref() != Some("No link")));
+    }
+
+    #[test]
+    fn uses_permalink_guids_as_links() {
+        let items = build_items(&source(), fixture("basic.rss"));
+
+        let permalink = items
+            .iter()
+            .find(|i| i.item.title.as_deref() == Some("Permalink guid"))
+            .unwrap();
+        assert_eq!(
+            permalink.item.link,
+            "http://localhost/articles/permalink.html"
+        );
+        assert!(permalink.item.guid_is_permalink);
+
+        let second = items
+            .iter()
+            .find(|i| i.item.title.as_deref() == Some("Second"))
+            .unwrap();
+        assert_eq!(second.item.link, "http://localhost/articles/second.html");
+        assert_eq!(second.item.guid.as_deref(), Some("second-article"));
+        assert!(!second.item.guid_is_permalink);
+    }
+
+    #[test]
+    fn stores_published_in_utc() {
+        let items = build_items(&source(), fixture("basic.rss"));
+        let second = items
+            .iter()
+            .find(|i| i.item.title.as_deref() == Some("Second"))
+            .unwrap();
+        // Published at 08:30 -0400
+        assert_eq!(
+            second.item.published,
+            NaiveDate::from_ymd_opt(2024, 6, 1)
+                .unwrap()
+                .and_hms_opt(12, 30, 0)
+        );
+    }
+
+    #[test]
+    fn filters_items_before_min_date() {
+        let mut source = source();
+        source.min_date = NaiveDate::from_ymd_opt(2025, 1, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0);
+
+        let items = build_items(&source, fixture("basic.rss"));
+        let titles = items
+            .iter()
+            .filter_map(|i| i.item.title.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(titles, ["First", "Permalink guid"]);
+    }
+}
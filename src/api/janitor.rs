@@ -0,0 +1,138 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use chrono::Utc;
+use http::HeaderMap;
+use serde::Deserialize;
+use sqlx::{Pool, Sqlite};
+
+use crate::{
+    config::Config,
+    db::{
+        self,
+        item::{ExpiredCounts, Retention},
+        Item, Source, Tombstone,
+    },
+    ApiError,
+};
+
+use super::{auth::authorize_admin, images};
+
+/// Starts the task that removes expired items every `janitor_interval` and keeps the db tidy
+pub fn start_janitor(config: Arc<Config>, sqlite: Pool<Sqlite>) {
+    tokio::spawn(async move {
+        let mut last_vacuum = tokio::time::Instant::now();
+        let mut removed_since_vacuum = 0;
+
+        loop {
+            tokio::time::sleep(config.janitor_interval).await;
+
+            let retention = retention(&config, None, None);
+            match Item::expire(retention, config.archive_expired, &sqlite).await {
+                Ok(0) => {}
+                Ok(removed) => {
+                    tracing::info!("Expired {removed} items");
+                    if !config.archive_expired {
+                        removed_since_vacuum += removed;
+                    }
+                }
+                Err(err) => tracing::error!("Error expiring items: {err:?}"),
+            }
+
+            match images::remove_unused(&config, &sqlite).await {
+                Ok(0) => {}
+                Ok(removed) => tracing::debug!("Removed {removed} unused thumbnails"),
+                Err(err) => tracing::error!("Error removing unused thumbnails: {err:?}"),
+            }
+
+            let tombstone_cutoff = Utc::now().naive_utc() - config.tombstone_ttl;
+            match Tombstone::delete_before(tombstone_cutoff, &sqlite).await {
+                Ok(0) => {}
+                Ok(forgotten) => tracing::debug!("Forgot {forgotten} deleted items"),
+                Err(err) => tracing::error!("Error expiring tombstones: {err:?}"),
+            }
+
+            if let Err(err) = db::optimize(&sqlite).await {
+                tracing::error!("Error optimizing db: {err:?}");
+            }
+            // Vacuuming rewrites the whole file, only bother when deletes freed something
+            if removed_since_vacuum > 0 && last_vacuum.elapsed() >= config.vacuum_interval {
+                tracing::info!("Vacuuming db");
+                match db::vacuum(&sqlite).await {
+                    Ok(()) => {
+                        last_vacuum = tokio::time::Instant::now();
+                        removed_since_vacuum = 0;
+                    }
+                    Err(err) => tracing::error!("Error vacuuming db: {err:?}"),
+                }
+            }
+        }
+    });
+}
+
+/// The configured policy, with `done_for` and `unread_for` replacing the global ages if set
+fn retention(
+    config: &Config,
+    done_for: Option<Duration>,
+    unread_for: Option<Duration>,
+) -> Retention {
+    let seconds = |duration: Option<Duration>| duration.map(|d| d.as_secs() as i64);
+    Retention {
+        now: Utc::now().naive_utc(),
+        done_for: seconds(done_for.or(config.retain_done_for)),
+        unread_for: seconds(unread_for.or(config.retain_unread_for)),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PreviewRetentionQuery {
+    #[serde(default, with = "humantime_serde")]
+    done_for: Option<Duration>,
+    #[serde(default, with = "humantime_serde")]
+    unread_for: Option<Duration>,
+}
+
+/// How many items the configured policy (or the one in the query) would remove right now.
+/// Per source overrides still apply.
+pub async fn preview_retention(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+    Query(query): Query<PreviewRetentionQuery>,
+) -> Result<Json<ExpiredCounts>, ApiError> {
+    authorize_admin(&state, &headers).await?;
+    let retention = retention(&state.config, query.done_for, query.unread_for);
+    Ok(Json(Item::count_expired(retention, &state.sqlite).await?))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceRetention {
+    #[serde(default)]
+    retain_done_for: Option<i64>,
+    #[serde(default)]
+    retain_unread_for: Option<i64>,
+}
+
+/// Sources are shared, so only admins can change how long their items are kept
+pub async fn set_source_retention(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+    Json(body): Json<SourceRetention>,
+) -> Result<(), ApiError> {
+    authorize_admin(&state, &headers).await?;
+    Source::get_by_id(id, &state.sqlite)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    Source::set_retention(
+        id,
+        body.retain_done_for,
+        body.retain_unread_for,
+        &state.sqlite,
+    )
+    .await?;
+    Ok(())
+}
@@ -1,22 +1,51 @@
 mod auth;
+mod backup;
 mod crud;
-mod rss;
+mod dates;
+mod fetch;
+mod images;
+mod ingest;
+mod janitor;
 mod preview;
+mod rate_limit;
+mod read_later;
+mod rss;
+mod smart_feeds;
+mod stats;
+mod webhooks;
+mod websub;
+#[cfg(test)]
+mod tests;
 
-use std::{sync::Arc, time::Duration};
+use std::sync::Arc;
 
-use auth::login;
+use auth::{create_token, delete_token, get_tokens, login, require_auth};
 use axum::{
-    routing::{get, post},
+    extract::DefaultBodyLimit,
+    middleware,
+    routing::{delete, get, post, put},
     Router,
 };
 use crud::{
-    create_item, create_source, create_tag, delete_item, delete_source, delete_tag, done, get_item,
-    get_items, get_source, get_sources, get_tag, get_tags, update_tag,
+    add_item_tags, create_item, create_source, create_tag, create_tag_alias, create_user,
+    delete_item, delete_source, delete_tag, delete_tag_alias, delete_user, done, favorite, get_item,
+    get_item_revisions, get_items, get_me, get_source, get_sources, get_tag, get_tag_aliases,
+    get_tag_usage, get_tags, get_users, merge_tags, prune_tags, remove_item_tag, rename_tag,
+    set_source_category_tags, set_source_credentials, unfavorite, update_tag,
+};
+use rate_limit::{limit_logins, LoginLimiter};
+use read_later::{
+    add_to_read_later, archive_read_later, get_read_later, remove_from_read_later,
+    reorder_read_later, set_read_later_progress,
 };
 use rss::{CloneReceiver, PollMessage};
+use smart_feeds::{
+    create_smart_feed, delete_smart_feed, get_smart_feed, get_smart_feed_items, get_smart_feeds,
+    update_smart_feed,
+};
 use sqlx::{Pool, Sqlite};
 use tokio::sync::mpsc;
+use webhooks::{create_webhook, delete_webhook, get_deliveries, get_webhooks};
 
 use crate::config::Config;
 
@@ -26,43 +55,103 @@ struct State {
     sqlite: Pool<Sqlite>,
     poll_recv: CloneReceiver<PollMessage>,
     poll_send: mpsc::Sender<()>,
-    client: reqwest::Client,
+    deliver_send: mpsc::Sender<()>,
+    /// For feeds and urls that came from them
+    fetcher: fetch::Fetcher,
+    login_limiter: Arc<LoginLimiter>,
 }
 
 pub fn api_router(config: Arc<Config>, sqlite: Pool<Sqlite>) -> color_eyre::Result<Router> {
-    let client = reqwest::Client::builder()
-        .connect_timeout(Duration::from_secs(5))
-        .build()?;
-    let (poll_recv, poll_send) = rss::start_poller(config.clone(), client.clone(), sqlite.clone());
+    let fetcher = fetch::Fetcher::new(&config)?;
+    let deliver_send =
+        webhooks::start_delivery_worker(config.clone(), fetcher.clone(), sqlite.clone());
+    let (poll_recv, poll_send) = rss::start_poller(
+        config.clone(),
+        fetcher.clone(),
+        sqlite.clone(),
+        deliver_send.clone(),
+    );
+    janitor::start_janitor(config.clone(), sqlite.clone());
+    backup::start_backups(config.clone(), sqlite.clone());
     let state = State {
         config,
         sqlite,
         poll_recv,
         poll_send,
-        client,
+        deliver_send,
+        fetcher,
+        login_limiter: Arc::default(),
     };
-    let router = Router::new()
+    let mut router = Router::new()
+        .route("/tags", get(get_tags).post(create_tag).put(update_tag))
+        .route("/tags/{name}", get(get_tag).delete(delete_tag))
+        .route("/tags/{name}/rename", post(rename_tag))
+        .route("/tags/merge", post(merge_tags))
+        .route("/tags/usage", get(get_tag_usage))
+        .route("/tags/prune", post(prune_tags))
+        .route("/tags/aliases", get(get_tag_aliases).post(create_tag_alias))
+        .route("/tags/aliases/{alias}", delete(delete_tag_alias))
+        .route("/items", get(get_items).post(create_item))
+        .route("/items/{id}", get(get_item).delete(delete_item))
+        .route("/items/{id}/revisions", get(get_item_revisions))
+        .route("/items/{id}/done", post(done))
+        .route("/items/{id}/favorite", post(favorite).delete(unfavorite))
+        .route("/items/{id}/tags", post(add_item_tags))
+        .route("/items/{id}/tags/{name}", delete(remove_item_tag))
+        .route("/sources", get(get_sources).post(create_source))
+        .route("/sources/{id}", get(get_source).delete(delete_source))
+        .route("/sources/preview", post(preview::preview_source))
+        .route("/sources/{id}/retention", put(janitor::set_source_retention))
+        .route("/sources/{id}/credentials", put(set_source_credentials))
+        .route("/sources/{id}/category-tags", put(set_source_category_tags))
+        .route("/retention/preview", get(janitor::preview_retention))
+        .route("/backup", get(backup::download_backup))
+        .route("/export", get(backup::export))
         .route(
-            "/tags",
-            get(get_tags)
-                .post(create_tag)
-                .delete(delete_tag)
-                .put(update_tag),
+            "/import",
+            post(backup::import).layer(DefaultBodyLimit::max(backup::MAX_IMPORT_BYTES)),
         )
-        .route("/tags/{name}", get(get_tag))
+        .route("/users", get(get_users).post(create_user))
+        .route("/users/me", get(get_me))
+        .route("/users/{id}", delete(delete_user))
+        .route("/tokens", get(get_tokens).post(create_token))
+        .route("/tokens/{id}", delete(delete_token))
+        .route("/webhooks", get(get_webhooks).post(create_webhook))
+        .route("/webhooks/{id}", delete(delete_webhook))
+        .route("/webhooks/deliveries", get(get_deliveries))
+        .route("/smart-feeds", get(get_smart_feeds).post(create_smart_feed))
         .route(
-            "/items",
-            get(get_items).post(create_item).delete(delete_item),
+            "/smart-feeds/{id}",
+            get(get_smart_feed)
+                .put(update_smart_feed)
+                .delete(delete_smart_feed),
         )
-        .route("/items/{id}", get(get_item))
-        .route("/items/{id}/done", post(done))
+        .route("/smart-feeds/{id}/items", get(get_smart_feed_items))
+        .route("/stats", get(stats::get_stats))
+        .route("/read-later", get(get_read_later).post(add_to_read_later))
+        .route("/read-later/order", put(reorder_read_later))
         .route(
-            "/sources",
-            get(get_sources).post(create_source).delete(delete_source),
+            "/read-later/{item_id}",
+            put(set_read_later_progress).delete(remove_from_read_later),
         )
-        .route("/sources/{id}", get(get_source))
-        .route("/sources/preview", post(preview::preview_source))
-        .route("/login", post(login))
+        .route("/read-later/{item_id}/archive", post(archive_read_later))
+        .route("/login", post(login));
+
+    if state.config.private {
+        router = router.route_layer(middleware::from_fn_with_state(state.clone(), require_auth));
+    }
+
+    // Hubs and image urls authenticate with signatures instead, so these are added after the auth
+    // layer
+    router = router
+        .route(
+            "/websub/{source_id}",
+            get(websub::verify_intent).post(websub::receive_content),
+        )
+        .route("/images/{item_id}", get(images::get_image));
+
+    let router = router
+        .layer(middleware::from_fn_with_state(state.clone(), limit_logins))
         .with_state(state);
 
     Ok(router)
@@ -0,0 +1,278 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{self, ConnectInfo},
+    middleware::Next,
+    response::Response,
+};
+use http::{HeaderMap, StatusCode};
+use rustc_hash::FxHashMap;
+
+use crate::{config::Config, ApiError};
+
+use super::State;
+
+const WINDOW: Duration = Duration::from_secs(60);
+/// Stale entries are only swept once the map gets this big
+const SWEEP_THRESHOLD: usize = 1024;
+
+#[derive(Debug, Default)]
+pub struct LoginLimiter {
+    clients: Mutex<FxHashMap<IpAddr, Client>>,
+}
+
+#[derive(Debug)]
+struct Client {
+    window_start: Instant,
+    last_seen: Instant,
+    attempts: u32,
+    failures: u32,
+    locked_until: Option<Instant>,
+}
+
+impl Client {
+    fn new(now: Instant) -> Self {
+        Self {
+            window_start: now,
+            last_seen: now,
+            attempts: 0,
+            failures: 0,
+            locked_until: None,
+        }
+    }
+
+    /// Clients are forgotten once they've been quiet for as long as a lockout would last, so
+    /// spraying a failure or two from many addresses can't grow the map forever
+    fn is_stale(&self, config: &Config, now: Instant) -> bool {
+        now - self.last_seen > WINDOW.max(config.login_lockout)
+            && self.locked_until.is_none_or(|until| until <= now)
+    }
+}
+
+impl LoginLimiter {
+    /// Errors if `ip` is locked out, or if this is a login attempt over the per minute limit
+    fn check(
+        &self,
+        config: &Config,
+        ip: IpAddr,
+        is_login: bool,
+        now: Instant,
+    ) -> Result<(), ApiError> {
+        let mut clients = self.clients.lock().unwrap();
+        if clients.len() > SWEEP_THRESHOLD {
+            clients.retain(|_, client| !client.is_stale(config, now));
+        }
+
+        let client = clients.entry(ip).or_insert_with(|| Client::new(now));
+        client.last_seen = now;
+        if let Some(locked_until) = client.locked_until {
+            if locked_until > now {
+                return Err(ApiError::RateLimited(locked_until - now));
+            }
+            client.locked_until = None;
+            client.failures = 0;
+        }
+
+        if is_login {
+            if now - client.window_start > WINDOW {
+                client.window_start = now;
+                client.attempts = 0;
+            }
+            client.attempts += 1;
+            if client.attempts > config.login_attempts_per_minute {
+                return Err(ApiError::RateLimited(WINDOW - (now - client.window_start)));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn record_failure(&self, config: &Config, ip: IpAddr, now: Instant) {
+        let mut clients = self.clients.lock().unwrap();
+        let client = clients.entry(ip).or_insert_with(|| Client::new(now));
+        client.last_seen = now;
+        client.failures += 1;
+        if client.failures >= config.login_max_failures {
+            tracing::warn!("Locking out {ip} after {} failed logins", client.failures);
+            client.locked_until = Some(now + config.login_lockout);
+        }
+    }
+
+    fn record_success(&self, ip: IpAddr) {
+        if let Some(client) = self.clients.lock().unwrap().get_mut(&ip) {
+            client.failures = 0;
+        }
+    }
+}
+
+/// Rate limits `/login` and locks out clients that keep failing to authenticate anywhere in the
+/// API, since every authenticated route can be used to guess passwords
+pub async fn limit_logins(
+    extract::State(state): extract::State<State>,
+    req: extract::Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+    let ip = client_ip(&state.config, peer, req.headers());
+    let has_credentials =
+        req.headers().contains_key("x-auth") || req.headers().contains_key("authorization");
+
+    state.login_limiter.check(
+        &state.config,
+        ip,
+        req.uri().path() == "/login",
+        Instant::now(),
+    )?;
+
+    let res = next.run(req).await;
+    if has_credentials {
+        if res.status() == StatusCode::UNAUTHORIZED {
+            state
+                .login_limiter
+                .record_failure(&state.config, ip, Instant::now());
+        } else {
+            state.login_limiter.record_success(ip);
+        }
+    }
+    Ok(res)
+}
+
+/// The address of the client, trusting `X-Forwarded-For` only when the request came through one of
+/// `Config::trusted_proxies`
+fn client_ip(config: &Config, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+    if !config.trusted_proxies.contains(&peer) {
+        return peer;
+    }
+
+    // Walk backwards through the proxies that we trust, the first one we don't is the client
+    headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(','))
+        .filter_map(|addr| addr.trim().parse::<IpAddr>().ok())
+        .rev()
+        .find(|addr| !config.trusted_proxies.contains(addr))
+        .unwrap_or(peer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(overrides: &str) -> Config {
+        Config::from_json(
+            format!(
+                r#"{{
+                    "domain": "http://localhost",
+                    "web_dir": "/tmp",
+                    "data_dir": "/tmp",
+                    "password": "password",
+                    "login_max_failures": 3,
+                    "login_lockout": "5m"
+                    {overrides}
+                }}"#
+            )
+            .as_bytes(),
+        )
+        .unwrap()
+    }
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn forwarded_for_is_only_trusted_from_proxies() {
+        let config = config(r#", "trusted_proxies": ["10.0.0.1", "10.0.0.2"]"#);
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "6.6.6.6, 1.2.3.4".parse().unwrap());
+        headers.append("x-forwarded-for", "10.0.0.2".parse().unwrap());
+
+        assert_eq!(client_ip(&config, ip("5.5.5.5"), &headers), ip("5.5.5.5"));
+        // A client can prepend anything, only the last untrusted hop counts
+        assert_eq!(client_ip(&config, ip("10.0.0.1"), &headers), ip("1.2.3.4"));
+        assert_eq!(
+            client_ip(&config, ip("10.0.0.1"), &HeaderMap::new()),
+            ip("10.0.0.1")
+        );
+        headers.insert("x-forwarded-for", "not an ip".parse().unwrap());
+        assert_eq!(client_ip(&config, ip("10.0.0.1"), &headers), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn failures_lock_clients_out() {
+        let config = config("");
+        let limiter = LoginLimiter::default();
+        let now = Instant::now();
+        let client = ip("1.2.3.4");
+
+        for _ in 0..3 {
+            limiter.check(&config, client, false, now).unwrap();
+            limiter.record_failure(&config, client, now);
+        }
+        assert!(matches!(
+            limiter.check(&config, client, false, now),
+            Err(ApiError::RateLimited(_))
+        ));
+        // Others aren't affected
+        limiter.check(&config, ip("1.2.3.5"), false, now).unwrap();
+
+        let later = now + config.login_lockout + Duration::from_secs(1);
+        limiter.check(&config, client, false, later).unwrap();
+        // The count starts over after a lockout
+        limiter.record_failure(&config, client, later);
+        limiter.check(&config, client, false, later).unwrap();
+    }
+
+    #[test]
+    fn logins_are_limited_per_minute() {
+        let config = config(r#", "login_attempts_per_minute": 2"#);
+        let limiter = LoginLimiter::default();
+        let now = Instant::now();
+        let client = ip("1.2.3.4");
+
+        limiter.check(&config, client, true, now).unwrap();
+        limiter.check(&config, client, true, now).unwrap();
+        assert!(limiter.check(&config, client, true, now).is_err());
+        // Only `/login` counts
+        limiter.check(&config, client, false, now).unwrap();
+        limiter
+            .check(&config, client, true, now + WINDOW + Duration::from_secs(1))
+            .unwrap();
+    }
+
+    #[test]
+    fn quiet_clients_are_swept_whatever_their_failures() {
+        let config = config("");
+        let limiter = LoginLimiter::default();
+        let now = Instant::now();
+        for i in 0..=SWEEP_THRESHOLD as u32 {
+            let client = IpAddr::V4(Ipv4Addr::from(i));
+            limiter.record_failure(&config, client, now);
+        }
+        let locked = ip("1.2.3.4");
+        for _ in 0..3 {
+            limiter.record_failure(&config, locked, now);
+        }
+
+        let later = now + config.login_lockout - Duration::from_secs(1);
+        limiter.check(&config, ip("1.2.3.5"), false, later).unwrap();
+        assert_eq!(limiter.clients.lock().unwrap().len(), SWEEP_THRESHOLD + 3);
+
+        let later = now + config.login_lockout + Duration::from_secs(1);
+        limiter.check(&config, ip("1.2.3.5"), false, later).unwrap();
+        assert_eq!(
+            limiter.clients.lock().unwrap().keys().collect::<Vec<_>>(),
+            [&ip("1.2.3.5")]
+        );
+    }
+}
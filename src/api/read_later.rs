@@ -0,0 +1,309 @@
+//! A queue of items to read later, separate from favorites. Items keep the order the user gives
+//! them, track how far in the user is and get archived once finished.
+
+use std::sync::LazyLock;
+
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use chrono::Utc;
+use http::HeaderMap;
+use rustc_hash::FxHashMap;
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    db::{Item, ReadLater},
+    ApiError,
+};
+
+use super::{
+    auth::{authorize, Scope},
+    crud::{add_item, GetItemsReturn},
+    fetch::{is_html, Fetcher},
+};
+
+/// Articles are bigger than the pages we only read the head of for images
+const MAX_ARTICLE_BYTES: usize = 2 * 1024 * 1024;
+
+/// Elements whose text isn't part of what's read
+const SKIPPED_ELEMENTS: &[&str] = &[
+    "script", "style", "noscript", "template", "svg", "nav", "header", "footer", "aside", "form",
+];
+
+/// Counts the words in the readable part of `html`: its first `<article>`, or `<main>`, or the
+/// whole body, without scripts, navigation and the like
+fn count_words(html: &str) -> usize {
+    static ROOTS: LazyLock<[Selector; 3]> = LazyLock::new(|| {
+        ["article", "main", "body"].map(|selector| Selector::parse(selector).unwrap())
+    });
+
+    let page = Html::parse_document(html);
+    let Some(root) = ROOTS
+        .iter()
+        .find_map(|selector| page.select(selector).next())
+    else {
+        return 0;
+    };
+    root.descendants()
+        .filter_map(|node| node.value().as_text().map(|text| (node, text)))
+        .filter(|(node, _)| {
+            !node.ancestors().any(|ancestor| {
+                ancestor
+                    .value()
+                    .as_element()
+                    .is_some_and(|element| SKIPPED_ELEMENTS.contains(&element.name()))
+            })
+        })
+        .map(|(_, text)| text.split_whitespace().count())
+        .sum()
+}
+
+/// Words in the item's page, or its description if the page can't be read
+async fn extract_word_count(fetcher: &Fetcher, item: &Item) -> Option<i64> {
+    let from_page = match fetcher.get(&item.link, MAX_ARTICLE_BYTES, is_html).await {
+        Ok(page) => page.map(|page| count_words(&page.text())),
+        Err(err) => {
+            tracing::warn!("Not estimating reading time from {}: {err}", item.link);
+            None
+        }
+    };
+    from_page
+        .filter(|words| *words > 0)
+        .or_else(|| {
+            item.description
+                .as_deref()
+                .map(count_words)
+                .filter(|words| *words > 0)
+        })
+        .map(|words| words as i64)
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReadLaterReturn {
+    #[serde(flatten)]
+    pub item: GetItemsReturn,
+    pub read_later: ReadLater,
+    /// Estimated from the item's extracted content
+    pub reading_minutes: Option<i64>,
+}
+
+async fn get_entry(
+    state: &super::State,
+    user_id: i64,
+    item_id: i64,
+) -> Result<ReadLaterReturn, ApiError> {
+    let read_later = ReadLater::get(user_id, item_id, &state.sqlite)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    let item = Item::get_for_user(item_id, user_id, &state.sqlite)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    Ok(ReadLaterReturn {
+        item: GetItemsReturn::from(item).with_proxied_image(&state.config),
+        reading_minutes: read_later.reading_minutes(),
+        read_later,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetReadLaterQuery {
+    /// List finished items instead of the queue
+    #[serde(default)]
+    archived: bool,
+}
+
+pub async fn get_read_later(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+    Query(query): Query<GetReadLaterQuery>,
+) -> Result<Json<Vec<ReadLaterReturn>>, ApiError> {
+    let user = authorize(&state, &headers, Scope::Read).await?;
+    let entries = ReadLater::get_for_user(user.id, query.archived, &state.sqlite).await?;
+    let mut items = ReadLater::items(user.id, query.archived, &state.sqlite)
+        .await?
+        .into_iter()
+        .map(|item| (item.item.id, item))
+        .collect::<FxHashMap<_, _>>();
+
+    Ok(Json(
+        entries
+            .into_iter()
+            .filter_map(|read_later| {
+                let item = items.remove(&read_later.item_id)?;
+                Some(ReadLaterReturn {
+                    item: GetItemsReturn::from(item).with_proxied_image(&state.config),
+                    reading_minutes: read_later.reading_minutes(),
+                    read_later,
+                })
+            })
+            .collect(),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum QueueItem {
+    /// An item the user can already see
+    Existing { item_id: i64 },
+    /// A link to add as an item, like `create_item` does
+    Link {
+        link: String,
+        #[serde(default)]
+        title: Option<String>,
+    },
+}
+
+/// Adds an item to the end of the queue, or puts a finished one back in it
+pub async fn add_to_read_later(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+    Json(body): Json<QueueItem>,
+) -> Result<Json<ReadLaterReturn>, ApiError> {
+    let user = authorize(&state, &headers, Scope::ItemsWrite).await?;
+    let item = match body {
+        QueueItem::Existing { item_id } => {
+            Item::get_for_user(item_id, user.id, &state.sqlite)
+                .await?
+                .ok_or(ApiError::NotFound)?
+                .item
+        }
+        QueueItem::Link { link, title } => {
+            let now = Utc::now().naive_utc();
+            let item = Item {
+                // Filled in by db
+                id: 0,
+                created_at: now,
+                updated_at: now,
+
+                link,
+                title,
+                description: None,
+                author: None,
+                published: None,
+                source_link: None,
+                image: None,
+                source_id: None,
+                archived_at: None,
+                guid: None,
+                guid_is_permalink: false,
+                content_hash: None,
+                content_updated_at: None,
+            };
+            add_item(item, user.id, &state.sqlite).await?
+        }
+    };
+
+    // Keep it visible (and safe from the janitor) even if they unsubscribe from its source
+    Item::track(item.id, user.id, &state.sqlite).await?;
+    let word_count = match ReadLater::get(user.id, item.id, &state.sqlite).await? {
+        Some(queued) if queued.word_count.is_some() => queued.word_count,
+        _ => extract_word_count(&state.fetcher, &item).await,
+    };
+    ReadLater::add(user.id, item.id, word_count, &state.sqlite).await?;
+    Ok(Json(get_entry(&state, user.id, item.id).await?))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReadLaterProgress {
+    progress: i64,
+}
+
+/// Saves how far into an item the user is, at 100% it's archived and marked done
+pub async fn set_read_later_progress(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+    Path(item_id): Path<i64>,
+    Json(body): Json<ReadLaterProgress>,
+) -> Result<Json<ReadLaterReturn>, ApiError> {
+    let user = authorize(&state, &headers, Scope::ItemsWrite).await?;
+    if !(0..=100).contains(&body.progress) {
+        return Err(ApiError::BadRequest(
+            "Progress is a percentage from 0 to 100".into(),
+        ));
+    }
+    let now = Utc::now().naive_utc();
+    if !ReadLater::set_progress(user.id, item_id, body.progress, now, &state.sqlite).await? {
+        return Err(ApiError::NotFound);
+    }
+    if body.progress == 100 {
+        Item::set_done(item_id, user.id, true, &state.sqlite).await?;
+    }
+    Ok(Json(get_entry(&state, user.id, item_id).await?))
+}
+
+/// Finishes an item without reading the rest of it
+pub async fn archive_read_later(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+    Path(item_id): Path<i64>,
+) -> Result<Json<ReadLaterReturn>, ApiError> {
+    let user = authorize(&state, &headers, Scope::ItemsWrite).await?;
+    if !ReadLater::archive(user.id, item_id, Utc::now().naive_utc(), &state.sqlite).await? {
+        return Err(ApiError::NotFound);
+    }
+    Item::set_done(item_id, user.id, true, &state.sqlite).await?;
+    Ok(Json(get_entry(&state, user.id, item_id).await?))
+}
+
+/// Takes item ids in the order they should come first, the rest of the queue keeps its order
+pub async fn reorder_read_later(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+    Json(item_ids): Json<Vec<i64>>,
+) -> Result<(), ApiError> {
+    let user = authorize(&state, &headers, Scope::ItemsWrite).await?;
+    ReadLater::reorder(user.id, &item_ids, &state.sqlite).await?;
+    Ok(())
+}
+
+pub async fn remove_from_read_later(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+    Path(item_id): Path<i64>,
+) -> Result<(), ApiError> {
+    let user = authorize(&state, &headers, Scope::ItemsWrite).await?;
+    ReadLater::delete(user.id, item_id, &state.sqlite).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_words_in_the_article() {
+        let html = r#"
+            <html>
+            <head><title>Not counted</title><script>let notCounted = 1;</script></head>
+            <body>
+                <nav>Home About</nav>
+                <article>
+                    <h1>Four words in heading</h1>
+                    <p>And <em>five</em> more in   here.</p>
+                    <aside>Related posts</aside>
+                    <style>p { color: red }</style>
+                </article>
+                <footer>Copyright</footer>
+            </body>
+            </html>
+        "#;
+        assert_eq!(count_words(html), 9);
+    }
+
+    #[test]
+    fn falls_back_to_main_then_body() {
+        assert_eq!(
+            count_words("<body><nav>Menu</nav><main>Just three words</main></body>"),
+            3
+        );
+        assert_eq!(
+            count_words("<body><header>Site</header><p>Two words</p></body>"),
+            2
+        );
+        // Descriptions are usually fragments
+        assert_eq!(count_words("Plain text <b>description</b>"), 3);
+        assert_eq!(count_words(""), 0);
+    }
+}
@@ -1,14 +1,13 @@
 use std::{
+    collections::BTreeMap,
     error::Error,
+    io,
     ops::{Deref, DerefMut},
     sync::{Arc, LazyLock},
     time::Duration,
 };
 
-use chrono::DateTime;
-use futures::{stream::FuturesUnordered, StreamExt};
-use http::{header::CONTENT_TYPE, Uri};
-use rustc_hash::FxHashSet;
+use http::Uri;
 use sqlx::{Pool, Sqlite};
 use tokio::{
     select,
@@ -17,13 +16,22 @@ use tokio::{
 use ts_rs::TS;
 
 use crate::{
+    api::{
+        dates,
+        fetch::{is_html, read_capped, FetchError, Fetcher},
+        ingest::{Ingest, Mode},
+        websub,
+    },
     config::Config,
     continue_on_err,
-    db::{Item, Source, Tag},
+    db::Source,
     ApiError,
 };
 
 const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Only the head of a page is needed for its image but it has to be parsed whole
+const MAX_PAGE_BYTES: usize = 512 * 1024;
+const MAX_FEED_BYTES: usize = 16 * 1024 * 1024;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, TS)]
 #[ts(export, export_to = "../web/src/types/PollMessage.ts")]
@@ -36,9 +44,10 @@ pub enum PollMessage {
 pub struct CloneReceiver<T>(broadcast::Receiver<T>);
 
 pub fn start_poller(
-    _config: Arc<Config>,
-    client: reqwest::Client,
+    config: Arc<Config>,
+    fetcher: Fetcher,
     sqlite: Pool<Sqlite>,
+    deliver_send: mpsc::Sender<()>,
 ) -> (CloneReceiver<PollMessage>, mpsc::Sender<()>) {
     let (msg_send, msg_recv) = broadcast::channel(128);
     let (poll_send, mut poll_recv) = mpsc::channel::<()>(1);
@@ -47,8 +56,7 @@ pub fn start_poller(
         loop {
             msg_send.send(PollMessage::Polling).unwrap();
             let now = chrono::Utc::now().naive_utc();
-            let sources = continue_on_err!(Source::get_all(&sqlite).await);
-            let client = client.clone();
+            let sources = continue_on_err!(Source::get_all_subscribed(&sqlite).await);
 
             // TODO: consider parallelization (i don't really need it personally though)
             for mut source in sources {
@@ -67,152 +75,37 @@ pub fn start_poller(
                 }
 
                 tracing::debug!("Polling {}", source.name);
-                let channel = continue_on_err!(get_channel_for_source(&client, &source).await);
-                let source_tags = continue_on_err!(Source::tags(source.id, &sqlite).await);
+                let mut channel = continue_on_err!(get_channel_for_source(&fetcher, &source).await);
 
-                let categories = std::sync::Mutex::new(FxHashSet::with_capacity_and_hasher(
-                    channel.items.len() * 3,
-                    Default::default(),
-                ));
-                let items = std::sync::Mutex::new(Vec::with_capacity(channel.items.len()));
-
-                let mut futures = channel
-                    .items
-                    .into_iter()
-                    .map(|channel_item| {
-                        async {
-                            let link = channel_item.link.ok_or_else(|| "item has no link")?;
-                            let pub_date = channel_item
-                                .pub_date
-                                .and_then(|s| DateTime::parse_from_rfc2822(&s).ok());
-                            if let Some(min_date) = source.min_date {
-                                if let Some(pub_date) = pub_date {
-                                    if pub_date
-                                        < min_date.and_local_timezone(pub_date.timezone()).unwrap()
-                                    {
-                                        // If item is older than the min_date for this source, ignore it
-                                        tracing::debug!("Ignoring {link} because its too old.");
-                                        return Ok(());
-                                    }
-                                }
-                            }
-
-                            let image = get_image_from_link(&client, &link).await?;
-
-                            let item = Item {
-                                // Filled in by db
-                                id: 0,
-                                created_at: now,
-                                updated_at: now,
-
-                                title: channel_item.title,
-                                link,
-                                author: channel_item.author,
-                                description: channel_item.description,
-                                favorite: false,
-                                done: false,
-                                published: pub_date.as_ref().map(DateTime::naive_local),
-                                image,
-                                source_id: Some(source.id),
-                                source_link: Some(source.url.clone()),
-                            };
-                            let item_categories = channel_item
-                                .categories
-                                .into_iter()
-                                .filter(|c| !c.name.is_empty())
-                                .map(|c| Arc::<str>::from(c.name.to_ascii_lowercase().as_str()))
-                                .collect::<FxHashSet<_>>();
-
-                            let mut categories = categories.lock().unwrap();
-                            for cat in &item_categories {
-                                categories.insert(cat.clone());
-                            }
-                            items.lock().unwrap().push((item, item_categories));
-                            Ok::<(), Box<dyn Error + 'static>>(())
-                        }
-                    })
-                    .collect::<FuturesUnordered<_>>();
-
-                while let Some(item_result) = futures.next().await {
-                    if let Err(err) = item_result {
-                        tracing::error!("Error while creating item from {}: {err:?}", source.name);
-                    }
+                // Subscribe to (or renew) pushes if the feed has a hub
+                if let Err(err) =
+                    websub::maintain(&config, &fetcher, &sqlite, &source, &channel).await
+                {
+                    tracing::error!("Error subscribing to hub for {}: {err:?}", source.name);
                 }
-                drop(futures);
+
+                let pub_date = channel.pub_date.take();
+                let ttl = channel.ttl.take();
+                let ingest = Ingest {
+                    fetcher: &fetcher,
+                    sqlite: &sqlite,
+                    deliver_send: &deliver_send,
+                    category_tags: config.category_tags,
+                };
+                continue_on_err!(ingest.run(&source, channel, Mode::Commit).await);
 
                 // We can consider the polling done at this point and update the row
-                source.last_pub = channel
-                    .pub_date
-                    .and_then(|pub_date| {
-                        chrono::DateTime::parse_from_rfc2822(&pub_date)
-                            .ok()
-                            .map(|dt| dt.naive_utc())
-                    })
+                source.last_pub = pub_date
+                    .as_deref()
+                    .and_then(dates::parse)
+                    .map(|dt| dt.naive_utc())
                     .unwrap_or(now);
                 source.last_poll = Some(now);
-                source.ttl = channel
-                    .ttl
-                    .as_deref()
-                    .and_then(|ttl_str| ttl_str.parse().ok());
+                source.ttl = ttl.as_deref().and_then(|ttl_str| ttl_str.parse().ok());
                 if let Err(err) = source.update(&sqlite).await {
                     tracing::error!("Error updating row for {}: {err:?}", source.name);
                 };
                 msg_send.send(PollMessage::PollDone).ok();
-
-                // Try to create tags for each category we found in the items
-                let category_tags = categories
-                    .into_inner()
-                    .unwrap()
-                    .iter()
-                    .map(|category| Tag {
-                        created_at: now,
-                        updated_at: now,
-                        text_color: None,
-                        background_color: None,
-                        border_color: None,
-
-                        name: category.to_string(),
-                    })
-                    .collect::<Vec<_>>();
-                if let Err(err) = Tag::insert_many(&category_tags, &sqlite).await {
-                    tracing::error!("Failed to create tags from categories: {err:?}");
-                };
-
-                for (mut item, mut item_categories) in items.into_inner().unwrap() {
-                    match item.insert(&sqlite).await {
-                        Ok(_) => {
-                            tracing::info!("Inserted new item for {}", item.link);
-                            // Add tags from the source
-                            for source_tag in &source_tags {
-                                item_categories.insert(Arc::from(source_tag.name.as_str()));
-                            }
-
-                            // Now add the tags to the item
-                            if let Err(err) = Item::add_tags(
-                                item.id,
-                                &item_categories.iter().map(Deref::deref).collect::<Vec<_>>(),
-                                &sqlite,
-                            )
-                            .await
-                            {
-                                tracing::error!("Failed to add tags to {}: {err:?}", item.link);
-                            };
-                        }
-                        Err(err) => match err.into_sqlx_error() {
-                            sqlx::Error::Database(db_err)
-                                if db_err.kind() == sqlx::error::ErrorKind::UniqueViolation =>
-                            {
-                                tracing::debug!(
-                                    "Tried to insert link {} that already exists",
-                                    item.link
-                                );
-                            }
-                            err => {
-                                tracing::error!("Error while adding item: {err:?}");
-                            }
-                        },
-                    }
-                }
             }
 
             // Wait for interval or be notified of immediate poll
@@ -226,38 +119,52 @@ pub fn start_poller(
     (CloneReceiver(msg_recv), poll_send)
 }
 
+/// Fetches and parses a source's feed. Feed urls come from users, so they're held to the same
+/// private address checks as the links inside them.
 pub async fn get_channel_for_source(
-    client: &reqwest::Client,
+    fetcher: &Fetcher,
     source: &Source,
 ) -> Result<rss::Channel, ApiError> {
-    let res = client.get(&source.url).send().await?;
-    Ok(rss::Channel::read_from(&*res.bytes().await?)?)
+    let mut req = fetcher.request(reqwest::Method::GET, &source.url)?;
+    if let Some(headers) = &source.headers {
+        let headers: BTreeMap<String, String> =
+            serde_json::from_str(headers).map_err(io::Error::from)?;
+        for (name, value) in headers {
+            req = req.header(name, value);
+        }
+    }
+    if let Some(username) = &source.auth_username {
+        req = req.basic_auth(username, source.auth_password.as_deref());
+    }
+
+    let res = req
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(FetchError::from)?;
+    let body = read_capped(res, MAX_FEED_BYTES).await?;
+    Ok(rss::Channel::read_from(&*body)?)
 }
 
 pub async fn get_image_from_link(
-    client: &reqwest::Client,
+    fetcher: &Fetcher,
     link: &str,
 ) -> Result<Option<String>, Box<dyn Error + 'static>> {
     static SELECTOR: LazyLock<scraper::Selector> =
         LazyLock::new(|| scraper::Selector::parse("head > meta[property=\"og:image\"]").unwrap());
 
-    let res = client.get(link).send().await?;
-
-    if res.content_length().is_some_and(|len| len > 512 * 1024) {
-        tracing::warn!("HTML for {link} over 512KiB, not parsing");
-        return Ok(None);
-    }
-
-    if !res
-        .headers()
-        .get(CONTENT_TYPE)
-        .is_some_and(|typ| typ == "text/html")
-    {
+    let page = match fetcher.get(link, MAX_PAGE_BYTES, is_html).await {
+        Ok(Some(page)) => page,
         // Not html
-        return Ok(None);
-    }
+        Ok(_) => return Ok(None),
+        // The item is still worth having without an image, whatever happened to its page
+        Err(err) => {
+            tracing::warn!("Not parsing HTML for {link}: {err}");
+            return Ok(None);
+        }
+    };
 
-    let page = scraper::Html::parse_document(&res.text().await?);
+    let page = scraper::Html::parse_document(&page.text());
     if !page.errors.is_empty() {
         tracing::error!("Html parse errors for {link}: {:?}", page.errors);
         return Ok(None);
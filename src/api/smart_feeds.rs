@@ -0,0 +1,138 @@
+//! Saved views of the feed. Each one is compiled to a single query in the db layer, so they stay
+//! cheap however many filters they combine.
+
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use http::HeaderMap;
+use serde::Deserialize;
+
+use crate::{db::SmartFeed, ApiError};
+
+use super::{
+    auth::{authorize, Scope},
+    crud::GetItemsReturn,
+};
+
+fn check_name(smart_feed: &SmartFeed) -> Result<(), ApiError> {
+    if smart_feed.name.trim().is_empty() {
+        return Err(ApiError::BadRequest(
+            "Smart feed name can't be empty".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// Names are unique per user
+fn name_taken(err: crate::db::Error, name: &str) -> ApiError {
+    match err {
+        crate::db::Error::InsertError(_, sqlx::Error::Database(db_err))
+        | crate::db::Error::UpdateError(_, sqlx::Error::Database(db_err))
+            if db_err.kind() == sqlx::error::ErrorKind::UniqueViolation =>
+        {
+            ApiError::BadRequest(format!("A smart feed named {name} already exists"))
+        }
+        err => err.into(),
+    }
+}
+
+pub async fn get_smart_feeds(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<SmartFeed>>, ApiError> {
+    let user = authorize(&state, &headers, Scope::Read).await?;
+    Ok(Json(SmartFeed::get_for_user(user.id, &state.sqlite).await?))
+}
+
+pub async fn get_smart_feed(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+) -> Result<Json<SmartFeed>, ApiError> {
+    let user = authorize(&state, &headers, Scope::Read).await?;
+    Ok(Json(
+        SmartFeed::get_by_id(id, user.id, &state.sqlite)
+            .await?
+            .ok_or(ApiError::NotFound)?,
+    ))
+}
+
+pub async fn create_smart_feed(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+    Json(mut smart_feed): Json<SmartFeed>,
+) -> Result<Json<SmartFeed>, ApiError> {
+    let user = authorize(&state, &headers, Scope::ItemsWrite).await?;
+    check_name(&smart_feed)?;
+    smart_feed.user_id = user.id;
+    smart_feed
+        .insert(&state.sqlite)
+        .await
+        .map_err(|err| name_taken(err, &smart_feed.name))?;
+    Ok(Json(smart_feed))
+}
+
+pub async fn update_smart_feed(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+    Json(mut smart_feed): Json<SmartFeed>,
+) -> Result<Json<SmartFeed>, ApiError> {
+    let user = authorize(&state, &headers, Scope::ItemsWrite).await?;
+    check_name(&smart_feed)?;
+    smart_feed.id = id;
+    smart_feed.user_id = user.id;
+    if !smart_feed
+        .update(&state.sqlite)
+        .await
+        .map_err(|err| name_taken(err, &smart_feed.name))?
+    {
+        return Err(ApiError::NotFound);
+    }
+    Ok(Json(
+        SmartFeed::get_by_id(id, user.id, &state.sqlite)
+            .await?
+            .ok_or(ApiError::NotFound)?,
+    ))
+}
+
+pub async fn delete_smart_feed(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+) -> Result<(), ApiError> {
+    let user = authorize(&state, &headers, Scope::ItemsWrite).await?;
+    SmartFeed::delete(id, user.id, &state.sqlite).await?;
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SmartFeedItemsQuery {
+    #[serde(default = "default_items_limit")]
+    limit: i64,
+}
+
+fn default_items_limit() -> i64 {
+    200
+}
+
+pub async fn get_smart_feed_items(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+    Query(query): Query<SmartFeedItemsQuery>,
+) -> Result<Json<Vec<GetItemsReturn>>, ApiError> {
+    let user = authorize(&state, &headers, Scope::Read).await?;
+    let smart_feed = SmartFeed::get_by_id(id, user.id, &state.sqlite)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    Ok(Json(
+        smart_feed
+            .items(query.limit.clamp(1, 1000), &state.sqlite)
+            .await?
+            .into_iter()
+            .map(|item| GetItemsReturn::from(item).with_proxied_image(&state.config))
+            .collect(),
+    ))
+}
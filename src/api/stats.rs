@@ -0,0 +1,40 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use chrono::Utc;
+use http::HeaderMap;
+use serde::Deserialize;
+
+use crate::{db::Stats, ApiError};
+
+use super::auth::user_or_owner;
+
+#[derive(Debug, Deserialize)]
+pub struct GetStatsQuery {
+    /// How many days of fetched items to count, including today
+    #[serde(default = "default_days")]
+    days: u64,
+}
+
+fn default_days() -> u64 {
+    30
+}
+
+/// Unread, total and favorite counts per source and tag, and how many items came in each day
+pub async fn get_stats(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+    Query(query): Query<GetStatsQuery>,
+) -> Result<Json<Stats>, ApiError> {
+    let user = user_or_owner(&state, &headers).await?;
+    Ok(Json(
+        Stats::load(
+            user.id,
+            query.days.clamp(1, 365),
+            Utc::now().naive_utc(),
+            &state.sqlite,
+        )
+        .await?,
+    ))
+}
@@ -0,0 +1,2346 @@
+//! End to end tests of the API against a temporary database and a local stand-in for the sites
+//! feeds point to, so nothing touches the network.
+
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use axum::{body::Body, extract::Path, routing::get, Router};
+use http::{
+    header::{AUTHORIZATION, CACHE_CONTROL, CONTENT_TYPE},
+    HeaderMap, HeaderValue, Request, StatusCode,
+};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool};
+use tempfile::TempDir;
+use tokio::net::TcpListener;
+use tower::ServiceExt;
+
+use crate::{
+    config::Config,
+    db::{self, item::Retention, Item, Source},
+    ApiError,
+};
+
+use super::{
+    api_router,
+    fetch::Fetcher,
+    ingest::{Ingest, Mode},
+};
+
+const PASSWORD: &str = "password";
+
+struct Harness {
+    api: Router,
+    config: Arc<Config>,
+    /// Base url of the stand-in server
+    base: String,
+    sqlite: SqlitePool,
+    _dir: TempDir,
+}
+
+/// Serves the files in `tests/fixtures` with `{{base}}` replaced by the server's url
+async fn start_standin() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+
+    let serve = |content_type: &'static str| {
+        let base = base.clone();
+        move |Path(name): Path<String>| {
+            let base = base.clone();
+            async move {
+                let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                    .join("tests/fixtures")
+                    .join(name);
+                match tokio::fs::read_to_string(path).await {
+                    Ok(body) => (
+                        StatusCode::OK,
+                        [(CONTENT_TYPE, content_type)],
+                        body.replace("{{base}}", &base),
+                    ),
+                    Err(_) => (
+                        StatusCode::NOT_FOUND,
+                        [(CONTENT_TYPE, "text/plain")],
+                        String::new(),
+                    ),
+                }
+            }
+        }
+    };
+    let router = Router::new()
+        .route("/feeds/{name}", get(serve("application/rss+xml")))
+        .route("/private/{name}", {
+            let feed = serve("application/rss+xml");
+            get(async move |headers: HeaderMap, Path(name): Path<String>| {
+                // reader:hunter2
+                let authorized = headers.get(AUTHORIZATION)
+                    == Some(&HeaderValue::from_static("Basic cmVhZGVyOmh1bnRlcjI="))
+                    && headers.get("x-token") == Some(&HeaderValue::from_static("secret"));
+                if !authorized {
+                    return (
+                        StatusCode::UNAUTHORIZED,
+                        [(CONTENT_TYPE, "text/plain")],
+                        String::new(),
+                    );
+                }
+                feed(Path(name)).await
+            })
+        })
+        .route("/articles/{name}", {
+            let html = serve("text/html; charset=utf-8");
+            let text = serve("text/plain");
+            get(async move |Path(name): Path<String>| {
+                if name.ends_with(".html") {
+                    html(Path(name)).await
+                } else {
+                    text(Path(name)).await
+                }
+            })
+        })
+        .route(
+            "/images/{name}",
+            get(async |Path(name): Path<String>| {
+                let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+                    .join("tests/fixtures")
+                    .join(name);
+                match tokio::fs::read(path).await {
+                    Ok(body) => (StatusCode::OK, [(CONTENT_TYPE, "image/png")], body),
+                    Err(_) => (
+                        StatusCode::NOT_FOUND,
+                        [(CONTENT_TYPE, "text/plain")],
+                        vec![],
+                    ),
+                }
+            }),
+        );
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+    base
+}
+
+impl Harness {
+    async fn new() -> Self {
+        Self::with_config(json!({})).await
+    }
+
+    /// Starts the API with `overrides` merged into the test config
+    async fn with_config(overrides: Value) -> Self {
+        let dir = TempDir::new().unwrap();
+        let mut config = json!({
+            "domain": "http://localhost",
+            "web_dir": dir.path(),
+            "data_dir": dir.path(),
+            "password": PASSWORD,
+            // The stand-in is on loopback
+            "allow_private_addresses": true,
+        });
+        config
+            .as_object_mut()
+            .unwrap()
+            .extend(overrides.as_object().unwrap().clone());
+        let config = Arc::new(Config::from_json(config.to_string().as_bytes()).unwrap());
+
+        let options = SqliteConnectOptions::new()
+            .filename(dir.path().join("db"))
+            .create_if_missing(true)
+            .pragma("foreign_keys", "on");
+        let sqlite = SqlitePool::connect_with(options).await.unwrap();
+        db::migrate(&sqlite).await.unwrap();
+
+        Self {
+            api: api_router(config.clone(), sqlite.clone()).unwrap(),
+            config,
+            base: start_standin().await,
+            sqlite,
+            _dir: dir,
+        }
+    }
+
+    async fn request(&self, method: &str, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+        self.request_as(("x-auth", PASSWORD), method, uri, body)
+            .await
+    }
+
+    /// Like [`Self::request`] but authenticated with the `auth` header instead of the owner's
+    /// password
+    async fn request_as(
+        &self,
+        auth: (&str, &str),
+        method: &str,
+        uri: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let req = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(auth.0, auth.1)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.map_or_else(Body::empty, |body| Body::from(body.to_string())))
+            .unwrap();
+        self.send(req).await
+    }
+
+    async fn send(&self, req: Request<Body>) -> (StatusCode, Value) {
+        let res = self.api.clone().oneshot(req).await.unwrap();
+        let status = res.status();
+        let bytes = res.into_body().collect().await.unwrap().to_bytes();
+        (
+            status,
+            serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+        )
+    }
+
+    async fn create_source(&self, feed: &str) -> (StatusCode, Value) {
+        self.request(
+            "POST",
+            "/sources",
+            Some(json!({
+                "id": 0,
+                "name": feed,
+                "url": format!("{}/feeds/{feed}", self.base),
+                "lastPoll": null,
+                "ttl": null,
+            })),
+        )
+        .await
+    }
+
+    /// Creates a user that isn't an admin, returning the `x-auth` value to log in as them
+    async fn create_user(&self, name: &str) -> String {
+        let (status, _) = self
+            .request(
+                "POST",
+                "/users",
+                Some(json!({ "name": name, "password": "hunter2" })),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        format!("{name}:hunter2")
+    }
+
+    /// Waits for the poller to insert `count` items
+    async fn wait_for_items(&self, count: usize) -> Vec<Value> {
+        for _ in 0..100 {
+            let (_, items) = self
+                .request("GET", "/items?from_last=1d&sort=fetched", None)
+                .await;
+            let items = items.as_array().cloned().unwrap_or_default();
+            if items.len() >= count {
+                return items;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("poller never inserted {count} items");
+    }
+}
+
+fn find<'a>(items: &'a [Value], title: &str) -> &'a Value {
+    items
+        .iter()
+        .find(|item| item["title"] == title)
+        .unwrap_or_else(|| panic!("no item titled {title}"))
+}
+
+#[tokio::test]
+async fn create_source_poll_and_mark_done() {
+    let harness = Harness::new().await;
+
+    let (status, source) = harness.create_source("standin.rss").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(source["ttl"], 30);
+
+    let items = harness.wait_for_items(2).await;
+    assert_eq!(items.len(), 2);
+
+    let with_image = find(&items, "With image");
+    // Images are served through our proxy, not straight from the publisher
+    let image = with_image["image"].as_str().unwrap();
+    assert!(image.starts_with(&format!(
+        "http://localhost/api/images/{}?sig=",
+        with_image["id"]
+    )));
+    assert_eq!(with_image["tags"], json!(["rust"]));
+    assert_eq!(with_image["source_id"], source["id"]);
+    assert_eq!(with_image["done"], false);
+
+    // Pages that aren't HTML are still ingested, just without an image
+    let not_html = find(&items, "Not HTML");
+    assert_eq!(not_html["image"], Value::Null);
+
+    let id = with_image["id"].as_i64().unwrap();
+    let (status, _) = harness
+        .request("POST", &format!("/items/{id}/done"), None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, items) = harness
+        .request("GET", "/items?from_last=1d&sort=fetched", None)
+        .await;
+    assert_eq!(items.as_array().unwrap().len(), 1);
+    let (_, items) = harness
+        .request(
+            "GET",
+            "/items?from_last=1d&sort=fetched&include_done=true",
+            None,
+        )
+        .await;
+    assert_eq!(find(items.as_array().unwrap(), "With image")["done"], true);
+}
+
+#[tokio::test]
+async fn malformed_feed_is_rejected() {
+    let harness = Harness::new().await;
+
+    let (status, _) = harness.create_source("malformed.rss").await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+
+    let (_, sources) = harness.request("GET", "/sources", None).await;
+    assert_eq!(sources, json!([]));
+}
+
+#[tokio::test]
+async fn missing_feed_is_rejected() {
+    let harness = Harness::new().await;
+
+    let (status, _) = harness.create_source("does-not-exist.rss").await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[tokio::test]
+async fn writes_require_auth() {
+    let harness = Harness::new().await;
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/items/1/done")
+        .body(Body::empty())
+        .unwrap();
+    let (status, _) = harness.send(req).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn preview_does_not_store_items() {
+    let harness = Harness::new().await;
+
+    let (status, preview) = harness
+        .request(
+            "POST",
+            "/sources/preview",
+            Some(json!({
+                "id": 0,
+                "name": "preview",
+                "url": format!("{}/feeds/standin.rss", harness.base),
+                "lastPoll": null,
+                "ttl": null,
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(preview.as_array().unwrap().len(), 2);
+
+    let count = sqlx::query_scalar!("SELECT COUNT(*) FROM items")
+        .fetch_one(&harness.sqlite)
+        .await
+        .unwrap();
+    assert_eq!(count, 0);
+}
+
+#[tokio::test]
+async fn old_items_from_new_sources_stay_out_of_recent_feed() {
+    let harness = Harness::new().await;
+
+    harness.create_source("standin.rss").await;
+    let items = harness.wait_for_items(2).await;
+    assert_eq!(
+        find(&items, "With image")["published"],
+        "2025-03-01T12:00:00Z",
+        "published dates are returned in UTC"
+    );
+
+    // Both items were published long before they were fetched
+    let (_, recent) = harness.request("GET", "/items?from_last=1d", None).await;
+    assert_eq!(recent, json!([]));
+
+    let (_, all) = harness
+        .request("GET", "/items?from_last=100years", None)
+        .await;
+    let titles = all
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["title"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(titles, ["With image", "Not HTML"]);
+}
+
+#[tokio::test]
+async fn janitor_expires_done_items_but_keeps_favorites() {
+    let harness = Harness::new().await;
+
+    harness.create_source("standin.rss").await;
+    let items = harness.wait_for_items(2).await;
+    let done = find(&items, "With image")["id"].as_i64().unwrap();
+    let favorite = find(&items, "Not HTML")["id"].as_i64().unwrap();
+    harness
+        .request("POST", &format!("/items/{done}/done"), None)
+        .await;
+    harness
+        .request("POST", &format!("/items/{favorite}/done"), None)
+        .await;
+    harness
+        .request("POST", &format!("/items/{favorite}/favorite"), None)
+        .await;
+
+    let retention = Retention {
+        now: (chrono::Utc::now() + chrono::Duration::days(2)).naive_utc(),
+        done_for: Some(24 * 60 * 60),
+        unread_for: None,
+    };
+    let counts = Item::count_expired(retention, &harness.sqlite)
+        .await
+        .unwrap();
+    assert_eq!((counts.done, counts.unread), (1, 0));
+
+    let (status, counts) = harness
+        .request("GET", "/retention/preview?done_for=1d", None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        counts,
+        json!({ "done": 0, "unread": 0 }),
+        "nothing is a day old yet"
+    );
+
+    assert_eq!(
+        Item::expire(retention, false, &harness.sqlite)
+            .await
+            .unwrap(),
+        1
+    );
+    assert!(Item::get_by_id(done, &harness.sqlite)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(Item::get_by_id(favorite, &harness.sqlite)
+        .await
+        .unwrap()
+        .is_some());
+}
+
+#[tokio::test]
+async fn deleted_items_are_not_ingested_again() {
+    let harness = Harness::new().await;
+
+    let (_, source) = harness.create_source("standin.rss").await;
+    let items = harness.wait_for_items(2).await;
+    let id = find(&items, "With image")["id"].as_i64().unwrap();
+    let (status, _) = harness
+        .request("DELETE", &format!("/items/{id}"), None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Ingest the feed again like the next poll would
+    let source = Source::get_by_id(source["id"].as_i64().unwrap(), &harness.sqlite)
+        .await
+        .unwrap()
+        .unwrap();
+    let channel =
+        super::rss::get_channel_for_source(&Fetcher::new(&harness.config).unwrap(), &source)
+            .await
+            .unwrap();
+    let fetcher = Fetcher::new(&harness.config).unwrap();
+    let ingest = Ingest {
+        fetcher: &fetcher,
+        sqlite: &harness.sqlite,
+        deliver_send: &tokio::sync::mpsc::channel(1).0,
+        category_tags: harness.config.category_tags,
+    };
+    let inserted = ingest.run(&source, channel, Mode::Commit).await.unwrap();
+    assert!(inserted.is_empty());
+
+    let (_, items) = harness
+        .request("GET", "/items?from_last=1d&sort=fetched", None)
+        .await;
+    let titles = items
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["title"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(titles, ["Not HTML"]);
+}
+
+#[tokio::test]
+async fn items_with_the_same_guid_are_the_same_item() {
+    let harness = Harness::new().await;
+
+    let (_, source) = harness.create_source("standin.rss").await;
+    harness.wait_for_items(2).await;
+    let source = Source::get_by_id(source["id"].as_i64().unwrap(), &harness.sqlite)
+        .await
+        .unwrap()
+        .unwrap();
+
+    let mut channel =
+        super::rss::get_channel_for_source(&Fetcher::new(&harness.config).unwrap(), &source)
+            .await
+            .unwrap();
+    let mut build = |link: &str| {
+        let item = &mut channel.items[0];
+        item.link = Some(link.into());
+        item.guid = Some(::rss::Guid {
+            value: "stable-id".into(),
+            permalink: false,
+        });
+        super::ingest::build_items(&source, channel.clone())
+            .remove(0)
+            .item
+    };
+
+    let mut first = build("http://localhost/old-url");
+    first.insert(&harness.sqlite).await.unwrap();
+    // The feed moved the item to a new url but kept its guid
+    let mut moved = build("http://localhost/new-url");
+    let err = moved.insert(&harness.sqlite).await.unwrap_err();
+    assert!(matches!(
+        err.into_sqlx_error(),
+        sqlx::Error::Database(err) if err.kind() == sqlx::error::ErrorKind::UniqueViolation
+    ));
+}
+
+#[tokio::test]
+async fn new_guids_reusing_a_link_are_new_items() {
+    let harness = Harness::new().await;
+
+    let (_, source) = harness.create_source("standin.rss").await;
+    harness.wait_for_items(2).await;
+    let source = Source::get_by_id(source["id"].as_i64().unwrap(), &harness.sqlite)
+        .await
+        .unwrap()
+        .unwrap();
+    let fetcher = Fetcher::new(&harness.config).unwrap();
+    let ingest = Ingest {
+        fetcher: &fetcher,
+        sqlite: &harness.sqlite,
+        deliver_send: &tokio::sync::mpsc::channel(1).0,
+        category_tags: harness.config.category_tags,
+    };
+    let channel =
+        super::rss::get_channel_for_source(&Fetcher::new(&harness.config).unwrap(), &source)
+            .await
+            .unwrap();
+    let link = format!("{}/articles/with-image.html?shared", harness.base);
+    let with_guid = |guid: &str, title: &str| {
+        let mut channel = channel.clone();
+        channel.items.truncate(1);
+        channel.items[0].link = Some(link.clone());
+        channel.items[0].title = Some(title.into());
+        channel.items[0].guid = Some(::rss::Guid {
+            value: guid.into(),
+            permalink: false,
+        });
+        channel
+    };
+
+    let inserted = ingest
+        .run(&source, with_guid("first", "First"), Mode::Commit)
+        .await
+        .unwrap();
+    assert_eq!(inserted.len(), 1);
+    // A different guid is a different post, even on the same page
+    let inserted = ingest
+        .run(&source, with_guid("second", "Second"), Mode::Commit)
+        .await
+        .unwrap();
+    assert_eq!(inserted.len(), 1);
+
+    let mut titles = sqlx::query_scalar!("SELECT title FROM items WHERE link = ?1", link)
+        .fetch_all(&harness.sqlite)
+        .await
+        .unwrap();
+    titles.sort();
+    assert_eq!(
+        titles,
+        [Some("First".to_string()), Some("Second".to_string())]
+    );
+
+    // Another feed linking the same pages gets its own items
+    harness.create_source("standin.rss?mirror").await;
+    let items = harness.wait_for_items(6).await;
+    assert_eq!(
+        items
+            .iter()
+            .filter(|item| item["title"] == "With image")
+            .count(),
+        2
+    );
+}
+
+#[tokio::test]
+async fn changed_items_are_updated_with_history() {
+    let harness = Harness::new().await;
+
+    let (_, source) = harness.create_source("standin.rss").await;
+    let items = harness.wait_for_items(2).await;
+    let id = find(&items, "With image")["id"].as_i64().unwrap();
+    assert_eq!(find(&items, "With image")["updated"], false);
+
+    let source = Source::get_by_id(source["id"].as_i64().unwrap(), &harness.sqlite)
+        .await
+        .unwrap()
+        .unwrap();
+    let mut channel =
+        super::rss::get_channel_for_source(&Fetcher::new(&harness.config).unwrap(), &source)
+            .await
+            .unwrap();
+    channel.items[0].title = Some("With image (corrected)".into());
+    let fetcher = Fetcher::new(&harness.config).unwrap();
+    let ingest = Ingest {
+        fetcher: &fetcher,
+        sqlite: &harness.sqlite,
+        deliver_send: &tokio::sync::mpsc::channel(1).0,
+        category_tags: harness.config.category_tags,
+    };
+    let inserted = ingest.run(&source, channel, Mode::Commit).await.unwrap();
+    assert!(inserted.is_empty());
+
+    let (_, item) = harness.request("GET", &format!("/items/{id}"), None).await;
+    assert_eq!(item["title"], "With image (corrected)");
+    assert_eq!(item["updated"], true);
+
+    let (_, revisions) = harness
+        .request("GET", &format!("/items/{id}/revisions"), None)
+        .await;
+    assert_eq!(revisions.as_array().unwrap().len(), 1);
+    assert_eq!(revisions[0]["title"], "With image");
+}
+
+#[tokio::test]
+async fn items_whose_pages_fail_are_kept_without_an_image() {
+    let harness = Harness::new().await;
+    let (_, source) = harness.create_source("standin.rss").await;
+    harness.wait_for_items(2).await;
+
+    let source = Source::get_by_id(source["id"].as_i64().unwrap(), &harness.sqlite)
+        .await
+        .unwrap()
+        .unwrap();
+    let mut channel =
+        super::rss::get_channel_for_source(&Fetcher::new(&harness.config).unwrap(), &source)
+            .await
+            .unwrap();
+    channel.items.truncate(1);
+    channel.items[0].link = Some(format!("{}/articles/missing.html", harness.base));
+    channel.items[0].guid = Some(::rss::Guid {
+        value: "missing".into(),
+        permalink: false,
+    });
+    let fetcher = Fetcher::new(&harness.config).unwrap();
+    let ingest = Ingest {
+        fetcher: &fetcher,
+        sqlite: &harness.sqlite,
+        deliver_send: &tokio::sync::mpsc::channel(1).0,
+        category_tags: harness.config.category_tags,
+    };
+    let inserted = ingest.run(&source, channel, Mode::Commit).await.unwrap();
+    assert_eq!(inserted.len(), 1);
+    assert_eq!(inserted[0].item.image, None);
+}
+
+#[tokio::test]
+async fn images_are_proxied_as_cached_thumbnails() {
+    let harness = Harness::new().await;
+    harness.create_source("standin.rss").await;
+    let items = harness.wait_for_items(2).await;
+    let with_image = find(&items, "With image");
+    let id = with_image["id"].as_i64().unwrap();
+    let uri = with_image["image"]
+        .as_str()
+        .unwrap()
+        .trim_start_matches("http://localhost/api")
+        .to_string();
+
+    // No auth header, the signature is enough
+    let get = |uri: String| {
+        let api = harness.api.clone();
+        async move {
+            let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+            api.oneshot(req).await.unwrap()
+        }
+    };
+    let res = get(uri.clone()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()[CONTENT_TYPE], "image/jpeg");
+    assert!(res.headers()[CACHE_CONTROL]
+        .to_str()
+        .unwrap()
+        .contains("immutable"));
+    let thumbnail = res.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(&thumbnail[..2], &[0xff, 0xd8]);
+    let cached = || {
+        std::fs::read_dir(harness._dir.path().join("images"))
+            .unwrap()
+            .count()
+    };
+    assert_eq!(cached(), 1);
+
+    // Served from the cache the second time
+    let res = get(uri.clone()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = get(format!("/images/{id}?sig=00000000000000000000000000000000")).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    let not_html = find(&items, "Not HTML")["id"].as_i64().unwrap();
+    let res = get(format!("/images/{not_html}?sig=bad")).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    // A new image gets a new url, and the old thumbnail goes once nothing uses it
+    assert_eq!(
+        super::images::remove_unused(&harness.config, &harness.sqlite)
+            .await
+            .unwrap(),
+        0
+    );
+    sqlx::query("UPDATE items SET image = image || '?v=2' WHERE id = ?")
+        .bind(id)
+        .execute(&harness.sqlite)
+        .await
+        .unwrap();
+    let res = get(uri).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    assert_eq!(
+        super::images::remove_unused(&harness.config, &harness.sqlite)
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(cached(), 0);
+}
+
+#[tokio::test]
+async fn feeds_and_links_at_private_addresses_are_not_fetched() {
+    let strict = Harness::with_config(json!({ "allow_private_addresses": false })).await;
+    let (status, _) = strict.create_source("standin.rss").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = strict
+        .request(
+            "POST",
+            "/sources/preview",
+            Some(json!({
+                "id": 0,
+                "name": "preview",
+                "url": format!("{}/feeds/standin.rss", strict.base),
+                "lastPoll": null,
+                "ttl": null,
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Nor are the articles a feed links to on loopback
+    let harness = Harness::new().await;
+    let (_, source) = harness.create_source("standin.rss").await;
+    harness.wait_for_items(2).await;
+    let source = Source::get_by_id(source["id"].as_i64().unwrap(), &harness.sqlite)
+        .await
+        .unwrap()
+        .unwrap();
+    let channel =
+        super::rss::get_channel_for_source(&Fetcher::new(&harness.config).unwrap(), &source)
+            .await
+            .unwrap();
+    let fetcher = Fetcher::new(&strict.config).unwrap();
+    let ingest = Ingest {
+        fetcher: &fetcher,
+        sqlite: &harness.sqlite,
+        deliver_send: &tokio::sync::mpsc::channel(1).0,
+        category_tags: harness.config.category_tags,
+    };
+    let previewed = ingest.run(&source, channel, Mode::DryRun).await.unwrap();
+    assert_eq!(previewed.len(), 2);
+    assert!(previewed
+        .iter()
+        .all(|ingested| ingested.item.image.is_none()));
+}
+
+#[tokio::test]
+async fn private_feeds_are_fetched_with_credentials() {
+    let harness = Harness::new().await;
+    let source = |credentials: Value| {
+        let mut source = json!({
+            "id": 0,
+            "name": "private",
+            "url": format!("{}/private/standin.rss", harness.base),
+            "lastPoll": null,
+            "ttl": null,
+        });
+        source
+            .as_object_mut()
+            .unwrap()
+            .extend(credentials.as_object().unwrap().clone());
+        source
+    };
+
+    let (status, _) = harness
+        .request("POST", "/sources", Some(source(json!({}))))
+        .await;
+    assert_ne!(status, StatusCode::OK);
+
+    let (status, created) = harness
+        .request(
+            "POST",
+            "/sources",
+            Some(source(json!({
+                "headers": { "x-token": "secret" },
+                "username": "reader",
+                "password": "hunter2",
+            }))),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(created["authUsername"], "reader");
+    assert!(created.get("authPassword").is_none());
+    assert!(created.get("headers").is_none());
+    harness.wait_for_items(2).await;
+
+    let id = created["id"].as_i64().unwrap();
+
+    // Others only get to subscribe to it if they know the credentials too
+    let other = harness.create_user("other").await;
+    let other = ("x-auth", other.as_str());
+    let (status, _) = harness
+        .request_as(other, "POST", "/sources", Some(source(json!({}))))
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = harness
+        .request_as(
+            other,
+            "POST",
+            "/sources",
+            Some(source(json!({
+                "headers": { "x-token": "secret" },
+                "username": "reader",
+                "password": "guess",
+            }))),
+        )
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, subscribed) = harness
+        .request_as(
+            other,
+            "POST",
+            "/sources",
+            Some(source(json!({
+                "headers": { "x-token": "secret" },
+                "username": "reader",
+                "password": "hunter2",
+            }))),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(subscribed["id"], id);
+
+    // The poller uses the stored credentials too
+    let source = Source::get_by_id(id, &harness.sqlite)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(source.auth_password.as_deref(), Some("hunter2"));
+    super::rss::get_channel_for_source(&Fetcher::new(&harness.config).unwrap(), &source)
+        .await
+        .unwrap();
+
+    let mut broken = source;
+    broken.headers = Some("{".into());
+    assert!(matches!(
+        super::rss::get_channel_for_source(&Fetcher::new(&harness.config).unwrap(), &broken).await,
+        Err(ApiError::Io(_))
+    ));
+
+    let (status, _) = harness
+        .request(
+            "PUT",
+            &format!("/sources/{id}/credentials"),
+            Some(json!({ "headers": { "bad header": "x" } })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = harness
+        .request(
+            "PUT",
+            &format!("/sources/{id}/credentials"),
+            Some(json!({})),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let source = Source::get_by_id(id, &harness.sqlite)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(
+        super::rss::get_channel_for_source(&Fetcher::new(&harness.config).unwrap(), &source)
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn export_imports_into_a_fresh_instance() {
+    let harness = Harness::new().await;
+    let (_, source) = harness.create_source("standin.rss").await;
+    let items = harness.wait_for_items(2).await;
+    let id = find(&items, "With image")["id"].as_i64().unwrap();
+    harness
+        .request("POST", &format!("/items/{id}/done"), None)
+        .await;
+
+    let (status, export) = harness.request("GET", "/export", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(export["version"], 1);
+    assert_eq!(export["sources"][0]["url"], source["url"]);
+    assert_eq!(export["items"].as_array().unwrap().len(), 2);
+
+    let fresh = Harness::new().await;
+    let (status, counts) = fresh.request("POST", "/import", Some(export.clone())).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(counts, json!({ "tags": 1, "sources": 1, "items": 2 }));
+
+    let (_, items) = fresh
+        .request(
+            "GET",
+            "/items?from_last=1d&sort=fetched&include_done=true",
+            None,
+        )
+        .await;
+    let items = items.as_array().unwrap();
+    let with_image = find(items, "With image");
+    assert_eq!(with_image["done"], true);
+    assert_eq!(with_image["tags"], json!(["rust"]));
+    assert_eq!(find(items, "Not HTML")["done"], false);
+
+    // Importing again doesn't duplicate anything
+    let (_, counts) = fresh.request("POST", "/import", Some(export)).await;
+    assert_eq!(counts, json!({ "tags": 0, "sources": 0, "items": 0 }));
+}
+
+#[tokio::test]
+async fn backups_are_sqlite_files() {
+    let harness = Harness::new().await;
+    harness.create_source("standin.rss").await;
+    harness.wait_for_items(2).await;
+
+    let req = Request::builder()
+        .uri("/backup")
+        .header("x-auth", PASSWORD)
+        .body(Body::empty())
+        .unwrap();
+    let res = harness.api.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let backup = res.into_body().collect().await.unwrap().to_bytes();
+    assert!(backup.starts_with(b"SQLite format 3\0"));
+
+    let req = Request::builder()
+        .uri("/backup")
+        .body(Body::empty())
+        .unwrap();
+    let res = harness.api.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn snapshots_are_rotated() {
+    let harness = Harness::with_config(json!({ "backups_kept": 2 })).await;
+    let mut paths = Vec::new();
+    for _ in 0..3 {
+        paths.push(
+            super::backup::snapshot(&harness.config, &harness.sqlite)
+                .await
+                .unwrap(),
+        );
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+
+    assert!(!paths[0].exists());
+    assert!(paths[1].exists() && paths[2].exists());
+}
+
+#[tokio::test]
+async fn updated_at_triggers_touch_their_own_rows() {
+    let harness = Harness::new().await;
+    let (_, source) = harness.create_source("standin.rss").await;
+    let items = harness.wait_for_items(2).await;
+    let id = find(&items, "With image")["id"].as_i64().unwrap();
+    let long_ago = "2000-01-01 00:00:00";
+
+    // Setting updated_at explicitly keeps that value
+    for table in ["tags", "items", "sources"] {
+        sqlx::query(&format!("UPDATE {table} SET updated_at = ?"))
+            .bind(long_ago)
+            .execute(&harness.sqlite)
+            .await
+            .unwrap();
+    }
+
+    sqlx::query("UPDATE items SET title = 'Changed' WHERE id = ?")
+        .bind(id)
+        .execute(&harness.sqlite)
+        .await
+        .unwrap();
+    sqlx::query("UPDATE sources SET name = 'Changed' WHERE id = ?")
+        .bind(source["id"].as_i64())
+        .execute(&harness.sqlite)
+        .await
+        .unwrap();
+
+    let touched = |sql: &'static str| async {
+        sqlx::query_scalar::<_, String>(sql)
+            .fetch_all(&harness.sqlite)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|updated_at| updated_at != long_ago)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        touched("SELECT updated_at FROM items ORDER BY title = 'Changed' DESC").await,
+        [true, false]
+    );
+    assert_eq!(touched("SELECT updated_at FROM sources").await, [true]);
+    assert_eq!(touched("SELECT updated_at FROM tags").await, [false]);
+}
+
+#[tokio::test]
+async fn tag_ids_are_migrated_to_text() {
+    let dir = TempDir::new().unwrap();
+    let options = SqliteConnectOptions::new()
+        .filename(dir.path().join("db"))
+        .create_if_missing(true)
+        .pragma("foreign_keys", "on");
+    let sqlite = SqlitePool::connect_with(options).await.unwrap();
+
+    let mut before = sqlx::migrate!();
+    before.migrations = before
+        .migrations
+        .iter()
+        .filter(|migration| migration.version < 12)
+        .cloned()
+        .collect::<Vec<_>>()
+        .into();
+    before.run(&sqlite).await.unwrap();
+    sqlx::raw_sql(
+        r#"
+        INSERT INTO tags (name) VALUES ('2024'), ('rust');
+        INSERT INTO sources (id, name, url, last_pub) VALUES (1, 'source', 'url', CURRENT_TIMESTAMP);
+        INSERT INTO items (id, link, source_id) VALUES (1, 'link', 1);
+        INSERT INTO items_to_tags (item_id, tag_id) VALUES (1, '2024'), (1, 'rust');
+        INSERT INTO sources_to_tags (source_id, tag_id) VALUES (1, '2024');
+        "#,
+    )
+    .execute(&sqlite)
+    .await
+    .unwrap();
+
+    db::migrate(&sqlite).await.unwrap();
+
+    let item_tags = sqlx::query_as::<_, (String, String)>(
+        "SELECT typeof(tag_id), tag_id FROM items_to_tags ORDER BY tag_id",
+    )
+    .fetch_all(&sqlite)
+    .await
+    .unwrap();
+    assert_eq!(
+        item_tags,
+        [
+            ("text".into(), "2024".into()),
+            ("text".into(), "rust".into())
+        ]
+    );
+    let source_tags =
+        sqlx::query_as::<_, (String, String)>("SELECT typeof(tag_id), tag_id FROM sources_to_tags")
+            .fetch_all(&sqlite)
+            .await
+            .unwrap();
+    assert_eq!(source_tags, [("text".into(), "2024".into())]);
+
+    // Numeric names now match the tag they belong to
+    let tags = Source::tags(1, &sqlite).await.unwrap();
+    assert_eq!(tags[0].name, "2024");
+}
+
+#[tokio::test]
+async fn rebuilding_items_keeps_what_references_them() {
+    let dir = TempDir::new().unwrap();
+    let options = SqliteConnectOptions::new()
+        .filename(dir.path().join("db"))
+        .create_if_missing(true)
+        .pragma("foreign_keys", "on");
+    let sqlite = SqlitePool::connect_with(options).await.unwrap();
+
+    let mut before = sqlx::migrate!();
+    before.migrations = before
+        .migrations
+        .iter()
+        .filter(|migration| migration.version < 19)
+        .cloned()
+        .collect::<Vec<_>>()
+        .into();
+    before.run(&sqlite).await.unwrap();
+    sqlx::raw_sql(
+        r#"
+        INSERT INTO tags (name) VALUES ('rust');
+        INSERT INTO sources (id, name, url, last_pub) VALUES (1, 'source', 'url', CURRENT_TIMESTAMP);
+        INSERT INTO items (id, link, source_id, guid) VALUES (1, 'link', 1, 'first');
+        INSERT INTO items_to_tags (item_id, tag_id) VALUES (1, 'rust');
+        INSERT INTO user_items (user_id, item_id, favorite) VALUES (1, 1, TRUE);
+        INSERT INTO read_later (user_id, item_id, position) VALUES (1, 1, 0);
+        "#,
+    )
+    .execute(&sqlite)
+    .await
+    .unwrap();
+
+    db::migrate(&sqlite).await.unwrap();
+
+    let kept = sqlx::query_as::<_, (i64, i64, i64)>(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM items_to_tags),
+            (SELECT COUNT(*) FROM user_items WHERE favorite),
+            (SELECT COUNT(*) FROM read_later)
+        "#,
+    )
+    .fetch_one(&sqlite)
+    .await
+    .unwrap();
+    assert_eq!(kept, (1, 1, 1));
+    // Foreign keys are back on
+    let deleted = sqlx::query("DELETE FROM tags WHERE name = 'rust'")
+        .execute(&sqlite)
+        .await;
+    assert!(deleted.is_ok());
+    let tagged = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM items_to_tags")
+        .fetch_one(&sqlite)
+        .await
+        .unwrap();
+    assert_eq!(tagged, 0);
+    sqlx::query("INSERT INTO items (link, source_id, guid) VALUES ('link', 1, 'second')")
+        .execute(&sqlite)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn deleting_a_source_keeps_favorited_items() {
+    let harness = Harness::new().await;
+    let (_, source) = harness.create_source("standin.rss").await;
+    let items = harness.wait_for_items(2).await;
+    let favorite = find(&items, "With image")["id"].as_i64().unwrap();
+    let other = find(&items, "Not HTML")["id"].as_i64().unwrap();
+    harness
+        .request("POST", &format!("/items/{favorite}/favorite"), None)
+        .await;
+
+    let (status, _) = harness
+        .request("DELETE", &format!("/sources/{}", source["id"]), None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let kept = Item::get_by_id(favorite, &harness.sqlite)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(kept.source_id, None);
+    assert!(Item::get_by_id(other, &harness.sqlite)
+        .await
+        .unwrap()
+        .is_none());
+    let (status, _) = harness
+        .request("GET", &format!("/items/{favorite}"), None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let tombstones = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM tombstones")
+        .fetch_one(&harness.sqlite)
+        .await
+        .unwrap();
+    assert_eq!(tombstones, 0);
+}
+
+#[tokio::test]
+async fn deleting_a_source_merges_kept_items_linking_the_same_page() {
+    let harness = Harness::new().await;
+    let (_, source) = harness.create_source("standin.rss").await;
+    let items = harness.wait_for_items(2).await;
+    let with_image = find(&items, "With image")["link"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let source = Source::get_by_id(source["id"].as_i64().unwrap(), &harness.sqlite)
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Two posts on one page, and a page someone also added by hand
+    let fetcher = Fetcher::new(&harness.config).unwrap();
+    let ingest = Ingest {
+        fetcher: &fetcher,
+        sqlite: &harness.sqlite,
+        deliver_send: &tokio::sync::mpsc::channel(1).0,
+        category_tags: harness.config.category_tags,
+    };
+    let channel =
+        super::rss::get_channel_for_source(&Fetcher::new(&harness.config).unwrap(), &source)
+            .await
+            .unwrap();
+    let shared = format!("{}/articles/with-image.html?shared", harness.base);
+    for guid in ["first", "second"] {
+        let mut channel = channel.clone();
+        channel.items.truncate(1);
+        channel.items[0].link = Some(shared.clone());
+        channel.items[0].guid = Some(::rss::Guid {
+            value: guid.into(),
+            permalink: false,
+        });
+        ingest.run(&source, channel, Mode::Commit).await.unwrap();
+    }
+    let (status, by_hand) = harness
+        .request(
+            "POST",
+            "/items",
+            Some(json!({ "id": 0, "link": with_image })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let favorites = sqlx::query_scalar!(
+        "SELECT id FROM items WHERE source_id = ?1 AND (link = ?2 OR link = ?3)",
+        source.id,
+        shared,
+        with_image
+    )
+    .fetch_all(&harness.sqlite)
+    .await
+    .unwrap();
+    assert_eq!(favorites.len(), 3);
+    for id in &favorites {
+        harness
+            .request("POST", &format!("/items/{id}/favorite"), None)
+            .await;
+    }
+
+    // Someone else leaving doesn't delete it for the rest
+    let other = harness.create_user("other").await;
+    let other = ("x-auth", other.as_str());
+    let subscribe =
+        json!({ "id": 0, "name": "standin", "url": source.url, "lastPoll": null, "ttl": null });
+    let (status, _) = harness
+        .request_as(other, "POST", "/sources", Some(subscribe))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = harness
+        .request_as(other, "DELETE", &format!("/sources/{}", source.id), None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, sources) = harness.request_as(other, "GET", "/sources", None).await;
+    assert_eq!(sources, json!([]));
+    assert!(Source::get_by_id(source.id, &harness.sqlite)
+        .await
+        .unwrap()
+        .is_some());
+
+    let (status, _) = harness
+        .request("DELETE", &format!("/sources/{}", source.id), None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(Source::get_by_id(source.id, &harness.sqlite)
+        .await
+        .unwrap()
+        .is_none());
+
+    let kept = sqlx::query_as::<_, (i64, String)>("SELECT id, link FROM items")
+        .fetch_all(&harness.sqlite)
+        .await
+        .unwrap();
+    assert_eq!(kept.len(), 2);
+    for (id, link) in kept {
+        if link != shared {
+            assert_eq!(id, by_hand["id"].as_i64().unwrap());
+        }
+        let (_, item) = harness.request("GET", &format!("/items/{id}"), None).await;
+        assert_eq!(item["favorite"], true, "{link}");
+        assert_eq!(item["tags"], json!(["rust"]), "{link}");
+    }
+}
+
+#[tokio::test]
+async fn deleting_a_source_can_delete_all_its_items() {
+    let harness = Harness::with_config(json!({ "source_items_on_delete": "delete" })).await;
+    let (_, source) = harness.create_source("standin.rss").await;
+    let items = harness.wait_for_items(2).await;
+    let favorite = find(&items, "With image")["id"].as_i64().unwrap();
+    harness
+        .request("POST", &format!("/items/{favorite}/favorite"), None)
+        .await;
+
+    harness
+        .request("DELETE", &format!("/sources/{}", source["id"]), None)
+        .await;
+    let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM items")
+        .fetch_one(&harness.sqlite)
+        .await
+        .unwrap();
+    assert_eq!(count, 0);
+}
+
+#[tokio::test]
+async fn renamed_tags_keep_their_items_and_catch_new_categories() {
+    let harness = Harness::new().await;
+    let (_, source) = harness.create_source("standin.rss").await;
+    harness.wait_for_items(2).await;
+
+    let (status, tag) = harness
+        .request(
+            "POST",
+            "/tags/rust/rename",
+            Some(json!({ "name": "rust-lang" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(tag["name"], "rust-lang");
+    let (_, items) = harness
+        .request("GET", "/items?from_last=1d&sort=fetched", None)
+        .await;
+    assert_eq!(
+        find(items.as_array().unwrap(), "With image")["tags"],
+        json!(["rust-lang"])
+    );
+    let (_, aliases) = harness.request("GET", "/tags/aliases", None).await;
+    assert_eq!(aliases[0]["alias"], "rust");
+    assert_eq!(aliases[0]["tag_id"], "rust-lang");
+
+    // New items with the old category get the new tag instead of bringing the old one back
+    let source = Source::get_by_id(source["id"].as_i64().unwrap(), &harness.sqlite)
+        .await
+        .unwrap()
+        .unwrap();
+    let mut channel =
+        super::rss::get_channel_for_source(&Fetcher::new(&harness.config).unwrap(), &source)
+            .await
+            .unwrap();
+    channel.items[0].link = Some(format!("{}/articles/with-image.html?new", harness.base));
+    channel.items[0].title = Some("New".into());
+    let fetcher = Fetcher::new(&harness.config).unwrap();
+    let ingest = Ingest {
+        fetcher: &fetcher,
+        sqlite: &harness.sqlite,
+        deliver_send: &tokio::sync::mpsc::channel(1).0,
+        category_tags: harness.config.category_tags,
+    };
+    let inserted = ingest.run(&source, channel, Mode::Commit).await.unwrap();
+    assert_eq!(inserted.len(), 1);
+    assert_eq!(
+        inserted[0]
+            .tags
+            .iter()
+            .map(|tag| &**tag)
+            .collect::<Vec<_>>(),
+        ["rust-lang"]
+    );
+    let (status, _) = harness.request("GET", "/tags/rust", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = harness
+        .request(
+            "POST",
+            "/tags/rust-lang/rename",
+            Some(json!({ "name": "rust-lang" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn merged_tags_move_onto_one_tag() {
+    let harness = Harness::new().await;
+    harness.create_source("standin.rss").await;
+    let items = harness.wait_for_items(2).await;
+    let id = find(&items, "Not HTML")["id"].as_i64().unwrap();
+
+    for name in ["rustlang", "rs"] {
+        harness
+            .request("POST", "/tags", Some(json!({ "name": name })))
+            .await;
+    }
+    harness
+        .request(
+            "POST",
+            &format!("/items/{id}/tags"),
+            Some(json!(["rustlang"])),
+        )
+        .await;
+
+    // Nothing is merged unless every tag exists
+    for (tags, into, expected) in [
+        (json!([]), "rust", StatusCode::BAD_REQUEST),
+        (json!(["rustlang", "typo"]), "rust", StatusCode::BAD_REQUEST),
+        (json!(["rustlang"]), "typo", StatusCode::NOT_FOUND),
+    ] {
+        let (status, _) = harness
+            .request(
+                "POST",
+                "/tags/merge",
+                Some(json!({ "tags": tags, "into": into })),
+            )
+            .await;
+        assert_eq!(status, expected, "{tags} into {into}");
+    }
+    let (_, tags) = harness.request("GET", "/tags", None).await;
+    assert_eq!(tags.as_array().unwrap().len(), 3);
+
+    let (status, _) = harness
+        .request(
+            "POST",
+            "/tags/merge",
+            Some(json!({ "tags": ["rustlang", "rs", "rs"], "into": "rust" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, tags) = harness.request("GET", "/tags", None).await;
+    let names = tags
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|tag| tag["name"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["rust"]);
+    let (_, item) = harness.request("GET", &format!("/items/{id}"), None).await;
+    assert_eq!(item["tags"], json!(["rust"]));
+
+    let (_, aliases) = harness.request("GET", "/tags/aliases", None).await;
+    assert_eq!(aliases.as_array().unwrap().len(), 2);
+    let (status, _) = harness.request("DELETE", "/tags/aliases/rs", None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, aliases) = harness.request("GET", "/tags/aliases", None).await;
+    assert_eq!(aliases[0]["alias"], "rustlang");
+}
+
+#[tokio::test]
+async fn smart_feeds_follow_renamed_and_merged_tags() {
+    let harness = Harness::new().await;
+    harness.create_source("standin.rss").await;
+    harness.wait_for_items(2).await;
+    harness
+        .request("POST", "/tags", Some(json!({ "name": "lang" })))
+        .await;
+
+    let (_, smart_feed) = harness
+        .request(
+            "POST",
+            "/smart-feeds",
+            Some(json!({
+                "name": "Rust",
+                "query": { "all_tags": ["rust"], "any_tags": ["lang", "rust"] },
+            })),
+        )
+        .await;
+    let id = smart_feed["id"].as_i64().unwrap();
+
+    let (status, _) = harness
+        .request(
+            "POST",
+            "/tags/rust/rename",
+            Some(json!({ "name": "rust-lang" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, smart_feed) = harness
+        .request("GET", &format!("/smart-feeds/{id}"), None)
+        .await;
+    assert_eq!(smart_feed["query"]["all_tags"], json!(["rust-lang"]));
+    assert_eq!(
+        smart_feed["query"]["any_tags"],
+        json!(["lang", "rust-lang"])
+    );
+    let (_, items) = harness
+        .request("GET", &format!("/smart-feeds/{id}/items"), None)
+        .await;
+    assert_eq!(items[0]["title"], "With image");
+
+    // Merging into a tag the filter already has doesn't list it twice
+    harness
+        .request(
+            "POST",
+            "/tags/merge",
+            Some(json!({ "tags": ["lang"], "into": "rust-lang" })),
+        )
+        .await;
+    let (_, smart_feed) = harness
+        .request("GET", &format!("/smart-feeds/{id}"), None)
+        .await;
+    assert_eq!(smart_feed["query"]["any_tags"], json!(["rust-lang"]));
+}
+
+#[tokio::test]
+async fn sources_can_limit_which_categories_become_tags() {
+    let harness = Harness::new().await;
+    let (_, source) = harness.create_source("standin.rss").await;
+    harness.wait_for_items(2).await;
+    let id = source["id"].as_i64().unwrap();
+
+    let ingest_with_categories = |link: &'static str| {
+        let harness = &harness;
+        async move {
+            let source = Source::get_by_id(id, &harness.sqlite)
+                .await
+                .unwrap()
+                .unwrap();
+            let mut channel = super::rss::get_channel_for_source(
+                &Fetcher::new(&harness.config).unwrap(),
+                &source,
+            )
+            .await
+            .unwrap();
+            channel.items[0].link =
+                Some(format!("{}/articles/with-image.html?{link}", harness.base));
+            channel.items[0].categories.push(rss::Category {
+                name: "Brand New".into(),
+                domain: None,
+            });
+            let fetcher = Fetcher::new(&harness.config).unwrap();
+            let ingest = Ingest {
+                fetcher: &fetcher,
+                sqlite: &harness.sqlite,
+                deliver_send: &tokio::sync::mpsc::channel(1).0,
+                category_tags: harness.config.category_tags,
+            };
+            let inserted = ingest.run(&source, channel, Mode::Commit).await.unwrap();
+            let mut tags = inserted[0]
+                .tags
+                .iter()
+                .map(|tag| tag.to_string())
+                .collect::<Vec<_>>();
+            tags.sort();
+            tags
+        }
+    };
+
+    let (status, _) = harness
+        .request(
+            "PUT",
+            &format!("/sources/{id}/category-tags"),
+            Some(json!({ "categoryTags": "existing" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(ingest_with_categories("existing").await, ["rust"]);
+    let (status, _) = harness.request("GET", "/tags/brand%20new", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    harness
+        .request(
+            "PUT",
+            &format!("/sources/{id}/category-tags"),
+            Some(json!({ "categoryTags": "ignore" })),
+        )
+        .await;
+    assert!(ingest_with_categories("ignore").await.is_empty());
+
+    // Back to the configured default, which creates every category
+    harness
+        .request(
+            "PUT",
+            &format!("/sources/{id}/category-tags"),
+            Some(json!({ "categoryTags": null })),
+        )
+        .await;
+    assert_eq!(ingest_with_categories("all").await, ["brand new", "rust"]);
+
+    let (status, _) = harness
+        .request(
+            "PUT",
+            &format!("/sources/{id}/category-tags"),
+            Some(json!({ "categoryTags": "some" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn unused_tags_are_listed_and_pruned() {
+    let harness = Harness::new().await;
+    harness.create_source("standin.rss").await;
+    harness.wait_for_items(2).await;
+
+    for name in ["lonely", "aliased", "parent"] {
+        harness
+            .request("POST", "/tags", Some(json!({ "name": name })))
+            .await;
+    }
+    harness
+        .request(
+            "POST",
+            "/tags/aliases",
+            Some(json!({ "alias": "alias", "tag": "aliased" })),
+        )
+        .await;
+    // Only used to group the tags under it
+    harness
+        .request(
+            "POST",
+            "/tags",
+            Some(json!({ "name": "child", "parent": "parent" })),
+        )
+        .await;
+
+    let (status, usage) = harness.request("GET", "/tags/usage", None).await;
+    assert_eq!(status, StatusCode::OK);
+    let usage = usage
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|tag| {
+            (
+                tag["name"].as_str().unwrap(),
+                tag["items"].as_i64().unwrap(),
+                tag["aliased"].as_bool().unwrap(),
+                tag["children"].as_i64().unwrap(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        usage,
+        [
+            ("aliased", 0, true, 0),
+            ("child", 0, false, 0),
+            ("lonely", 0, false, 0),
+            ("parent", 0, false, 1),
+            ("rust", 1, false, 0)
+        ]
+    );
+
+    // Nothing is old enough yet
+    let (_, pruned) = harness
+        .request("POST", "/tags/prune", Some(json!({ "older_than": "1h" })))
+        .await;
+    assert_eq!(pruned, json!([]));
+
+    let (status, pruned) = harness
+        .request("POST", "/tags/prune", Some(json!({})))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(pruned, json!(["child", "lonely"]));
+
+    // With nothing under it anymore the parent goes too
+    let (_, pruned) = harness
+        .request("POST", "/tags/prune", Some(json!({ "max_items": 1 })))
+        .await;
+    assert_eq!(pruned, json!(["parent", "rust"]));
+}
+
+#[tokio::test]
+async fn filtering_by_a_tag_includes_tags_nested_under_it() {
+    let harness = Harness::new().await;
+    harness.create_source("standin.rss").await;
+    let items = harness.wait_for_items(2).await;
+    let plain = find(&items, "Not HTML")["id"].as_i64().unwrap();
+
+    for (name, parent) in [("work", None), ("infra", Some("work")), ("personal", None)] {
+        let (status, _) = harness
+            .request(
+                "POST",
+                "/tags",
+                Some(json!({ "name": name, "parent": parent })),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let (status, _) = harness
+        .request(
+            "PUT",
+            "/tags",
+            Some(json!({ "name": "rust", "parent": "work" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    harness
+        .request(
+            "POST",
+            &format!("/items/{plain}/tags"),
+            Some(json!(["infra"])),
+        )
+        .await;
+
+    let titles = |tags: &'static str| {
+        let harness = &harness;
+        async move {
+            let (_, items) = harness
+                .request(
+                    "GET",
+                    &format!("/items?from_last=1d&sort=fetched&tags={tags}"),
+                    None,
+                )
+                .await;
+            let mut titles = items
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| item["title"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>();
+            titles.sort();
+            titles
+        }
+    };
+    assert_eq!(titles("work").await, ["Not HTML", "With image"]);
+    assert_eq!(titles("infra").await, ["Not HTML"]);
+    assert_eq!(titles("work,rust").await, ["With image"]);
+    assert!(titles("personal").await.is_empty());
+
+    let (_, tree) = harness.request("GET", "/tags?tree=true", None).await;
+    let outline = |nodes: &Value| {
+        nodes
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|node| node["name"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(outline(&tree), ["personal", "work"]);
+    assert_eq!(outline(&tree[1]["children"]), ["infra", "rust"]);
+    assert_eq!(tree[1]["children"][1]["parent"], "work");
+
+    // A tag can't end up under itself
+    let (status, _) = harness
+        .request(
+            "PUT",
+            "/tags",
+            Some(json!({ "name": "work", "parent": "rust" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Children of a deleted tag move to the top level
+    harness.request("DELETE", "/tags/work", None).await;
+    let (_, tree) = harness.request("GET", "/tags?tree=true", None).await;
+    assert_eq!(outline(&tree), ["infra", "personal", "rust"]);
+}
+
+#[tokio::test]
+async fn smart_feeds_combine_their_filters() {
+    let harness = Harness::new().await;
+    let (_, source) = harness.create_source("standin.rss").await;
+    let items = harness.wait_for_items(2).await;
+    let with_image = find(&items, "With image")["id"].as_i64().unwrap();
+
+    let titles = |id: i64| {
+        let harness = &harness;
+        async move {
+            let (status, items) = harness
+                .request("GET", &format!("/smart-feeds/{id}/items"), None)
+                .await;
+            assert_eq!(status, StatusCode::OK);
+            items
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| item["title"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        }
+    };
+
+    let (status, rust) = harness
+        .request(
+            "POST",
+            "/smart-feeds",
+            Some(json!({
+                "name": "Rust",
+                "query": {
+                    "any_tags": ["rust", "wasm"],
+                    "done": false,
+                    "max_age": "3days",
+                    "sort": "fetched",
+                },
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let rust = rust["id"].as_i64().unwrap();
+    assert_eq!(titles(rust).await, ["With image"]);
+
+    let (_, other) = harness
+        .request(
+            "POST",
+            "/smart-feeds",
+            Some(json!({
+                "name": "Other",
+                "query": { "none_tags": ["rust"], "text": "isn't html" },
+            })),
+        )
+        .await;
+    let other = other["id"].as_i64().unwrap();
+    assert_eq!(titles(other).await, ["Not HTML"]);
+
+    harness
+        .request("POST", &format!("/items/{with_image}/done"), None)
+        .await;
+    assert!(titles(rust).await.is_empty());
+
+    // Names are unique per user
+    let (status, _) = harness
+        .request(
+            "POST",
+            "/smart-feeds",
+            Some(json!({ "name": "Rust", "query": {} })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, updated) = harness
+        .request(
+            "PUT",
+            &format!("/smart-feeds/{other}"),
+            Some(json!({
+                "name": "From the stand-in",
+                "query": { "source_ids": [source["id"]], "done": true },
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(updated["query"]["source_ids"], json!([source["id"]]));
+    assert_eq!(titles(other).await, ["With image"]);
+
+    let (_, smart_feeds) = harness.request("GET", "/smart-feeds", None).await;
+    let names = smart_feeds
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|smart_feed| smart_feed["name"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["From the stand-in", "Rust"]);
+
+    harness
+        .request("DELETE", &format!("/smart-feeds/{rust}"), None)
+        .await;
+    let (status, _) = harness
+        .request("GET", &format!("/smart-feeds/{rust}/items"), None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn stats_count_items_per_source_tag_and_day() {
+    let harness = Harness::new().await;
+    let (_, source) = harness.create_source("standin.rss").await;
+    let items = harness.wait_for_items(2).await;
+    let with_image = find(&items, "With image")["id"].as_i64().unwrap();
+    let plain = find(&items, "Not HTML")["id"].as_i64().unwrap();
+    harness
+        .request("POST", &format!("/items/{with_image}/done"), None)
+        .await;
+    harness
+        .request("POST", &format!("/items/{plain}/favorite"), None)
+        .await;
+
+    let (status, stats) = harness.request("GET", "/stats?days=3", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        stats["sources"],
+        json!([{
+            "source_id": source["id"],
+            "name": source["name"],
+            "total": 2,
+            "unread": 1,
+            "favorite": 1,
+            // The two items were published a day apart
+            "posts_per_day": 1.0,
+        }])
+    );
+    assert_eq!(
+        stats["tags"],
+        json!([{ "tag": "rust", "total": 1, "unread": 0, "favorite": 0 }])
+    );
+
+    let per_day = stats["per_day"].as_array().unwrap();
+    assert_eq!(per_day.len(), 3);
+    assert_eq!(
+        per_day
+            .iter()
+            .map(|day| day["items"].as_i64().unwrap())
+            .collect::<Vec<_>>(),
+        [0, 0, 2]
+    );
+    assert_eq!(
+        per_day[2]["day"],
+        chrono::Utc::now()
+            .date_naive()
+            .format("%Y-%m-%d")
+            .to_string()
+    );
+
+    // Archived items and other users' items don't count
+    sqlx::query("UPDATE items SET archived_at = CURRENT_TIMESTAMP WHERE id = ?")
+        .bind(plain)
+        .execute(&harness.sqlite)
+        .await
+        .unwrap();
+    let (_, stats) = harness.request("GET", "/stats?days=1", None).await;
+    assert_eq!(stats["sources"][0]["total"], 1);
+    assert_eq!(
+        stats["per_day"],
+        json!([{ "day": per_day[2]["day"], "items": 1 }])
+    );
+    let other = harness.create_user("other").await;
+    let (_, stats) = harness
+        .request_as(("x-auth", &other), "GET", "/stats?days=1", None)
+        .await;
+    assert_eq!(stats["sources"], json!([]));
+    assert_eq!(stats["tags"], json!([]));
+    assert_eq!(stats["per_day"][0]["items"], 0);
+}
+
+#[tokio::test]
+async fn read_later_queue_keeps_order_and_progress() {
+    let harness = Harness::new().await;
+    harness.create_source("standin.rss").await;
+    let items = harness.wait_for_items(2).await;
+    let plain = find(&items, "Not HTML")["id"].as_i64().unwrap();
+
+    let (status, queued) = harness
+        .request("POST", "/read-later", Some(json!({ "item_id": plain })))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    // The page isn't HTML so the description is counted instead
+    assert_eq!(queued["read_later"]["word_count"], 7);
+    assert_eq!(queued["reading_minutes"], 1);
+
+    // Links are added as items, like they are by `POST /items`
+    let link = format!("{}/articles/with-image.html?later", harness.base);
+    let (status, added) = harness
+        .request(
+            "POST",
+            "/read-later",
+            Some(json!({ "link": link, "title": "Later" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let later = added["id"].as_i64().unwrap();
+    assert_eq!(added["title"], "Later");
+    assert_eq!(added["read_later"]["word_count"], 5);
+
+    let queue_ids = |queue: Value| {
+        queue
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["id"].as_i64().unwrap())
+            .collect::<Vec<_>>()
+    };
+    let (_, queue) = harness.request("GET", "/read-later", None).await;
+    assert_eq!(queue_ids(queue), [plain, later]);
+
+    let (status, _) = harness
+        .request("PUT", "/read-later/order", Some(json!([later])))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, queue) = harness.request("GET", "/read-later", None).await;
+    assert_eq!(queue_ids(queue), [later, plain]);
+
+    let progress = format!("/read-later/{later}");
+    let (status, _) = harness
+        .request("PUT", &progress, Some(json!({ "progress": 101 })))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (_, halfway) = harness
+        .request("PUT", &progress, Some(json!({ "progress": 50 })))
+        .await;
+    assert_eq!(halfway["read_later"]["progress"], 50);
+    assert_eq!(halfway["read_later"]["archived_at"], Value::Null);
+
+    // Finishing archives the item and marks it done
+    let (_, finished) = harness
+        .request("PUT", &progress, Some(json!({ "progress": 100 })))
+        .await;
+    assert_ne!(finished["read_later"]["archived_at"], Value::Null);
+    assert_eq!(finished["done"], true);
+    let (_, queue) = harness.request("GET", "/read-later", None).await;
+    assert_eq!(queue_ids(queue), [plain]);
+    let (_, archived) = harness
+        .request("GET", "/read-later?archived=true", None)
+        .await;
+    assert_eq!(queue_ids(archived), [later]);
+
+    // Scrolling back up doesn't slip it into the queue at its old position
+    let (_, reread) = harness
+        .request("PUT", &progress, Some(json!({ "progress": 20 })))
+        .await;
+    assert_eq!(reread["read_later"]["progress"], 20);
+    assert_eq!(
+        reread["read_later"]["archived_at"],
+        finished["read_later"]["archived_at"]
+    );
+    let (_, queue) = harness.request("GET", "/read-later", None).await;
+    assert_eq!(queue_ids(queue), [plain]);
+
+    let (status, _) = harness
+        .request("DELETE", &format!("/read-later/{plain}"), None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, queue) = harness.request("GET", "/read-later", None).await;
+    assert_eq!(queue_ids(queue), Vec::<i64>::new());
+}
+
+#[tokio::test]
+async fn users_cant_touch_items_from_sources_they_dont_follow() {
+    let harness = Harness::new().await;
+    let (_, source) = harness.create_source("standin.rss").await;
+    let items = harness.wait_for_items(2).await;
+    let id = find(&items, "With image")["id"].as_i64().unwrap();
+    let other = harness.create_user("other").await;
+    let other = ("x-auth", other.as_str());
+
+    for (method, uri) in [
+        ("GET", format!("/items/{id}")),
+        ("POST", format!("/items/{id}/done")),
+        ("POST", format!("/items/{id}/favorite")),
+        ("DELETE", format!("/items/{id}/favorite")),
+        ("DELETE", format!("/items/{id}/tags/rust")),
+        // Items that don't exist aren't a database error either
+        ("DELETE", "/items/9999/favorite".into()),
+        ("DELETE", "/items/9999/tags/rust".into()),
+    ] {
+        let (status, _) = harness.request_as(other, method, &uri, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{method} {uri}");
+    }
+    let (_, feed) = harness
+        .request_as(other, "GET", "/items?from_last=1d", None)
+        .await;
+    assert_eq!(feed, json!([]));
+
+    // Nor can they post into the source
+    let (status, forged) = harness
+        .request_as(
+            other,
+            "POST",
+            "/items",
+            Some(json!({
+                "id": 0,
+                "link": format!("{}/articles/forged.html", harness.base),
+                "title": "Forged",
+                "source_id": source["id"],
+                "guid": "forged",
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(forged["source_id"], Value::Null);
+    assert_eq!(forged["guid"], Value::Null);
+    let (_, feed) = harness
+        .request("GET", "/items?from_last=1d&sort=fetched", None)
+        .await;
+    assert!(feed
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|item| item["title"] != "Forged"));
+
+    // Nothing the other user tried changed the owner's copy
+    let (_, item) = harness.request("GET", &format!("/items/{id}"), None).await;
+    assert_eq!(item["done"], false);
+    assert_eq!(item["tags"], json!(["rust"]));
+}
+
+#[tokio::test]
+async fn tokens_only_do_what_their_scopes_allow() {
+    let harness = Harness::new().await;
+    harness.create_source("standin.rss").await;
+    let items = harness.wait_for_items(2).await;
+    let id = find(&items, "With image")["id"].as_i64().unwrap();
+
+    let (status, _) = harness
+        .request(
+            "POST",
+            "/tokens",
+            Some(json!({ "name": "admin", "scopes": ["account"] })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (_, reader) = harness
+        .request(
+            "POST",
+            "/tokens",
+            Some(json!({ "name": "reader", "scopes": ["read"] })),
+        )
+        .await;
+    assert_eq!(reader["last_used_at"], Value::Null);
+    let reader = format!("Bearer {}", reader["token"].as_str().unwrap());
+    let reader = ("authorization", reader.as_str());
+    let (_, writer) = harness
+        .request(
+            "POST",
+            "/tokens",
+            Some(json!({ "name": "writer", "scopes": ["items:write"] })),
+        )
+        .await;
+    let writer = format!("Bearer {}", writer["token"].as_str().unwrap());
+    let writer = ("authorization", writer.as_str());
+
+    let (status, feed) = harness
+        .request_as(reader, "GET", "/items?from_last=1d&sort=fetched", None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(feed.as_array().unwrap().len(), 2);
+    let done = format!("/items/{id}/done");
+    let (status, _) = harness.request_as(reader, "POST", &done, None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = harness.request_as(writer, "POST", &done, None).await;
+    assert_eq!(status, StatusCode::OK);
+    // Tokens never manage the account, whatever their scopes
+    let (status, _) = harness.request_as(writer, "GET", "/tokens", None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = harness
+        .request_as(
+            ("authorization", "Bearer mf_nope"),
+            "GET",
+            "/items?from_last=1d",
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (_, tokens) = harness.request("GET", "/tokens", None).await;
+    assert!(tokens
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|token| token["last_used_at"] != Value::Null && token.get("token_hash").is_none()));
+}
+
+#[tokio::test]
+async fn webhooks_are_signed_and_failures_retried() {
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+
+    let harness = Harness::new().await;
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let receiver = format!("http://{}", listener.local_addr().unwrap());
+    let (received_send, mut received) = tokio::sync::mpsc::unbounded_channel();
+    let router = Router::new()
+        .route(
+            "/hook",
+            axum::routing::post(async move |headers: HeaderMap, body: axum::body::Bytes| {
+                received_send.send((headers, body)).unwrap();
+            }),
+        )
+        .route(
+            "/fail",
+            axum::routing::post(async || StatusCode::INTERNAL_SERVER_ERROR),
+        );
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+    let (status, signed) = harness
+        .request(
+            "POST",
+            "/webhooks",
+            Some(json!({ "url": format!("{receiver}/hook"), "secret": "s3cret" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, failing) = harness
+        .request(
+            "POST",
+            "/webhooks",
+            Some(json!({ "url": format!("{receiver}/fail"), "tags": "rust" })),
+        )
+        .await;
+    harness.create_source("standin.rss").await;
+
+    let mut titles = Vec::new();
+    for _ in 0..2 {
+        let (headers, body) = tokio::time::timeout(Duration::from_secs(5), received.recv())
+            .await
+            .expect("webhook was never delivered")
+            .unwrap();
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"s3cret").unwrap();
+        mac.update(&body);
+        let signature = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+        assert_eq!(headers["x-my-feed-signature"], signature.as_str());
+        assert!(headers.contains_key("x-my-feed-delivery"));
+        let item = serde_json::from_slice::<Value>(&body).unwrap();
+        titles.push(item["title"].as_str().unwrap().to_string());
+    }
+    titles.sort();
+    assert_eq!(titles, ["Not HTML", "With image"]);
+
+    for _ in 0..100 {
+        let (_, deliveries) = harness.request("GET", "/webhooks/deliveries", None).await;
+        let deliveries = deliveries.as_array().unwrap();
+        if deliveries.len() == 3 && deliveries.iter().all(|d| d["attempts"] == 1) {
+            for delivery in deliveries {
+                if delivery["webhook_id"] == signed["id"] {
+                    assert_eq!(delivery["status_code"], 200);
+                    assert_ne!(delivery["delivered_at"], Value::Null);
+                } else {
+                    // Only the item tagged rust matched, and it's retried later
+                    assert_eq!(delivery["webhook_id"], failing["id"]);
+                    assert_eq!(delivery["status_code"], 500);
+                    assert_eq!(delivery["delivered_at"], Value::Null);
+                    assert_ne!(delivery["next_attempt_at"], Value::Null);
+                }
+            }
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("deliveries were never attempted");
+}
+
+#[tokio::test]
+async fn webhooks_cant_point_at_private_addresses() {
+    let harness = Harness::with_config(json!({ "allow_private_addresses": false })).await;
+    for url in [
+        "http://127.0.0.1:8080/hook",
+        "http://[::1]/hook",
+        "file:///etc/passwd",
+    ] {
+        let (status, _) = harness
+            .request("POST", "/webhooks", Some(json!({ "url": url })))
+            .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{url}");
+    }
+    let (status, _) = harness
+        .request(
+            "POST",
+            "/webhooks",
+            Some(json!({ "url": "https://example.com/hook" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn hubs_are_asked_once_until_they_verify() {
+    let harness = Harness::new().await;
+    let (_, source) = harness.create_source("standin.rss").await;
+    let id = source["id"].as_i64().unwrap();
+    // Let the first poll finish so the poller doesn't touch the subscription
+    harness.wait_for_items(2).await;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let hub = format!("http://{}/hub", listener.local_addr().unwrap());
+    let (requests_send, mut requests) = tokio::sync::mpsc::unbounded_channel();
+    let router = Router::new().route(
+        "/hub",
+        axum::routing::post(
+            async move |axum::Form(form): axum::Form<std::collections::HashMap<String, String>>| {
+                requests_send.send(form).unwrap();
+                StatusCode::ACCEPTED
+            },
+        ),
+    );
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+    let channel = rss::Channel::read_from(
+        format!(
+            r#"<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom"><channel>
+                <title>Pushed</title><link>https://example.com</link><description/>
+                <atom:link rel="hub" href="{hub}"/>
+                <atom:link rel="self" href="https://example.com/feed.xml"/>
+            </channel></rss>"#
+        )
+        .as_bytes(),
+    )
+    .unwrap();
+    let fetcher = Fetcher::new(&harness.config).unwrap();
+    let maintain = async || {
+        let source = Source::get_by_id(id, &harness.sqlite)
+            .await
+            .unwrap()
+            .unwrap();
+        super::websub::maintain(
+            &harness.config,
+            &fetcher,
+            &harness.sqlite,
+            &source,
+            &channel,
+        )
+        .await
+        .unwrap();
+    };
+
+    maintain().await;
+    let request = requests.try_recv().unwrap();
+    assert_eq!(request["hub.mode"], "subscribe");
+    assert_eq!(request["hub.topic"], "https://example.com/feed.xml");
+    assert_eq!(
+        request["hub.callback"],
+        format!("http://localhost/api/websub/{id}")
+    );
+    // Not verified yet, so it isn't asked again on the next poll
+    maintain().await;
+    assert!(requests.try_recv().is_err());
+
+    let topic = "https%3A%2F%2Fexample.com%2Ffeed.xml";
+    let verify = |mode: &str, topic: &str| {
+        format!(
+            "/websub/{id}?hub.mode={mode}&hub.topic={topic}&hub.challenge=12345&hub.lease_seconds={}",
+            i64::MAX
+        )
+    };
+    let (status, _) = harness
+        .request(
+            "GET",
+            &verify("subscribe", "https%3A%2F%2Fexample.com%2Fother.xml"),
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, challenge) = harness
+        .request("GET", &verify("subscribe", topic), None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(challenge, 12345);
+
+    let subscribed = Source::get_by_id(id, &harness.sqlite)
+        .await
+        .unwrap()
+        .unwrap();
+    // Hubs can't keep us subscribed for longer than we asked
+    let expires_at = subscribed.websub_lease_expires_at.unwrap();
+    assert!(expires_at <= chrono::Utc::now().naive_utc() + chrono::Duration::days(10));
+    assert_eq!(subscribed.websub_attempts, 0);
+    // The hub signs pushes with the secret it was sent
+    assert_eq!(
+        subscribed.websub_secret.as_deref(),
+        Some(request["hub.secret"].as_str())
+    );
+    maintain().await;
+    assert!(requests.try_recv().is_err());
+
+    // Nothing is pending anymore, so nobody else can extend or end the subscription
+    for mode in ["subscribe", "denied"] {
+        let (status, _) = harness.request("GET", &verify(mode, topic), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "{mode}");
+    }
+    let unchanged = Source::get_by_id(id, &harness.sqlite)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(unchanged.websub_lease_expires_at, Some(expires_at));
+
+    // Renewing waits on the hub again, which can deny it
+    let expired = chrono::Utc::now().naive_utc() - chrono::Duration::hours(1);
+    Source::set_websub_lease(id, Some(expired), &harness.sqlite)
+        .await
+        .unwrap();
+    maintain().await;
+    assert_eq!(requests.try_recv().unwrap()["hub.mode"], "subscribe");
+    let (status, _) = harness.request("GET", &verify("denied", topic), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let denied = Source::get_by_id(id, &harness.sqlite)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(denied.websub_hub, None);
+    assert_eq!(denied.websub_secret, None);
+}
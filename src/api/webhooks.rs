@@ -0,0 +1,250 @@
+use std::{io, sync::Arc, time::Duration};
+
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use chrono::{NaiveDateTime, Utc};
+use hmac::{Hmac, Mac};
+use http::{header::CONTENT_TYPE, HeaderMap};
+use serde::Deserialize;
+use sha2::Sha256;
+use sqlx::{Pool, Sqlite};
+use tokio::{select, sync::mpsc};
+
+use crate::{
+    config::Config,
+    db::{Item, Webhook, WebhookDelivery},
+    ApiError,
+};
+
+use super::{
+    auth::{authorize, Scope},
+    crud::GetItemsReturn,
+    fetch::{FetchError, Fetcher},
+};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const MAX_ATTEMPTS: i64 = 8;
+/// Doubled after every failed attempt
+const BASE_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Starts the task that delivers queued webhooks, send to the returned channel to have it check
+/// for new deliveries immediately. Webhook urls come from users, so they go through the same
+/// private address checks as links from feeds.
+pub fn start_delivery_worker(
+    config: Arc<Config>,
+    fetcher: Fetcher,
+    sqlite: Pool<Sqlite>,
+) -> mpsc::Sender<()> {
+    let (deliver_send, mut deliver_recv) = mpsc::channel::<()>(1);
+
+    tokio::spawn(async move {
+        loop {
+            let now = Utc::now().naive_utc();
+            match WebhookDelivery::get_due(now, &sqlite).await {
+                Ok(deliveries) => {
+                    for mut delivery in deliveries {
+                        deliver(&config, &fetcher, &sqlite, &mut delivery).await;
+                        if let Err(err) = delivery.update(&sqlite).await {
+                            tracing::error!("Error updating delivery {}: {err:?}", delivery.id);
+                        }
+                    }
+                }
+                Err(err) => tracing::error!("Error getting webhook deliveries: {err:?}"),
+            }
+
+            let wait = match WebhookDelivery::next_attempt_at(&sqlite).await {
+                Ok(Some(next)) => (next - Utc::now().naive_utc())
+                    .to_std()
+                    .unwrap_or_default()
+                    .min(CHECK_INTERVAL),
+                _ => CHECK_INTERVAL,
+            };
+
+            // Wait for the next retry or be notified of new deliveries
+            select! {
+                _ = tokio::time::sleep(wait) => {},
+                _ = deliver_recv.recv() => {}
+            };
+        }
+    });
+
+    deliver_send
+}
+
+/// Queues deliveries of a newly inserted item for every webhook it matches
+pub async fn enqueue(item: &Item, tags: &[&str], sqlite: &Pool<Sqlite>) -> Result<usize, ApiError> {
+    let Some(source_id) = item.source_id else {
+        return Ok(0);
+    };
+
+    let mut queued = 0;
+    for webhook in Webhook::get_subscribed_to(source_id, sqlite).await? {
+        if webhook.matches(item.source_id, tags) {
+            WebhookDelivery::enqueue(webhook.id, item.id, sqlite).await?;
+            queued += 1;
+        }
+    }
+    Ok(queued)
+}
+
+/// `sha256=` and the hex HMAC-SHA256 of `body`, sent as `x-my-feed-signature`
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts keys of any length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// When to retry after the `attempts`th failed attempt, if at all
+fn next_attempt_at(attempts: i64, now: NaiveDateTime) -> Option<NaiveDateTime> {
+    (attempts < MAX_ATTEMPTS).then(|| now + BASE_BACKOFF * 2u32.pow(attempts as u32 - 1))
+}
+
+/// Attempts a delivery once and records the outcome and next attempt on it
+async fn deliver(
+    config: &Config,
+    fetcher: &Fetcher,
+    sqlite: &Pool<Sqlite>,
+    delivery: &mut WebhookDelivery,
+) {
+    let now = Utc::now().naive_utc();
+    delivery.attempts += 1;
+
+    let result = async {
+        let webhook = Webhook::get_by_id(delivery.webhook_id, sqlite)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+        let item = Item::get_for_user(delivery.item_id, webhook.user_id, sqlite)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+        let item = GetItemsReturn::from(item).with_proxied_image(config);
+        let body = serde_json::to_vec(&item).map_err(io::Error::from)?;
+
+        let mut req = fetcher
+            .post(&webhook.url)?
+            .header(CONTENT_TYPE, "application/json")
+            .header("x-my-feed-delivery", delivery.id);
+        if let Some(secret) = webhook.secret.as_deref() {
+            req = req.header("x-my-feed-signature", sign(secret, &body));
+        }
+
+        Ok::<_, ApiError>(req.body(body).send().await.map_err(FetchError::from)?)
+    }
+    .await;
+
+    match result {
+        Ok(res) => {
+            delivery.status_code = Some(res.status().as_u16().into());
+            if res.status().is_success() {
+                tracing::debug!("Delivered webhook {}", delivery.id);
+                delivery.error = None;
+                delivery.delivered_at = Some(now);
+                delivery.next_attempt_at = None;
+                return;
+            }
+            delivery.error = Some(format!("Unexpected status {}", res.status()));
+        }
+        Err(ApiError::NotFound) => {
+            // The webhook or item is gone, nothing to retry
+            delivery.error = Some("Webhook or item no longer exists".into());
+            delivery.next_attempt_at = None;
+            return;
+        }
+        Err(err) => {
+            delivery.status_code = None;
+            delivery.error = Some(err.to_string());
+        }
+    }
+
+    tracing::warn!(
+        "Webhook delivery {} failed (attempt {}): {:?}",
+        delivery.id,
+        delivery.attempts,
+        delivery.error
+    );
+    delivery.next_attempt_at = next_attempt_at(delivery.attempts, now);
+}
+
+pub async fn create_webhook(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+    Json(mut webhook): Json<Webhook>,
+) -> Result<Json<Webhook>, ApiError> {
+    let user = authorize(&state, &headers, Scope::WebhooksWrite).await?;
+    let url =
+        reqwest::Url::parse(&webhook.url).map_err(|err| ApiError::BadRequest(err.to_string()))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(ApiError::BadRequest(
+            "Webhooks must be http or https".into(),
+        ));
+    }
+    // Hosts are checked again when delivering since they can resolve somewhere else later
+    state.fetcher.check_url(&webhook.url)?;
+    webhook.user_id = user.id;
+    webhook.insert(&state.sqlite).await?;
+    Ok(Json(webhook))
+}
+
+pub async fn delete_webhook(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+) -> Result<(), ApiError> {
+    let user = authorize(&state, &headers, Scope::WebhooksWrite).await?;
+    Webhook::delete(id, user.id, &state.sqlite).await?;
+    Ok(())
+}
+
+pub async fn get_webhooks(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<Webhook>>, ApiError> {
+    let user = authorize(&state, &headers, Scope::Read).await?;
+    Ok(Json(Webhook::get_for_user(user.id, &state.sqlite).await?))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetDeliveriesQuery {
+    #[serde(default = "default_deliveries_limit")]
+    limit: i64,
+}
+
+fn default_deliveries_limit() -> i64 {
+    50
+}
+
+pub async fn get_deliveries(
+    State(state): State<super::State>,
+    headers: HeaderMap,
+    Query(query): Query<GetDeliveriesQuery>,
+) -> Result<Json<Vec<WebhookDelivery>>, ApiError> {
+    let user = authorize(&state, &headers, Scope::Read).await?;
+    Ok(Json(
+        WebhookDelivery::recent_for_user(user.id, query.limit.clamp(1, 500), &state.sqlite)
+            .await?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payloads_are_signed_with_hmac_sha256() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn retries_back_off_until_the_last_attempt() {
+        let now = Utc::now().naive_utc();
+        assert_eq!(next_attempt_at(1, now), Some(now + BASE_BACKOFF));
+        assert_eq!(next_attempt_at(3, now), Some(now + BASE_BACKOFF * 4));
+        assert_eq!(next_attempt_at(MAX_ATTEMPTS, now), None);
+    }
+}
@@ -0,0 +1,307 @@
+use std::time::Duration;
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+};
+use chrono::Utc;
+use hmac::{digest::KeyInit, Hmac, Mac};
+use http::{HeaderMap, StatusCode};
+use serde::Deserialize;
+use sqlx::{Pool, Sqlite};
+
+use crate::{config::Config, db::Source, ApiError};
+
+use super::{
+    fetch::{FetchError, Fetcher},
+    ingest::{Ingest, Mode},
+};
+
+/// How long we ask hubs to keep our subscriptions for
+const LEASE: Duration = Duration::from_secs(10 * 24 * 60 * 60);
+/// Subscriptions are renewed once they are this close to expiring
+const RENEW_BEFORE: Duration = Duration::from_secs(24 * 60 * 60);
+/// How long to wait for a hub to verify before asking again, doubled every time it doesn't
+const RETRY_AFTER: Duration = Duration::from_secs(60 * 60);
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// The hub and topic (self) urls a feed advertises with `<atom:link rel="hub">`
+pub fn find_hub(channel: &rss::Channel) -> Option<(String, String)> {
+    let links = channel.atom_ext()?.links();
+    let hub = links.iter().find(|link| link.rel() == "hub")?;
+    let topic = links.iter().find(|link| link.rel() == "self")?;
+    Some((hub.href().to_string(), topic.href().to_string()))
+}
+
+/// Subscribes to the hub of a freshly fetched channel if we haven't yet, the hub changed, or the
+/// lease is about to run out. Hubs come from feeds, so they're requested through the fetcher.
+pub async fn maintain(
+    config: &Config,
+    fetcher: &Fetcher,
+    sqlite: &Pool<Sqlite>,
+    source: &Source,
+    channel: &rss::Channel,
+) -> Result<(), ApiError> {
+    let Some((hub, topic)) = find_hub(channel) else {
+        if source.websub_hub.is_some() {
+            // The feed stopped advertising a hub, go back to just polling
+            Source::set_websub(source.id, None, None, None, sqlite).await?;
+        }
+        return Ok(());
+    };
+
+    let now = Utc::now().naive_utc();
+    let same_hub = source.websub_hub.as_deref() == Some(&hub)
+        && source.websub_topic.as_deref() == Some(&topic);
+    let is_current = same_hub
+        && source
+            .websub_lease_expires_at
+            .is_some_and(|expires_at| expires_at - RENEW_BEFORE > now);
+    // Hubs that don't verify aren't asked again on every poll
+    let is_pending = same_hub
+        && source.websub_lease_expires_at.is_none()
+        && source
+            .websub_requested_at
+            .is_some_and(|requested_at| requested_at + retry_after(source.websub_attempts) > now);
+    if is_current || is_pending {
+        return Ok(());
+    }
+
+    // The secret only changes with the subscription, or a push signed with the old one could
+    // arrive after we've already forgotten it
+    let secret = match source.websub_secret.as_deref() {
+        Some(secret)
+            if same_hub
+                && source
+                    .websub_lease_expires_at
+                    .is_none_or(|expires_at| expires_at > now) =>
+        {
+            secret.to_string()
+        }
+        _ => {
+            let mut bytes = [0u8; 32];
+            OsRng.fill_bytes(&mut bytes);
+            let secret = hex::encode(bytes);
+            Source::set_websub(source.id, Some(&hub), Some(&topic), Some(&secret), sqlite).await?;
+            secret
+        }
+    };
+    Source::websub_requested(source.id, now, sqlite).await?;
+
+    let callback = format!(
+        "{}/api/websub/{}",
+        config.domain.to_string().trim_end_matches('/'),
+        source.id
+    );
+    tracing::info!("Subscribing to {topic} at {hub}");
+    fetcher
+        .post(&hub)?
+        .form(&[
+            ("hub.mode", "subscribe"),
+            ("hub.topic", &topic),
+            ("hub.callback", &callback),
+            ("hub.secret", &secret),
+            ("hub.lease_seconds", &LEASE.as_secs().to_string()),
+        ])
+        .send()
+        .await
+        .map_err(FetchError::from)?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// How long to wait for a hub to verify after `attempts` unverified requests
+fn retry_after(attempts: i64) -> Duration {
+    RETRY_AFTER
+        .saturating_mul(2u32.saturating_pow(attempts.saturating_sub(1) as u32))
+        .min(MAX_RETRY_AFTER)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyQuery {
+    #[serde(rename = "hub.mode")]
+    mode: String,
+    #[serde(rename = "hub.topic")]
+    topic: String,
+    #[serde(rename = "hub.challenge")]
+    challenge: Option<String>,
+    #[serde(rename = "hub.lease_seconds")]
+    lease_seconds: Option<i64>,
+}
+
+/// Hubs call this to confirm that we actually asked to (un)subscribe. Anyone can call it, so
+/// subscriptions are only confirmed or denied while we're waiting on the hub.
+pub async fn verify_intent(
+    State(state): State<super::State>,
+    Path(source_id): Path<i64>,
+    Query(query): Query<VerifyQuery>,
+) -> Result<String, ApiError> {
+    let source = Source::get_by_id(source_id, &state.sqlite).await?;
+    let is_our_topic = source
+        .as_ref()
+        .is_some_and(|source| source.websub_topic.as_deref() == Some(&query.topic));
+    // Verifying resets the attempts, so only requests the hub hasn't answered yet count
+    let is_pending = is_our_topic
+        && source.as_ref().is_some_and(|source| {
+            source.websub_requested_at.is_some() && source.websub_attempts > 0
+        });
+
+    match query.mode.as_str() {
+        "subscribe" if is_pending => {
+            // We ask for `LEASE`, a hub granting more just gets renewed early
+            let lease_seconds = query
+                .lease_seconds
+                .unwrap_or(LEASE.as_secs() as i64)
+                .clamp(0, LEASE.as_secs() as i64);
+            let expires_at = Utc::now()
+                .naive_utc()
+                .checked_add_signed(chrono::Duration::seconds(lease_seconds))
+                .ok_or_else(|| ApiError::BadRequest("Lease is too long".into()))?;
+            Source::set_websub_lease(source_id, Some(expires_at), &state.sqlite).await?;
+            tracing::info!("Subscribed to {} until {expires_at}", query.topic);
+            query.challenge.ok_or(ApiError::NotFound)
+        }
+        // We only stop wanting pushes when the source is gone or moved to another topic
+        "unsubscribe" if !is_our_topic => query.challenge.ok_or(ApiError::NotFound),
+        "denied" if is_pending => {
+            tracing::warn!("Hub denied subscription to {}", query.topic);
+            Source::set_websub(source_id, None, None, None, &state.sqlite).await?;
+            Ok(String::new())
+        }
+        _ => Err(ApiError::NotFound),
+    }
+}
+
+/// Hubs push new content here, which goes through the same ingest as polling
+pub async fn receive_content(
+    State(state): State<super::State>,
+    Path(source_id): Path<i64>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<StatusCode, ApiError> {
+    let source = Source::get_by_id(source_id, &state.sqlite)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    let Some(secret) = source.websub_secret.as_deref() else {
+        return Err(ApiError::NotFound);
+    };
+
+    let signature = headers
+        .get("x-hub-signature")
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or_default();
+    if !verify_signature(secret.as_bytes(), &body, signature) {
+        // The spec wants a success response even for bad signatures, we just ignore the content
+        tracing::warn!("Ignoring push for {} with invalid signature", source.name);
+        return Ok(StatusCode::ACCEPTED);
+    }
+
+    let channel = match rss::Channel::read_from(&*body) {
+        Ok(channel) => channel,
+        Err(err) => {
+            tracing::error!("Invalid push for {}: {err:?}", source.name);
+            return Ok(StatusCode::ACCEPTED);
+        }
+    };
+
+    tracing::debug!("Received push for {}", source.name);
+    // Fetching images can take a while and hubs don't wait long
+    tokio::spawn(async move {
+        let ingest = Ingest {
+            fetcher: &state.fetcher,
+            sqlite: &state.sqlite,
+            deliver_send: &state.deliver_send,
+            category_tags: state.config.category_tags,
+        };
+        if let Err(err) = ingest.run(&source, channel, Mode::Commit).await {
+            tracing::error!("Error ingesting push for {}: {err:?}", source.name);
+        }
+    });
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Checks an `X-Hub-Signature` header of the form `method=hex`
+fn verify_signature(secret: &[u8], body: &[u8], signature: &str) -> bool {
+    let Some((method, signature)) = signature.split_once('=') else {
+        return false;
+    };
+    let Ok(signature) = hex::decode(signature) else {
+        return false;
+    };
+
+    match method {
+        "sha1" => verify_hmac::<Hmac<sha1::Sha1>>(secret, body, &signature),
+        "sha256" => verify_hmac::<Hmac<sha2::Sha256>>(secret, body, &signature),
+        "sha384" => verify_hmac::<Hmac<sha2::Sha384>>(secret, body, &signature),
+        "sha512" => verify_hmac::<Hmac<sha2::Sha512>>(secret, body, &signature),
+        _ => false,
+    }
+}
+
+fn verify_hmac<M: Mac + KeyInit>(secret: &[u8], body: &[u8], signature: &[u8]) -> bool {
+    let Ok(mut mac) = <M as KeyInit>::new_from_slice(secret) else {
+        return false;
+    };
+    mac.update(body);
+    mac.verify_slice(signature).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sign<M: Mac + KeyInit>(body: &[u8]) -> String {
+        let mut mac = <M as KeyInit>::new_from_slice(b"secret").unwrap();
+        mac.update(body);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    #[test]
+    fn signatures_are_checked_with_the_named_hash() {
+        let body = b"<rss></rss>";
+        let sha1 = sign::<Hmac<sha1::Sha1>>(body);
+        let sha256 = sign::<Hmac<sha2::Sha256>>(body);
+        let sha512 = sign::<Hmac<sha2::Sha512>>(body);
+
+        assert!(verify_signature(b"secret", body, &format!("sha1={sha1}")));
+        assert!(verify_signature(
+            b"secret",
+            body,
+            &format!("sha256={sha256}")
+        ));
+        assert!(verify_signature(
+            b"secret",
+            body,
+            &format!("sha512={sha512}")
+        ));
+
+        assert!(!verify_signature(
+            b"other",
+            body,
+            &format!("sha256={sha256}")
+        ));
+        assert!(!verify_signature(
+            b"secret",
+            b"<rss/>",
+            &format!("sha256={sha256}")
+        ));
+        // A valid signature under the wrong name
+        assert!(!verify_signature(
+            b"secret",
+            body,
+            &format!("sha1={sha256}")
+        ));
+        assert!(!verify_signature(b"secret", body, &format!("md5={sha1}")));
+        assert!(!verify_signature(b"secret", body, "sha256=not hex"));
+        assert!(!verify_signature(b"secret", body, &sha256));
+        assert!(!verify_signature(b"secret", body, ""));
+    }
+
+    #[test]
+    fn unverified_requests_back_off() {
+        assert_eq!(retry_after(1), RETRY_AFTER);
+        assert_eq!(retry_after(3), RETRY_AFTER * 4);
+        assert_eq!(retry_after(100), MAX_RETRY_AFTER);
+    }
+}
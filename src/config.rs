@@ -1,8 +1,16 @@
-use std::{fs, net::SocketAddr, path::Path, sync::Arc};
+use std::{
+    fs,
+    net::{IpAddr, SocketAddr},
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
 
 use http::Uri;
 use serde::Deserialize;
 
+use crate::db::{source::OnSourceDelete, CategoryTags};
+
 #[derive(Debug, Deserialize)]
 pub struct Config {
     #[cfg_attr(debug_assertions, serde(default = "dev_domain"))]
@@ -29,6 +37,89 @@ pub struct Config {
 
     #[serde(default = "default_password")]
     pub password: Arc<str>,
+
+    /// Require authentication for every API route, including reads
+    #[serde(default)]
+    pub private: bool,
+
+    /// API paths (e.g. `/feeds/rss`) that stay public when `private` is set
+    #[serde(default)]
+    pub public_paths: Vec<Arc<str>>,
+
+    #[serde(default = "default_login_attempts_per_minute")]
+    pub login_attempts_per_minute: u32,
+
+    /// Failed authentications from one address before it is locked out
+    #[serde(default = "default_login_max_failures")]
+    pub login_max_failures: u32,
+
+    #[serde(default = "default_login_lockout", with = "humantime_serde")]
+    pub login_lockout: Duration,
+
+    /// Reverse proxies whose `X-Forwarded-For` header is trusted
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+
+    /// How long items everyone is done with are kept, forever if unset. Favorites and items
+    /// waiting in a read later queue are always kept.
+    #[serde(default, with = "humantime_serde")]
+    pub retain_done_for: Option<Duration>,
+
+    /// How long items someone hasn't finished are kept, forever if unset
+    #[serde(default, with = "humantime_serde")]
+    pub retain_unread_for: Option<Duration>,
+
+    /// Hide expired items from feeds instead of deleting them
+    #[serde(default)]
+    pub archive_expired: bool,
+
+    /// How long deleted items are remembered so polling doesn't add them again
+    #[serde(default = "default_tombstone_ttl", with = "humantime_serde")]
+    pub tombstone_ttl: Duration,
+
+    #[serde(default = "default_janitor_interval", with = "humantime_serde")]
+    pub janitor_interval: Duration,
+
+    #[serde(default = "default_vacuum_interval", with = "humantime_serde")]
+    pub vacuum_interval: Duration,
+
+    /// Let feeds and the links in them point at private and loopback addresses, only for trusted
+    /// users
+    #[serde(default)]
+    pub allow_private_addresses: bool,
+
+    /// Sent with every outgoing request, some hosts reject requests without one
+    #[serde(default = "default_user_agent")]
+    pub user_agent: Arc<str>,
+
+    /// Gives up on outgoing requests that take longer than this in total
+    #[serde(default = "default_request_timeout", with = "humantime_serde")]
+    pub request_timeout: Duration,
+
+    #[serde(default = "default_max_redirects")]
+    pub max_redirects: usize,
+
+    /// Sends outgoing requests through this proxy, an `http://`, `https://` or `socks5://` url.
+    /// The proxy resolves hostnames, so only literal private addresses are refused then and a
+    /// warning is logged at startup unless `allow_private_addresses` is set.
+    #[serde(default)]
+    pub proxy: Option<Arc<str>>,
+
+    /// Writes a snapshot of the db to `data_dir/backups` this often, never if unset
+    #[serde(default, with = "humantime_serde")]
+    pub backup_interval: Option<Duration>,
+
+    /// How many scheduled snapshots to keep, the oldest are deleted first
+    #[serde(default = "default_backups_kept")]
+    pub backups_kept: usize,
+
+    /// What happens to a source's items once nobody is subscribed to it anymore
+    #[serde(default)]
+    pub source_items_on_delete: OnSourceDelete,
+
+    /// How categories from feeds become tags, sources can override it
+    #[serde(default)]
+    pub category_tags: CategoryTags,
 }
 
 impl Config {
@@ -79,6 +170,18 @@ fn default_data_dir() -> Arc<Path> {
     }
 }
 
+fn default_login_attempts_per_minute() -> u32 {
+    10
+}
+
+fn default_login_max_failures() -> u32 {
+    5
+}
+
+fn default_login_lockout() -> Duration {
+    Duration::from_secs(15 * 60)
+}
+
 fn default_password() -> Arc<str> {
     if cfg!(debug_assertions) {
         Arc::from("password")
@@ -86,3 +189,31 @@ fn default_password() -> Arc<str> {
         panic!("Must set PASSWORD env variable for production.")
     }
 }
+
+fn default_tombstone_ttl() -> Duration {
+    Duration::from_secs(180 * 24 * 60 * 60)
+}
+
+fn default_janitor_interval() -> Duration {
+    Duration::from_secs(60 * 60)
+}
+
+fn default_vacuum_interval() -> Duration {
+    Duration::from_secs(7 * 24 * 60 * 60)
+}
+
+fn default_user_agent() -> Arc<str> {
+    Arc::from(concat!("my-feed/", env!("CARGO_PKG_VERSION")))
+}
+
+fn default_request_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+
+fn default_max_redirects() -> usize {
+    10
+}
+
+fn default_backups_kept() -> usize {
+    7
+}
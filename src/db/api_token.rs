@@ -0,0 +1,112 @@
+use serde::{Deserialize, Serialize};
+use sqlx::prelude::*;
+
+use super::Error;
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, ts_rs::TS)]
+#[ts(export, export_to = "../web/src/types/ApiToken.ts")]
+pub struct ApiToken {
+    #[ts(type = "number")]
+    pub id: i64,
+
+    #[ts(type = "number")]
+    pub user_id: i64,
+
+    pub name: String,
+
+    #[serde(skip)]
+    #[ts(skip)]
+    pub token_hash: String,
+
+    /// Comma separated list of scopes
+    pub scopes: String,
+
+    pub last_used_at: Option<chrono::NaiveDateTime>,
+
+    pub created_at: chrono::NaiveDateTime,
+}
+
+impl ApiToken {
+    pub async fn get_for_user(
+        user_id: i64,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Vec<Self>, Error> {
+        sqlx::query_as!(
+            ApiToken,
+            "SELECT * FROM api_tokens WHERE user_id = ?1",
+            user_id
+        )
+        .fetch_all(executor)
+        .await
+        .map_err(|e| Error::SelectError("api_tokens", e))
+    }
+
+    pub async fn get_by_hash(
+        token_hash: &str,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Option<Self>, Error> {
+        sqlx::query_as!(
+            ApiToken,
+            "SELECT * FROM api_tokens WHERE token_hash = ?1",
+            token_hash
+        )
+        .fetch_optional(executor)
+        .await
+        .map_err(|e| Error::SelectError("api_tokens", e))
+    }
+
+    /// Inserts self into the database and populates its `id` field
+    pub async fn insert(
+        &mut self,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<(), Error> {
+        let id = sqlx::query!(
+            r#"
+		INSERT INTO api_tokens(user_id, name, token_hash, scopes)
+		VALUES (?1, ?2, ?3, ?4)
+		"#,
+            self.user_id,
+            self.name,
+            self.token_hash,
+            self.scopes
+        )
+        .execute(executor)
+        .await
+        .map_err(|e| Error::InsertError("api_tokens", e))?
+        .last_insert_rowid();
+
+        self.id = id;
+        Ok(())
+    }
+
+    pub async fn touch(
+        id: i64,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            "UPDATE api_tokens SET last_used_at = CURRENT_TIMESTAMP WHERE id = ?",
+            id
+        )
+        .execute(executor)
+        .await
+        .map_err(|e| Error::UpdateError("api_tokens", e))
+        .map(|_| ())
+    }
+
+    /// Revokes one of `user_id`'s tokens
+    pub async fn delete(
+        id: i64,
+        user_id: i64,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            "DELETE FROM api_tokens WHERE id = ? AND user_id = ?",
+            id,
+            user_id
+        )
+        .execute(executor)
+        .await
+        .map_err(|e| Error::DeleteError("api_tokens", e))
+        .map(|_| ())
+    }
+}
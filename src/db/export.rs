@@ -0,0 +1,417 @@
+//! A JSON copy of the feed state that doesn't mirror the schema, so it can be imported into newer
+//! versions or other instances. Sources are matched by url, items by link (or guid within their
+//! source) and tags by name, so importing the same export twice doesn't duplicate anything.
+
+use std::collections::BTreeMap;
+
+use chrono::NaiveDateTime;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use sqlx::Pool;
+
+use super::{Error, Item, Source, Tag};
+
+pub const EXPORT_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Export {
+    pub version: u32,
+    #[serde(default)]
+    pub tags: Vec<ExportTag>,
+    #[serde(default)]
+    pub sources: Vec<ExportSource>,
+    #[serde(default)]
+    pub items: Vec<ExportItem>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportTag {
+    pub name: String,
+    #[serde(default)]
+    pub background_color: Option<String>,
+    #[serde(default)]
+    pub text_color: Option<String>,
+    #[serde(default)]
+    pub border_color: Option<String>,
+    #[serde(default)]
+    pub parent: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportSource {
+    pub name: String,
+    pub url: String,
+    #[serde(default)]
+    pub ttl: Option<i64>,
+    #[serde(default)]
+    pub favorite: bool,
+    #[serde(default, with = "super::utc::option")]
+    pub min_date: Option<NaiveDateTime>,
+    #[serde(default)]
+    pub retain_done_for: Option<i64>,
+    #[serde(default)]
+    pub retain_unread_for: Option<i64>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub auth_username: Option<String>,
+    #[serde(default)]
+    pub auth_password: Option<String>,
+    #[serde(default)]
+    pub category_tags: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportItem {
+    pub link: String,
+    #[serde(default)]
+    pub guid: Option<String>,
+    #[serde(default)]
+    pub guid_is_permalink: bool,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub author: Option<String>,
+    #[serde(default, with = "super::utc::option")]
+    pub published: Option<NaiveDateTime>,
+    #[serde(default)]
+    pub source_link: Option<String>,
+    #[serde(default)]
+    pub image: Option<String>,
+    #[serde(default, with = "super::utc::option")]
+    pub created_at: Option<NaiveDateTime>,
+    #[serde(default, with = "super::utc::option")]
+    pub archived_at: Option<NaiveDateTime>,
+    /// Url of the source the item came from, none for items added by hand
+    #[serde(default)]
+    pub source_url: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Whether the user who exported it was done with it
+    #[serde(default)]
+    pub done: bool,
+    #[serde(default)]
+    pub favorite: bool,
+}
+
+/// How many rows an import created, existing ones aren't counted
+#[derive(Debug, Default, Serialize, ts_rs::TS)]
+#[ts(export, export_to = "../web/src/types/ImportCounts.ts")]
+pub struct ImportCounts {
+    pub tags: u64,
+    pub sources: u64,
+    pub items: u64,
+}
+
+fn group(pairs: impl IntoIterator<Item = (i64, String)>) -> FxHashMap<i64, Vec<String>> {
+    let mut groups = FxHashMap::<_, Vec<_>>::default();
+    for (id, name) in pairs {
+        groups.entry(id).or_default().push(name);
+    }
+    groups
+}
+
+impl Export {
+    /// Everything in the db, with `user_id`'s done and favorite states on items
+    pub async fn load(user_id: i64, sqlite: &Pool<super::DB>) -> Result<Self, Error> {
+        // Read everything from one snapshot
+        let mut tx = sqlite
+            .begin()
+            .await
+            .map_err(|e| Error::SelectError("sources", e))?;
+
+        let tags = Tag::get_all(&mut *tx).await?;
+        let sources = Source::get_all(&mut *tx).await?;
+        let source_tags = sqlx::query!(
+            r#"SELECT source_id AS "source_id!", tag_id AS "tag_id!: String" FROM sources_to_tags"#
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| Error::SelectError("sources_to_tags", e))?;
+        let items = sqlx::query_as!(Item, "SELECT * FROM items")
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(|e| Error::SelectError("items", e))?;
+        let item_tags = sqlx::query!(
+            r#"SELECT item_id AS "item_id!", tag_id AS "tag_id!: String" FROM items_to_tags"#
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| Error::SelectError("items_to_tags", e))?;
+        let user_items = sqlx::query!(
+            "SELECT item_id, done, favorite FROM user_items WHERE user_id = ?",
+            user_id
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| Error::SelectError("user_items", e))?;
+        tx.commit()
+            .await
+            .map_err(|e| Error::SelectError("sources", e))?;
+
+        let mut source_tags = group(source_tags.into_iter().map(|r| (r.source_id, r.tag_id)));
+        let mut item_tags = group(item_tags.into_iter().map(|r| (r.item_id, r.tag_id)));
+        let user_items = user_items
+            .into_iter()
+            .map(|r| (r.item_id, (r.done, r.favorite)))
+            .collect::<FxHashMap<_, _>>();
+        let source_urls = sources
+            .iter()
+            .map(|source| (source.id, source.url.clone()))
+            .collect::<FxHashMap<_, _>>();
+
+        Ok(Self {
+            version: EXPORT_VERSION,
+            tags: tags
+                .into_iter()
+                .map(|tag| ExportTag {
+                    name: tag.name,
+                    background_color: tag.background_color,
+                    text_color: tag.text_color,
+                    border_color: tag.border_color,
+                    parent: tag.parent,
+                })
+                .collect(),
+            sources: sources
+                .into_iter()
+                .map(|source| ExportSource {
+                    tags: source_tags.remove(&source.id).unwrap_or_default(),
+                    name: source.name,
+                    url: source.url,
+                    ttl: source.ttl,
+                    favorite: source.favorite,
+                    min_date: source.min_date,
+                    retain_done_for: source.retain_done_for,
+                    retain_unread_for: source.retain_unread_for,
+                    headers: source
+                        .headers
+                        .and_then(|headers| serde_json::from_str(&headers).ok())
+                        .unwrap_or_default(),
+                    auth_username: source.auth_username,
+                    auth_password: source.auth_password,
+                    category_tags: source.category_tags,
+                })
+                .collect(),
+            items: items
+                .into_iter()
+                .map(|item| {
+                    let (done, favorite) = user_items.get(&item.id).copied().unwrap_or_default();
+                    ExportItem {
+                        tags: item_tags.remove(&item.id).unwrap_or_default(),
+                        source_url: item.source_id.and_then(|id| source_urls.get(&id).cloned()),
+                        link: item.link,
+                        guid: item.guid,
+                        guid_is_permalink: item.guid_is_permalink,
+                        title: item.title,
+                        description: item.description,
+                        author: item.author,
+                        published: item.published,
+                        source_link: item.source_link,
+                        image: item.image,
+                        created_at: Some(item.created_at),
+                        archived_at: item.archived_at,
+                        done,
+                        favorite,
+                    }
+                })
+                .collect(),
+        })
+    }
+
+    /// Adds everything missing from the db in one transaction. `user_id` is subscribed to the
+    /// sources and gets the done and favorite states on items.
+    pub async fn import(
+        &self,
+        user_id: i64,
+        sqlite: &Pool<super::DB>,
+    ) -> Result<ImportCounts, Error> {
+        let now = chrono::Utc::now().naive_utc();
+        let mut counts = ImportCounts::default();
+        let mut tx = sqlite
+            .begin()
+            .await
+            .map_err(|e| Error::InsertError("sources", e))?;
+
+        let mut new_tags = Vec::new();
+        for tag in &self.tags {
+            let inserted = sqlx::query!(
+                r#"
+            INSERT INTO tags (name, background_color, text_color, border_color)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT (name) DO NOTHING
+            "#,
+                tag.name,
+                tag.background_color,
+                tag.text_color,
+                tag.border_color
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| Error::InsertError("tags", e))?
+            .rows_affected();
+            if inserted > 0 {
+                new_tags.push(tag);
+            }
+        }
+        counts.tags = new_tags.len() as u64;
+        // Parents can come after their children, so they're set once every tag exists. Tags that
+        // were already here keep their place in the tree.
+        for tag in new_tags {
+            let Some(parent) = &tag.parent else {
+                continue;
+            };
+            sqlx::query!(
+                r#"
+            UPDATE tags
+            SET parent = ?2
+            WHERE name = ?1 AND ?2 != ?1 AND ?2 IN (SELECT name FROM tags)
+            "#,
+                tag.name,
+                parent
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| Error::UpdateError("tags", e))?;
+        }
+
+        let mut source_ids = FxHashMap::default();
+        for source in &self.sources {
+            let id = match Source::get_by_url(&source.url, &mut *tx).await? {
+                Some(existing) => existing.id,
+                None => {
+                    let headers = (!source.headers.is_empty())
+                        .then(|| serde_json::to_string(&source.headers).unwrap());
+                    let id = sqlx::query!(
+                        r#"
+                    INSERT INTO sources (name, url, last_pub, ttl, favorite, min_date, retain_done_for, retain_unread_for, headers, auth_username, auth_password, category_tags)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+                    "#,
+                        source.name,
+                        source.url,
+                        now,
+                        source.ttl,
+                        source.favorite,
+                        source.min_date,
+                        source.retain_done_for,
+                        source.retain_unread_for,
+                        headers,
+                        source.auth_username,
+                        source.auth_password,
+                        source.category_tags
+                    )
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|e| Error::InsertError("sources", e))?
+                    .last_insert_rowid();
+                    counts.sources += 1;
+                    id
+                }
+            };
+            super::User::subscribe(user_id, id, &mut *tx).await?;
+            for tag in &source.tags {
+                sqlx::query!(
+                    "INSERT INTO sources_to_tags (source_id, tag_id) SELECT ?1, name FROM tags WHERE name = ?2",
+                    id,
+                    tag
+                )
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| Error::InsertError("sources_to_tags", e))?;
+            }
+            source_ids.insert(source.url.as_str(), id);
+        }
+
+        for item in &self.items {
+            let source_id = match item.source_url.as_deref() {
+                Some(url) => match source_ids.get(url) {
+                    Some(id) => Some(*id),
+                    None => Source::get_by_url(url, &mut *tx)
+                        .await?
+                        .map(|source| source.id),
+                },
+                None => None,
+            };
+
+            counts.items += sqlx::query!(
+                r#"
+            INSERT INTO items (link, guid, guid_is_permalink, title, description, author, published, source_link, image, created_at, archived_at, source_id)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, COALESCE(?10, CURRENT_TIMESTAMP), ?11, ?12)
+            ON CONFLICT DO NOTHING
+            "#,
+                item.link,
+                item.guid,
+                item.guid_is_permalink,
+                item.title,
+                item.description,
+                item.author,
+                item.published,
+                item.source_link,
+                item.image,
+                item.created_at,
+                item.archived_at,
+                source_id
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| Error::InsertError("items", e))?
+            .rows_affected();
+
+            let id = sqlx::query_scalar!(
+                r#"
+            SELECT id AS "id!"
+            FROM items
+            WHERE source_id IS ?2 AND (guid = ?3 OR (?3 IS NULL AND guid IS NULL AND link = ?1))
+            "#,
+                item.link,
+                source_id,
+                item.guid
+            )
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| Error::SelectError("items", e))?;
+
+            for tag in &item.tags {
+                sqlx::query!(
+                    "INSERT OR IGNORE INTO items_to_tags (item_id, tag_id) SELECT ?1, name FROM tags WHERE name = ?2",
+                    id,
+                    tag
+                )
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| Error::InsertError("items_to_tags", e))?;
+            }
+
+            // Items added by hand only show up for users that have them
+            if item.done || item.favorite || source_id.is_none() {
+                sqlx::query!(
+                    r#"
+                INSERT INTO user_items (user_id, item_id, done, favorite)
+                VALUES (?1, ?2, ?3, ?4)
+                ON CONFLICT (user_id, item_id) DO UPDATE
+                SET done = done OR excluded.done, favorite = favorite OR excluded.favorite
+                "#,
+                    user_id,
+                    id,
+                    item.done,
+                    item.favorite
+                )
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| Error::InsertError("user_items", e))?;
+            }
+        }
+
+        tx.commit()
+            .await
+            .map_err(|e| Error::InsertError("items", e))?;
+        Ok(counts)
+    }
+}
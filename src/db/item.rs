@@ -3,9 +3,10 @@ use std::time::Duration;
 use futures::TryFutureExt;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use sqlx::prelude::*;
 
-use super::Error;
+use super::{tag::tag_tree_cte, Error};
 
 #[derive(Debug, FromRow, Serialize, Deserialize, ts_rs::TS)]
 #[ts(export, export_to = "../web/src/types/Item.ts")]
@@ -21,25 +22,193 @@ pub struct Item {
 
     pub author: Option<String>,
 
+    /// When the feed says the item was published, in UTC
+    #[serde(default, with = "super::utc::option")]
+    #[ts(type = "string | null")]
     pub published: Option<chrono::NaiveDateTime>,
 
     pub source_link: Option<String>,
 
     pub image: Option<String>,
 
+    /// When we fetched the item
+    #[serde(skip_deserializing, serialize_with = "super::utc::serialize")]
+    pub created_at: chrono::NaiveDateTime,
+
+    #[serde(skip_deserializing, serialize_with = "super::utc::serialize")]
+    pub updated_at: chrono::NaiveDateTime,
+
+    pub source_id: Option<i64>,
+
+    /// Set when the janitor archived the item instead of deleting it
+    #[serde(skip_deserializing, serialize_with = "super::utc::option::serialize")]
+    #[ts(type = "string | null")]
+    pub archived_at: Option<chrono::NaiveDateTime>,
+
+    /// The feed's id for the item, items from the same source with the same guid are the same item
     #[serde(default)]
-    pub favorite: bool,
+    pub guid: Option<String>,
 
+    /// Whether `guid` is also a url to the item
     #[serde(default)]
-    pub done: bool,
+    pub guid_is_permalink: bool,
 
-    #[serde(skip_deserializing)]
+    /// Hash of the content the feed can change, see `Item::compute_content_hash`
+    #[serde(skip)]
+    #[ts(skip)]
+    pub content_hash: Option<String>,
+
+    /// When the feed last changed the item after we ingested it
+    #[serde(skip_deserializing, serialize_with = "super::utc::option::serialize")]
+    #[ts(type = "string | null")]
+    pub content_updated_at: Option<chrono::NaiveDateTime>,
+}
+
+/// How many previous versions of an item are kept
+const MAX_REVISIONS: i64 = 5;
+
+/// An item's content before its feed changed it
+#[derive(Debug, Clone, FromRow, Serialize, ts_rs::TS)]
+#[ts(export, export_to = "../web/src/types/ItemRevision.ts")]
+pub struct ItemRevision {
+    #[ts(type = "number")]
+    pub id: i64,
+
+    #[ts(type = "number")]
+    pub item_id: i64,
+
+    pub link: String,
+
+    pub title: Option<String>,
+
+    pub description: Option<String>,
+
+    pub author: Option<String>,
+
+    #[serde(serialize_with = "super::utc::serialize")]
     pub created_at: chrono::NaiveDateTime,
+}
 
-    #[serde(skip_deserializing)]
-    pub updated_at: chrono::NaiveDateTime,
+/// A macro rather than a const so `USER_ITEM_SELECT` can be built from it with `concat!`
+macro_rules! item_tags {
+    () => {
+        r#"
+    SELECT tag_id FROM items_to_tags WHERE item_id = i.id
+    UNION
+    SELECT tag_id FROM user_items_to_tags WHERE item_id = i.id AND user_id = ?1
+"#
+    };
+}
 
-    pub source_id: Option<i64>,
+/// Tags on the item `i`, its own and the ones user `?1` added
+pub(super) const ITEM_TAGS: &str = item_tags!();
+
+/// Items `i` the user bound to `?1` can see, from their subscriptions or that they added
+/// themselves, joined with their state for them as `ui`. Selects can add conditions with `AND`.
+macro_rules! visible_items {
+    () => {
+        r#"
+    FROM items i
+    LEFT JOIN user_items ui ON i.id = ui.item_id AND ui.user_id = ?1
+    WHERE (
+        i.source_id IN (SELECT source_id FROM subscriptions WHERE user_id = ?1)
+        OR ui.user_id IS NOT NULL
+    )
+"#
+    };
+}
+pub(super) use visible_items;
+
+/// Selects items visible to the user bound to `?1` with their state and tags (`tags` holds both
+/// the tags from the feed and the user's own)
+pub(super) const USER_ITEM_SELECT: &str = concat!(
+    r#"
+    SELECT
+        i.*,
+        COALESCE(ui.done, FALSE) AS done,
+        COALESCE(ui.favorite, FALSE) AS favorite,
+        (SELECT GROUP_CONCAT(tag_id, ',') FROM ("#,
+    item_tags!(),
+    ")) AS tags",
+    visible_items!()
+);
+
+/// What the feed is ordered (and `from_last` measured) by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ts_rs::TS)]
+#[ts(export, export_to = "../web/src/types/FeedSort.ts")]
+#[serde(rename_all = "lowercase")]
+pub enum FeedSort {
+    /// When the item was published, falling back to when we fetched it. Items can't be published
+    /// after we fetched them, so a feed with dates in the future can't pin them to the top.
+    #[default]
+    Published,
+    /// When we fetched the item
+    Fetched,
+}
+
+impl FeedSort {
+    pub(super) fn sql(self) -> &'static str {
+        match self {
+            FeedSort::Published => "MIN(COALESCE(i.published, i.created_at), i.created_at)",
+            FeedSort::Fetched => "i.created_at",
+        }
+    }
+}
+
+/// How long items are kept, in seconds. `None` keeps them forever unless their source overrides
+/// it, and a source setting 0 keeps its items forever.
+#[derive(Debug, Clone, Copy)]
+pub struct Retention {
+    pub now: chrono::NaiveDateTime,
+    pub done_for: Option<i64>,
+    pub unread_for: Option<i64>,
+}
+
+/// Items a `Retention` would remove, binds `?1` to `?3` to its fields. Items count as done once
+/// every user that can see them is done with them, and anyone's favorite is kept.
+const EXPIRED_ITEMS: &str = r#"
+    WITH candidates AS (
+        SELECT
+            i.id,
+            unixepoch(i.created_at) AS fetched,
+            NOT EXISTS (
+                SELECT 1
+                FROM subscriptions sub
+                LEFT JOIN user_items ui ON ui.item_id = i.id AND ui.user_id = sub.user_id
+                WHERE sub.source_id = i.source_id AND COALESCE(ui.done, FALSE) = FALSE
+            ) AND NOT EXISTS (
+                SELECT 1 FROM user_items WHERE item_id = i.id AND done = FALSE
+            ) AS done,
+            s.retain_done_for,
+            s.retain_unread_for
+        FROM items i
+        LEFT JOIN sources s ON i.source_id = s.id
+        WHERE i.archived_at IS NULL
+            AND NOT EXISTS (SELECT 1 FROM user_items WHERE item_id = i.id AND favorite)
+            AND NOT EXISTS (
+                SELECT 1 FROM read_later WHERE item_id = i.id AND archived_at IS NULL
+            )
+    ),
+    expired AS (
+        SELECT id, done
+        FROM candidates
+        WHERE fetched < unixepoch(?1) - NULLIF(
+            CASE
+                WHEN done THEN COALESCE(retain_done_for, ?2)
+                ELSE COALESCE(retain_unread_for, ?3)
+            END,
+            0
+        )
+    )
+"#;
+
+#[derive(Debug, Default, Serialize, FromRow, ts_rs::TS)]
+#[ts(export, export_to = "../web/src/types/ExpiredCounts.ts")]
+pub struct ExpiredCounts {
+    #[ts(type = "number")]
+    pub done: i64,
+    #[ts(type = "number")]
+    pub unread: i64,
 }
 
 #[derive(Debug, Serialize, FromRow)]
@@ -47,6 +216,8 @@ pub struct ItemWTags {
     #[serde(flatten)]
     #[sqlx(flatten)]
     pub item: Item,
+    pub done: bool,
+    pub favorite: bool,
     pub tags: Option<String>,
 }
 
@@ -60,51 +231,236 @@ impl Item {
             .map_err(|e| Error::SelectError("items", e))
     }
 
+    /// Items from `user_id`'s subscriptions (or that they added themselves) with their state, from
+    /// the last `duration`. With `tags`, only items tagged with each of them (or a tag nested
+    /// under it) are included.
     pub async fn feed(
+        user_id: i64,
         duration: Duration,
         include_done: bool,
+        sort: FeedSort,
+        tags: &[&str],
         executor: impl Executor<'_, Database = super::DB>,
     ) -> Result<Vec<ItemWTags>, Error> {
         let cutoff_date_time = (chrono::Utc::now() - duration).naive_utc();
-        sqlx::query_as(
-            r#"
-            SELECT i.*, GROUP_CONCAT(t.name, ',') AS tags
-            FROM items i
-            LEFT JOIN items_to_tags it ON i.id = it.item_id
-            LEFT JOIN tags t ON it.tag_id = t.name
-            WHERE i.created_at >= ?1 AND (i.done = false OR ?2)
-            GROUP BY i.id
-            ORDER BY i.created_at DESC;
+        let sort = sort.sql();
+        let tags = tags.iter().unique().collect::<Vec<_>>();
+        let (tag_tree, tag_filter) = if tags.is_empty() {
+            (String::new(), String::new())
+        } else {
+            (
+                format!("WITH RECURSIVE {}", tag_tree_cte("tag_tree", 4, tags.len())),
+                format!(
+                    r#"
+            AND (SELECT COUNT(DISTINCT root) FROM tag_tree WHERE name IN ({ITEM_TAGS})) = {}
             "#,
-        )
-        .bind(cutoff_date_time)
-        .bind(include_done)
-        .fetch_all(executor)
+                    tags.len()
+                ),
+            )
+        };
+        let sql = format!(
+            r#"
+            {tag_tree}
+            {USER_ITEM_SELECT}
+            AND i.archived_at IS NULL
+            AND {sort} >= ?2 AND (COALESCE(ui.done, FALSE) = FALSE OR ?3)
+            {tag_filter}
+            ORDER BY {sort} DESC, i.id DESC;
+            "#
+        );
+        let mut query = sqlx::query_as(&sql)
+            .bind(user_id)
+            .bind(cutoff_date_time)
+            .bind(include_done);
+
+        for tag in tags {
+            query = query.bind(tag);
+        }
+
+        query
+            .fetch_all(executor)
+            .await
+            .map_err(|e| Error::SelectError("items", e))
+    }
+
+    /// Gets an item with `user_id`'s state if it is visible to them
+    pub async fn get_for_user(
+        id: i64,
+        user_id: i64,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Option<ItemWTags>, Error> {
+        sqlx::query_as(&format!(
+            r#"
+            {USER_ITEM_SELECT}
+            AND i.id = ?2;
+            "#
+        ))
+        .bind(user_id)
+        .bind(id)
+        .fetch_optional(executor)
         .await
         .map_err(|e| Error::SelectError("items", e))
     }
 
     pub async fn set_done(
         id: i64,
+        user_id: i64,
         done: bool,
         executor: impl Executor<'_, Database = super::DB>,
     ) -> Result<(), Error> {
         sqlx::query!(
             r#"
-                UPDATE items
+                INSERT INTO user_items (user_id, item_id, done)
+                VALUES (?1, ?2, ?3)
+                ON CONFLICT (user_id, item_id) DO UPDATE
                 SET
-                    done = ?
-                WHERE id = ?
+                    done = excluded.done
                 "#,
-            done,
+            user_id,
+            id,
+            done
+        )
+        .execute(executor)
+        .await
+        .map_err(|e| Error::UpdateError("user_items", e))
+        .map(|_| ())
+    }
+
+    pub async fn set_favorite(
+        id: i64,
+        user_id: i64,
+        favorite: bool,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+                INSERT INTO user_items (user_id, item_id, favorite)
+                VALUES (?1, ?2, ?3)
+                ON CONFLICT (user_id, item_id) DO UPDATE
+                SET
+                    favorite = excluded.favorite
+                "#,
+            user_id,
+            id,
+            favorite
+        )
+        .execute(executor)
+        .await
+        .map_err(|e| Error::UpdateError("user_items", e))
+        .map(|_| ())
+    }
+
+    /// Makes an item without a source show up in `user_id`'s feed
+    pub async fn track(
+        id: i64,
+        user_id: i64,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            "INSERT OR IGNORE INTO user_items (user_id, item_id) VALUES (?, ?)",
+            user_id,
             id
         )
         .execute(executor)
         .await
-        .map_err(|e| Error::UpdateError("items", e))
+        .map_err(|e| Error::InsertError("user_items", e))
         .map(|_| ())
     }
 
+    /// Removes `user_id`'s state for an item
+    pub async fn untrack(
+        id: i64,
+        user_id: i64,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            "DELETE FROM user_items WHERE user_id = ? AND item_id = ?",
+            user_id,
+            id
+        )
+        .execute(executor)
+        .await
+        .map_err(|e| Error::DeleteError("user_items", e))
+        .map(|_| ())
+    }
+
+    /// Deletes the item if it has no source and no user is tracking it anymore
+    pub async fn delete_if_orphaned(
+        id: i64,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+            DELETE FROM items
+            WHERE id = ?1
+                AND source_id IS NULL
+                AND NOT EXISTS (SELECT 1 FROM user_items WHERE item_id = ?1)
+            "#,
+            id
+        )
+        .execute(executor)
+        .await
+        .map_err(|e| Error::DeleteError("items", e))
+        .map(|_| ())
+    }
+
+    /// Every image url items have, to tell which cached thumbnails are still needed
+    pub async fn images(
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Vec<String>, Error> {
+        sqlx::query_scalar!(
+            r#"SELECT DISTINCT image AS "image!" FROM items WHERE image IS NOT NULL"#
+        )
+        .fetch_all(executor)
+        .await
+        .map_err(|e| Error::SelectError("items", e))
+    }
+
+    /// How many items `retention` would remove right now
+    pub async fn count_expired(
+        retention: Retention,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<ExpiredCounts, Error> {
+        sqlx::query_as(&format!(
+            r#"
+            {EXPIRED_ITEMS}
+            SELECT
+                COALESCE(SUM(done), 0) AS done,
+                COALESCE(SUM(NOT done), 0) AS unread
+            FROM expired;
+            "#
+        ))
+        .bind(retention.now)
+        .bind(retention.done_for)
+        .bind(retention.unread_for)
+        .fetch_one(executor)
+        .await
+        .map_err(|e| Error::SelectError("items", e))
+    }
+
+    /// Deletes (or archives) the items `retention` says are expired, returns how many
+    pub async fn expire(
+        retention: Retention,
+        archive: bool,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<u64, Error> {
+        let sql = if archive {
+            format!(
+                "{EXPIRED_ITEMS} UPDATE items SET archived_at = ?1 WHERE id IN (SELECT id FROM expired);"
+            )
+        } else {
+            format!("{EXPIRED_ITEMS} DELETE FROM items WHERE id IN (SELECT id FROM expired);")
+        };
+        sqlx::query(&sql)
+            .bind(retention.now)
+            .bind(retention.done_for)
+            .bind(retention.unread_for)
+            .execute(executor)
+            .await
+            .map_err(|e| Error::DeleteError("items", e))
+            .map(|result| result.rows_affected())
+    }
+
     pub async fn get_by_id(
         id: i64,
         executor: impl Executor<'_, Database = super::DB>,
@@ -121,36 +477,210 @@ impl Item {
         }
     }
 
-    pub async fn all_with_tags(
-        tags: &[&str],
+    /// Hash of the fields a feed can change after publishing
+    pub fn compute_content_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        for field in [
+            Some(&self.link),
+            self.title.as_ref(),
+            self.description.as_ref(),
+            self.author.as_ref(),
+        ] {
+            // Length prefixes keep ("ab", "c") and ("a", "bc") apart, 0 marks a missing field
+            let len = field.map_or(0, |field| field.len() as u64 + 1);
+            hasher.update(len.to_le_bytes());
+            hasher.update(field.map_or("", String::as_str));
+        }
+        hex::encode(hasher.finalize())
+    }
+
+    /// The stored version of an item built from a feed: the one with its guid, or its link if it
+    /// has no guid. Items from other sources are never matched so two feeds linking the same page
+    /// don't fight over it.
+    pub async fn get_existing(
+        &self,
         executor: impl Executor<'_, Database = super::DB>,
-    ) -> Result<Vec<Self>, Error> {
-        let sql = format!(
+    ) -> Result<Option<Self>, Error> {
+        sqlx::query_as!(
+            Item,
             r#"
-		SELECT i.*
-		FROM items i
-		JOIN items_to_tags st ON s.id = st.item_id
-		JOIN tags t ON st.tag_id = t.name
-		WHERE t.name IN ({})
-		GROUP BY s.id
-		HAVING COUNT(DISTINCT t.name) = {};
-		"#,
-            tags.iter()
-                .map(|_| "?")
-                .intersperse(",")
-                .collect::<Box<str>>(),
-            tags.len() // Should be safe to directly interpolate into query
-        );
-        let mut query = sqlx::query_as(&sql);
+        SELECT *
+        FROM items
+        WHERE source_id IS ?1 AND (guid = ?2 OR (?2 IS NULL AND guid IS NULL AND link = ?3))
+        "#,
+            self.source_id,
+            self.guid,
+            self.link
+        )
+        .fetch_optional(executor)
+        .await
+        .map_err(|e| Error::SelectError("items", e))
+    }
 
-        for tag in tags {
-            query = query.bind(tag);
-        }
+    /// Moves users' state, queue entries and tags from item `from` onto `into`, then deletes
+    /// `from`. For items of a deleted source that link the same page as one that's already kept.
+    pub async fn merge_into(
+        from: i64,
+        into: i64,
+        conn: &mut sqlx::SqliteConnection,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+        INSERT INTO user_items (user_id, item_id, done, favorite)
+        SELECT user_id, ?2, done, favorite FROM user_items WHERE item_id = ?1
+        ON CONFLICT (user_id, item_id) DO UPDATE
+        SET
+            done = done OR excluded.done,
+            favorite = favorite OR excluded.favorite
+        "#,
+            from,
+            into
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| Error::InsertError("user_items", e))?;
+        sqlx::query!(
+            r#"
+        INSERT OR IGNORE INTO read_later (user_id, item_id, position, progress, word_count, archived_at)
+        SELECT user_id, ?2, position, progress, word_count, archived_at
+        FROM read_later
+        WHERE item_id = ?1
+        "#,
+            from,
+            into
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| Error::InsertError("read_later", e))?;
+        sqlx::query!(
+            r#"
+        INSERT OR IGNORE INTO items_to_tags (item_id, tag_id, created_at)
+        SELECT ?2, tag_id, created_at FROM items_to_tags WHERE item_id = ?1
+        "#,
+            from,
+            into
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| Error::InsertError("items_to_tags", e))?;
+        sqlx::query!(
+            r#"
+        INSERT OR IGNORE INTO user_items_to_tags (user_id, item_id, tag_id, created_at)
+        SELECT user_id, ?2, tag_id, created_at FROM user_items_to_tags WHERE item_id = ?1
+        "#,
+            from,
+            into
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| Error::InsertError("user_items_to_tags", e))?;
 
-        query
-            .fetch_all(executor)
+        // Deleting cascades to whatever still points at it
+        sqlx::query!("DELETE FROM items WHERE id = ?", from)
+            .execute(&mut *conn)
             .await
-            .map_err(|e| Error::SelectError("items", e))
+            .map_err(|e| Error::DeleteError("items", e))
+            .map(|_| ())
+    }
+
+    /// Replaces the content of item `id` with `self`'s, keeping what it was as a revision
+    pub async fn update_content(
+        &self,
+        id: i64,
+        sqlite: &sqlx::Pool<super::DB>,
+    ) -> Result<(), Error> {
+        let now = chrono::Utc::now().naive_utc();
+        let content_hash = self.compute_content_hash();
+        let mut tx = sqlite
+            .begin()
+            .await
+            .map_err(|e| Error::UpdateError("items", e))?;
+
+        sqlx::query!(
+            r#"
+        INSERT INTO item_revisions (item_id, link, title, description, author)
+        SELECT id, link, title, description, author
+        FROM items
+        WHERE id = ?1
+        "#,
+            id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| Error::InsertError("item_revisions", e))?;
+
+        sqlx::query!(
+            r#"
+        DELETE FROM item_revisions
+        WHERE item_id = ?1 AND id NOT IN (
+            SELECT id FROM item_revisions WHERE item_id = ?1 ORDER BY id DESC LIMIT ?2
+        )
+        "#,
+            id,
+            MAX_REVISIONS
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| Error::DeleteError("item_revisions", e))?;
+
+        sqlx::query!(
+            r#"
+        UPDATE items
+        SET
+            link = ?1,
+            title = ?2,
+            description = ?3,
+            author = ?4,
+            content_hash = ?5,
+            content_updated_at = ?6,
+            updated_at = ?6
+        WHERE id = ?7
+        "#,
+            self.link,
+            self.title,
+            self.description,
+            self.author,
+            content_hash,
+            now,
+            id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| Error::UpdateError("items", e))?;
+
+        tx.commit()
+            .await
+            .map_err(|e| Error::UpdateError("items", e))
+    }
+
+    /// Previous versions of an item, newest first
+    pub async fn revisions(
+        id: i64,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Vec<ItemRevision>, Error> {
+        sqlx::query_as!(
+            ItemRevision,
+            "SELECT * FROM item_revisions WHERE item_id = ?1 ORDER BY id DESC",
+            id
+        )
+        .fetch_all(executor)
+        .await
+        .map_err(|e| Error::SelectError("item_revisions", e))
+    }
+
+    /// The item added by hand with `link`, feeds can have their own items with the same link
+    pub async fn get_by_link(
+        link: &str,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Option<Self>, Error> {
+        sqlx::query_as!(
+            Item,
+            "SELECT * FROM items WHERE link = ?1 AND source_id IS NULL",
+            link
+        )
+        .fetch_optional(executor)
+        .await
+        .map_err(|e| Error::SelectError("items", e))
     }
 
     /// Inserts self into the database and populates its `id` field
@@ -158,10 +688,11 @@ impl Item {
         &mut self,
         executor: impl Executor<'_, Database = super::DB>,
     ) -> Result<(), Error> {
+        let content_hash = self.compute_content_hash();
         let id = sqlx::query!(
             r#"
-		INSERT OR IGNORE INTO items(link, title, description, author, published, source_link, image, favorite, source_id)
-		VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+		INSERT INTO items(link, title, description, author, published, source_link, image, source_id, guid, guid_is_permalink, content_hash)
+		VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
 		"#,
             self.link,
             self.title,
@@ -170,8 +701,10 @@ impl Item {
             self.published,
             self.source_link,
 			self.image,
-			self.favorite,
-			self.source_id
+			self.source_id,
+			self.guid,
+			self.guid_is_permalink,
+			content_hash
         )
         .execute(executor)
         .await
@@ -179,6 +712,7 @@ impl Item {
         .last_insert_rowid();
 
         self.id = id;
+        self.content_hash = Some(content_hash);
         Ok(())
     }
 
@@ -214,6 +748,59 @@ impl Item {
             .map(|_| ())
     }
 
+    /// Tags an item for `user_id` only
+    pub async fn add_user_tags(
+        id: i64,
+        user_id: i64,
+        tags: &[&str],
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<(), Error> {
+        let sql = format!(
+            r#"
+			INSERT OR IGNORE INTO user_items_to_tags (user_id, item_id, tag_id)
+			SELECT {}, {}, name
+			FROM tags
+			WHERE name IN ({});
+		"#,
+            user_id,
+            id,
+            tags.iter()
+                .map(|_| "?")
+                .intersperse(",")
+                .collect::<Box<str>>()
+        );
+
+        let mut query = sqlx::query(&sql);
+
+        for tag in tags {
+            query = query.bind(tag);
+        }
+
+        query
+            .execute(executor)
+            .await
+            .map_err(|e| Error::InsertError("user_items_to_tags", e))
+            .map(|_| ())
+    }
+
+    pub async fn remove_user_tag(
+        id: i64,
+        user_id: i64,
+        tag: &str,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            "DELETE FROM user_items_to_tags WHERE user_id = ? AND item_id = ? AND tag_id = ?",
+            user_id,
+            id,
+            tag
+        )
+        .execute(executor)
+        .await
+        .map_err(|e| Error::DeleteError("user_items_to_tags", e))
+        .map(|_| ())
+    }
+
     pub async fn remove_tag(
         id: i64,
         tag: &str,
@@ -237,7 +824,7 @@ impl Item {
         sqlx::query!("DELETE FROM items WHERE id = ?", id)
             .execute(executor)
             .await
-            .map_err(|e| Error::DeleteError("tags", e))
+            .map_err(|e| Error::DeleteError("items", e))
             .map(|_| ())
     }
 }
@@ -1,16 +1,93 @@
 use sqlx::Sqlite;
 use thiserror::Error;
 
+pub mod api_token;
+pub mod export;
 pub mod item;
+pub mod read_later;
+pub mod smart_feed;
 pub mod source;
+pub mod stats;
 pub mod tag;
+pub mod tombstone;
+pub mod user;
+pub mod utc;
+pub mod webhook;
 
-pub use item::Item;
+pub use api_token::ApiToken;
+pub use item::{FeedSort, Item};
+pub use read_later::ReadLater;
+pub use smart_feed::SmartFeed;
 pub use source::Source;
-pub use tag::Tag;
+pub use stats::Stats;
+pub use tag::{CategoryTags, Tag, TagAlias};
+pub use tombstone::Tombstone;
+pub use user::User;
+pub use webhook::{Webhook, WebhookDelivery};
 
 type DB = Sqlite;
 
+/// Runs the migrations on one connection with foreign keys off, which SQLite needs to rebuild a
+/// table others reference (dropping it would cascade into them), then warns about rows that no
+/// longer point anywhere
+pub async fn migrate(sqlite: &sqlx::Pool<DB>) -> Result<(), Error> {
+    let mut conn = sqlite
+        .acquire()
+        .await
+        .map_err(|e| Error::UpdateError("sqlite_master", e))?;
+    sqlx::query("PRAGMA foreign_keys = OFF")
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| Error::UpdateError("sqlite_master", e))?;
+    let migrated = sqlx::migrate!().run(&mut *conn).await;
+    let violations = sqlx::query("PRAGMA foreign_key_check")
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(|e| Error::SelectError("sqlite_master", e));
+    sqlx::query("PRAGMA foreign_keys = ON")
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| Error::UpdateError("sqlite_master", e))?;
+
+    migrated.map_err(Error::MigrateError)?;
+    let violations = violations?.len();
+    if violations > 0 {
+        tracing::warn!("{violations} rows reference rows that don't exist");
+    }
+    Ok(())
+}
+
+/// Lets SQLite update its query planner statistics, cheap enough to run regularly
+pub async fn optimize(executor: impl sqlx::Executor<'_, Database = DB>) -> Result<(), Error> {
+    sqlx::query("PRAGMA optimize")
+        .execute(executor)
+        .await
+        .map_err(|e| Error::UpdateError("sqlite_master", e))
+        .map(|_| ())
+}
+
+/// Rebuilds the database file to give space freed by deletes back to the OS
+pub async fn vacuum(executor: impl sqlx::Executor<'_, Database = DB>) -> Result<(), Error> {
+    sqlx::query("VACUUM")
+        .execute(executor)
+        .await
+        .map_err(|e| Error::UpdateError("sqlite_master", e))
+        .map(|_| ())
+}
+
+/// Writes a consistent copy of the database to `path` while it's in use, `path` must not exist
+pub async fn backup_into(
+    path: &str,
+    executor: impl sqlx::Executor<'_, Database = DB>,
+) -> Result<(), Error> {
+    sqlx::query("VACUUM INTO ?")
+        .bind(path)
+        .execute(executor)
+        .await
+        .map_err(|e| Error::SelectError("sqlite_master", e))
+        .map(|_| ())
+}
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("Error inserting row into {0}: {1:?}")]
@@ -23,10 +100,12 @@ pub enum Error {
     DeleteError(&'static str, sqlx::Error),
     #[error("Row for {0} is invalid because \"{1}\"")]
     InvalidRow(&'static str, String),
+    #[error("Error migrating the database: {0:?}")]
+    MigrateError(sqlx::migrate::MigrateError),
 }
 
 impl Error {
-    /// Panics if this is an invalid row error
+    /// Panics if this is an invalid row or migration error
     pub fn into_sqlx_error(self) -> sqlx::Error {
         match self {
             Error::InsertError(_, error) => error,
@@ -34,6 +113,7 @@ impl Error {
             Error::SelectError(_, error) => error,
             Error::DeleteError(_, error) => error,
             Error::InvalidRow(_, _) => panic!("No sqlx error"),
+            Error::MigrateError(_) => panic!("No sqlx error"),
         }
     }
 }
@@ -0,0 +1,260 @@
+use chrono::NaiveDateTime;
+use itertools::Itertools;
+use serde::Serialize;
+use sqlx::prelude::*;
+
+use super::{
+    item::{ItemWTags, USER_ITEM_SELECT},
+    Error,
+};
+
+/// Average adult silent reading speed
+const WORDS_PER_MINUTE: i64 = 230;
+
+/// An item in a user's read later queue
+#[derive(Debug, Clone, FromRow, Serialize, ts_rs::TS)]
+#[ts(export, export_to = "../web/src/types/ReadLater.ts")]
+pub struct ReadLater {
+    #[ts(type = "number")]
+    pub user_id: i64,
+
+    #[ts(type = "number")]
+    pub item_id: i64,
+
+    /// Lower comes first
+    #[ts(type = "number")]
+    pub position: i64,
+
+    /// Percent of the item read
+    #[ts(type = "number")]
+    pub progress: i64,
+
+    /// Words in the item's extracted content
+    #[ts(type = "number | null")]
+    pub word_count: Option<i64>,
+
+    /// Set once the item is finished
+    #[serde(serialize_with = "super::utc::option::serialize")]
+    #[ts(type = "string | null")]
+    pub archived_at: Option<NaiveDateTime>,
+
+    #[serde(serialize_with = "super::utc::serialize")]
+    #[ts(type = "string")]
+    pub created_at: NaiveDateTime,
+
+    #[serde(serialize_with = "super::utc::serialize")]
+    #[ts(type = "string")]
+    pub updated_at: NaiveDateTime,
+}
+
+impl ReadLater {
+    /// Minutes it takes to read the whole item, rounded up
+    pub fn reading_minutes(&self) -> Option<i64> {
+        self.word_count
+            .map(|words| (words + WORDS_PER_MINUTE - 1) / WORDS_PER_MINUTE)
+    }
+
+    /// The queue in order, or the finished items with the most recently finished first
+    pub async fn get_for_user(
+        user_id: i64,
+        archived: bool,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Vec<Self>, Error> {
+        sqlx::query_as!(
+            ReadLater,
+            r#"
+        SELECT *
+        FROM read_later
+        WHERE user_id = ?1 AND (archived_at IS NOT NULL) = ?2
+        ORDER BY
+            CASE WHEN ?2 THEN NULL ELSE position END,
+            archived_at DESC,
+            item_id
+        "#,
+            user_id,
+            archived
+        )
+        .fetch_all(executor)
+        .await
+        .map_err(|e| Error::SelectError("read_later", e))
+    }
+
+    pub async fn get(
+        user_id: i64,
+        item_id: i64,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Option<Self>, Error> {
+        sqlx::query_as!(
+            ReadLater,
+            "SELECT * FROM read_later WHERE user_id = ?1 AND item_id = ?2",
+            user_id,
+            item_id
+        )
+        .fetch_optional(executor)
+        .await
+        .map_err(|e| Error::SelectError("read_later", e))
+    }
+
+    /// The items in `user_id`'s queue (or finished ones), in no particular order
+    pub async fn items(
+        user_id: i64,
+        archived: bool,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Vec<ItemWTags>, Error> {
+        sqlx::query_as(&format!(
+            r#"
+            {USER_ITEM_SELECT}
+            AND i.id IN (
+                SELECT item_id
+                FROM read_later
+                WHERE user_id = ?1 AND (archived_at IS NOT NULL) = ?2
+            );
+            "#
+        ))
+        .bind(user_id)
+        .bind(archived)
+        .fetch_all(executor)
+        .await
+        .map_err(|e| Error::SelectError("items", e))
+    }
+
+    /// Adds an item to the end of the queue. Finished items go back in the queue from the start,
+    /// items already in it stay where they are.
+    pub async fn add(
+        user_id: i64,
+        item_id: i64,
+        word_count: Option<i64>,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+        INSERT INTO read_later (user_id, item_id, word_count, position)
+        VALUES (
+            ?1,
+            ?2,
+            ?3,
+            (SELECT COALESCE(MAX(position), -1) + 1 FROM read_later WHERE user_id = ?1)
+        )
+        ON CONFLICT (user_id, item_id) DO UPDATE
+        SET
+            position = excluded.position,
+            progress = 0,
+            word_count = COALESCE(excluded.word_count, word_count),
+            archived_at = NULL
+        WHERE archived_at IS NOT NULL
+        "#,
+            user_id,
+            item_id,
+            word_count
+        )
+        .execute(executor)
+        .await
+        .map_err(|e| Error::InsertError("read_later", e))
+        .map(|_| ())
+    }
+
+    /// Saves how far into the item the user is, finishing it at 100%. Archived items stay archived,
+    /// `add` puts them back at the end of the queue. Returns whether it's queued.
+    pub async fn set_progress(
+        user_id: i64,
+        item_id: i64,
+        progress: i64,
+        now: NaiveDateTime,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<bool, Error> {
+        sqlx::query!(
+            r#"
+        UPDATE read_later
+        SET
+            progress = ?3,
+            archived_at = COALESCE(archived_at, CASE WHEN ?3 >= 100 THEN ?4 END)
+        WHERE user_id = ?1 AND item_id = ?2
+        "#,
+            user_id,
+            item_id,
+            progress,
+            now
+        )
+        .execute(executor)
+        .await
+        .map_err(|e| Error::UpdateError("read_later", e))
+        .map(|result| result.rows_affected() > 0)
+    }
+
+    /// Finishes an item without reading the rest. Returns whether it's queued.
+    pub async fn archive(
+        user_id: i64,
+        item_id: i64,
+        now: NaiveDateTime,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<bool, Error> {
+        sqlx::query!(
+            r#"
+        UPDATE read_later
+        SET archived_at = COALESCE(archived_at, ?3)
+        WHERE user_id = ?1 AND item_id = ?2
+        "#,
+            user_id,
+            item_id,
+            now
+        )
+        .execute(executor)
+        .await
+        .map_err(|e| Error::UpdateError("read_later", e))
+        .map(|result| result.rows_affected() > 0)
+    }
+
+    /// Moves `item_ids` to the front of the queue in that order, the rest keep their order after
+    /// them. Ids that aren't queued are ignored.
+    pub async fn reorder(
+        user_id: i64,
+        item_ids: &[i64],
+        sqlite: &sqlx::Pool<super::DB>,
+    ) -> Result<(), Error> {
+        let mut tx = sqlite
+            .begin()
+            .await
+            .map_err(|e| Error::UpdateError("read_later", e))?;
+        let queue = Self::get_for_user(user_id, false, &mut *tx).await?;
+        let queued = queue.iter().map(|entry| entry.item_id).collect::<Vec<_>>();
+        let mut order = item_ids
+            .iter()
+            .copied()
+            .filter(|id| queued.contains(id))
+            .unique()
+            .collect::<Vec<_>>();
+        order.extend(queued.iter().filter(|id| !item_ids.contains(id)));
+
+        for (position, item_id) in order.into_iter().enumerate() {
+            let position = position as i64;
+            sqlx::query!(
+                "UPDATE read_later SET position = ?3 WHERE user_id = ?1 AND item_id = ?2",
+                user_id,
+                item_id,
+                position
+            )
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| Error::UpdateError("read_later", e))?;
+        }
+        tx.commit()
+            .await
+            .map_err(|e| Error::UpdateError("read_later", e))
+    }
+
+    pub async fn delete(
+        user_id: i64,
+        item_id: i64,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            "DELETE FROM read_later WHERE user_id = ?1 AND item_id = ?2",
+            user_id,
+            item_id
+        )
+        .execute(executor)
+        .await
+        .map_err(|e| Error::DeleteError("read_later", e))
+        .map(|_| ())
+    }
+}
@@ -0,0 +1,386 @@
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use sqlx::prelude::*;
+
+use super::{
+    item::{ItemWTags, ITEM_TAGS, USER_ITEM_SELECT},
+    tag::tag_tree_cte,
+    Error, FeedSort,
+};
+
+/// A saved view of a user's items
+#[derive(Debug, Clone, Serialize, Deserialize, ts_rs::TS)]
+#[ts(export, export_to = "../web/src/types/SmartFeed.ts")]
+pub struct SmartFeed {
+    #[ts(type = "number")]
+    #[serde(skip_deserializing)]
+    pub id: i64,
+
+    #[ts(type = "number")]
+    #[serde(skip_deserializing)]
+    pub user_id: i64,
+
+    pub name: String,
+
+    pub query: SmartFeedQuery,
+
+    #[serde(skip_deserializing)]
+    pub created_at: NaiveDateTime,
+
+    #[serde(skip_deserializing)]
+    pub updated_at: NaiveDateTime,
+}
+
+/// Which items are in a smart feed, every filter that's set has to match. Tags include the tags
+/// nested under them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ts_rs::TS)]
+#[ts(export, export_to = "../web/src/types/SmartFeedQuery.ts")]
+pub struct SmartFeedQuery {
+    /// Items need every one of these tags
+    #[serde(default)]
+    pub all_tags: Vec<String>,
+
+    /// Items need at least one of these tags
+    #[serde(default)]
+    pub any_tags: Vec<String>,
+
+    /// Items can't have any of these tags
+    #[serde(default)]
+    pub none_tags: Vec<String>,
+
+    /// Only items from these sources
+    #[ts(type = "Array<number>")]
+    #[serde(default)]
+    pub source_ids: Vec<i64>,
+
+    /// Only items from sources marked as favorite
+    #[serde(default)]
+    pub favorite_sources: bool,
+
+    #[serde(default)]
+    pub done: Option<bool>,
+
+    #[serde(default)]
+    pub favorite: Option<bool>,
+
+    /// Only items newer than this, like `3days`, measured by `sort`
+    #[ts(type = "string | null")]
+    #[serde(default, with = "humantime_serde")]
+    pub max_age: Option<Duration>,
+
+    /// Text the title, description or author has to contain, ignoring ASCII case
+    #[serde(default)]
+    pub text: Option<String>,
+
+    #[serde(default)]
+    pub sort: FeedSort,
+}
+
+/// Values bound to a compiled query, in parameter order
+enum Param {
+    Int(i64),
+    Text(String),
+    DateTime(NaiveDateTime),
+}
+
+#[derive(Default)]
+struct Params(Vec<Param>);
+
+impl Params {
+    /// Adds a parameter and returns its placeholder
+    fn push(&mut self, param: Param) -> String {
+        self.0.push(param);
+        format!("?{}", self.0.len())
+    }
+
+    /// Adds the tags and returns a tag tree over them
+    fn tag_tree(&mut self, table: &str, tags: &[String]) -> String {
+        let first_param = self.0.len() + 1;
+        self.0
+            .extend(tags.iter().map(|tag| Param::Text(tag.clone())));
+        tag_tree_cte(table, first_param, tags.len())
+    }
+}
+
+impl SmartFeedQuery {
+    /// Swaps `from` for `into` in the tag filters, returns whether anything changed
+    fn retag(&mut self, from: &str, into: &str) -> bool {
+        let mut changed = false;
+        for tags in [&mut self.all_tags, &mut self.any_tags, &mut self.none_tags] {
+            if tags.iter().any(|tag| tag == from) {
+                *tags = tags
+                    .iter()
+                    .map(|tag| if tag == from { into } else { tag })
+                    .unique()
+                    .map(String::from)
+                    .collect();
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// Compiles the filters into one query over `user_id`'s items, newest first
+    fn sql(&self, user_id: i64, limit: i64) -> (String, Params) {
+        let mut params = Params::default();
+        // USER_ITEM_SELECT expects the user as ?1
+        params.push(Param::Int(user_id));
+
+        let all_tags = self.all_tags.iter().unique().cloned().collect::<Vec<_>>();
+        let any_tags = self.any_tags.iter().unique().cloned().collect::<Vec<_>>();
+        let none_tags = self.none_tags.iter().unique().cloned().collect::<Vec<_>>();
+        let mut trees = Vec::new();
+        let mut filters = vec![String::from("i.archived_at IS NULL")];
+        if !all_tags.is_empty() {
+            trees.push(params.tag_tree("all_tags", &all_tags));
+            filters.push(format!(
+                "(SELECT COUNT(DISTINCT root) FROM all_tags WHERE name IN ({ITEM_TAGS})) = {}",
+                all_tags.len()
+            ));
+        }
+        if !any_tags.is_empty() {
+            trees.push(params.tag_tree("any_tags", &any_tags));
+            filters.push(format!(
+                "EXISTS (SELECT 1 FROM any_tags WHERE name IN ({ITEM_TAGS}))"
+            ));
+        }
+        if !none_tags.is_empty() {
+            trees.push(params.tag_tree("none_tags", &none_tags));
+            filters.push(format!(
+                "NOT EXISTS (SELECT 1 FROM none_tags WHERE name IN ({ITEM_TAGS}))"
+            ));
+        }
+
+        if !self.source_ids.is_empty() {
+            let ids = self
+                .source_ids
+                .iter()
+                .map(|id| params.push(Param::Int(*id)))
+                .join(", ");
+            filters.push(format!("i.source_id IN ({ids})"));
+        }
+        if self.favorite_sources {
+            filters.push("i.source_id IN (SELECT id FROM sources WHERE favorite)".into());
+        }
+        if let Some(done) = self.done {
+            let done = params.push(Param::Int(done.into()));
+            filters.push(format!("COALESCE(ui.done, FALSE) = {done}"));
+        }
+        if let Some(favorite) = self.favorite {
+            let favorite = params.push(Param::Int(favorite.into()));
+            filters.push(format!("COALESCE(ui.favorite, FALSE) = {favorite}"));
+        }
+        let sort = self.sort.sql();
+        if let Some(max_age) = self.max_age {
+            let cutoff = chrono::Duration::from_std(max_age)
+                .ok()
+                .and_then(|max_age| chrono::Utc::now().naive_utc().checked_sub_signed(max_age))
+                .unwrap_or(NaiveDateTime::MIN);
+            let cutoff = params.push(Param::DateTime(cutoff));
+            filters.push(format!("{sort} >= {cutoff}"));
+        }
+        if let Some(text) = self.text.as_deref().filter(|text| !text.is_empty()) {
+            let escaped = text
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            let pattern = params.push(Param::Text(format!("%{escaped}%")));
+            filters.push(format!(
+                r#"(
+                    i.title LIKE {pattern} ESCAPE '\'
+                    OR i.description LIKE {pattern} ESCAPE '\'
+                    OR i.author LIKE {pattern} ESCAPE '\'
+                )"#
+            ));
+        }
+        let limit = params.push(Param::Int(limit));
+
+        let with = if trees.is_empty() {
+            String::new()
+        } else {
+            format!("WITH RECURSIVE {}", trees.join(", "))
+        };
+        let filters = filters.join("\n            AND ");
+        let sql = format!(
+            r#"
+            {with}
+            {USER_ITEM_SELECT}
+            AND {filters}
+            ORDER BY {sort} DESC, i.id DESC
+            LIMIT {limit};
+            "#
+        );
+        (sql, params)
+    }
+}
+
+struct SmartFeedRow {
+    id: i64,
+    user_id: i64,
+    name: String,
+    query: String,
+    created_at: NaiveDateTime,
+    updated_at: NaiveDateTime,
+}
+
+impl TryFrom<SmartFeedRow> for SmartFeed {
+    type Error = Error;
+
+    fn try_from(row: SmartFeedRow) -> Result<Self, Self::Error> {
+        Ok(Self {
+            query: serde_json::from_str(&row.query)
+                .map_err(|err| Error::InvalidRow("smart_feeds", err.to_string()))?,
+            id: row.id,
+            user_id: row.user_id,
+            name: row.name,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        })
+    }
+}
+
+impl SmartFeed {
+    pub async fn get_for_user(
+        user_id: i64,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Vec<Self>, Error> {
+        sqlx::query_as!(
+            SmartFeedRow,
+            "SELECT * FROM smart_feeds WHERE user_id = ?1 ORDER BY name",
+            user_id
+        )
+        .fetch_all(executor)
+        .await
+        .map_err(|e| Error::SelectError("smart_feeds", e))?
+        .into_iter()
+        .map(Self::try_from)
+        .collect()
+    }
+
+    /// Gets a smart feed if it belongs to `user_id`
+    pub async fn get_by_id(
+        id: i64,
+        user_id: i64,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Option<Self>, Error> {
+        sqlx::query_as!(
+            SmartFeedRow,
+            "SELECT * FROM smart_feeds WHERE id = ?1 AND user_id = ?2",
+            id,
+            user_id
+        )
+        .fetch_optional(executor)
+        .await
+        .map_err(|e| Error::SelectError("smart_feeds", e))?
+        .map(Self::try_from)
+        .transpose()
+    }
+
+    /// The items in this feed right now, at most `limit` of them
+    pub async fn items(
+        &self,
+        limit: i64,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Vec<ItemWTags>, Error> {
+        let (sql, params) = self.query.sql(self.user_id, limit);
+        let mut query = sqlx::query_as(&sql);
+
+        for param in params.0 {
+            query = match param {
+                Param::Int(value) => query.bind(value),
+                Param::Text(value) => query.bind(value),
+                Param::DateTime(value) => query.bind(value),
+            };
+        }
+
+        query
+            .fetch_all(executor)
+            .await
+            .map_err(|e| Error::SelectError("items", e))
+    }
+
+    /// Inserts self into the database and populates its `id` field
+    pub async fn insert(
+        &mut self,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<(), Error> {
+        let query = serde_json::to_string(&self.query).unwrap();
+        let row = sqlx::query!(
+            r#"
+		INSERT INTO smart_feeds(user_id, name, query)
+		VALUES (?1, ?2, ?3)
+		RETURNING id, created_at, updated_at
+		"#,
+            self.user_id,
+            self.name,
+            query
+        )
+        .fetch_one(executor)
+        .await
+        .map_err(|e| Error::InsertError("smart_feeds", e))?;
+
+        self.id = row.id;
+        self.created_at = row.created_at;
+        self.updated_at = row.updated_at;
+        Ok(())
+    }
+
+    /// Saves the name and query, returns whether the smart feed exists
+    pub async fn update(
+        &self,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<bool, Error> {
+        let query = serde_json::to_string(&self.query).unwrap();
+        sqlx::query!(
+            "UPDATE smart_feeds SET name = ?1, query = ?2 WHERE id = ?3 AND user_id = ?4",
+            self.name,
+            query,
+            self.id,
+            self.user_id
+        )
+        .execute(executor)
+        .await
+        .map_err(|e| Error::UpdateError("smart_feeds", e))
+        .map(|result| result.rows_affected() > 0)
+    }
+
+    /// Points every smart feed filtering by tag `from` at `into` instead, for renames and merges
+    pub(super) async fn retag(
+        from: &str,
+        into: &str,
+        conn: &mut sqlx::SqliteConnection,
+    ) -> Result<(), Error> {
+        let feeds = sqlx::query_as!(SmartFeedRow, "SELECT * FROM smart_feeds")
+            .fetch_all(&mut *conn)
+            .await
+            .map_err(|e| Error::SelectError("smart_feeds", e))?
+            .into_iter()
+            .map(Self::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        for mut feed in feeds {
+            if feed.query.retag(from, into) {
+                feed.update(&mut *conn).await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn delete(
+        id: i64,
+        user_id: i64,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            "DELETE FROM smart_feeds WHERE id = ?1 AND user_id = ?2",
+            id,
+            user_id
+        )
+        .execute(executor)
+        .await
+        .map_err(|e| Error::DeleteError("smart_feeds", e))
+        .map(|_| ())
+    }
+}
@@ -2,7 +2,7 @@ use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use sqlx::prelude::*;
 
-use super::{Error, Tag};
+use super::{CategoryTags, Error, Item, Tag, User};
 
 #[derive(Debug, FromRow, Serialize, Deserialize, ts_rs::TS)]
 #[ts(export, export_to = "../web/src/types/Source.ts")]
@@ -26,7 +26,9 @@ pub struct Source {
     #[serde(default)]
     pub favorite: bool,
 
-    #[serde(default)]
+    /// Items published before this are ignored, in UTC
+    #[serde(default, with = "super::utc::option")]
+    #[ts(type = "string | null")]
     pub min_date: Option<chrono::NaiveDateTime>,
 
     #[serde(skip_deserializing)]
@@ -34,9 +36,86 @@ pub struct Source {
 
     #[serde(skip_deserializing)]
     pub updated_at: chrono::NaiveDateTime,
+
+    /// WebSub hub the feed advertised, if we subscribed to it
+    #[serde(skip_deserializing)]
+    pub websub_hub: Option<String>,
+
+    #[serde(skip_deserializing)]
+    pub websub_topic: Option<String>,
+
+    #[serde(skip)]
+    #[ts(skip)]
+    pub websub_secret: Option<String>,
+
+    #[serde(skip_deserializing)]
+    pub websub_lease_expires_at: Option<chrono::NaiveDateTime>,
+
+    /// When we last asked the hub to subscribe
+    #[serde(skip)]
+    #[ts(skip)]
+    pub websub_requested_at: Option<chrono::NaiveDateTime>,
+
+    /// Requests in a row the hub hasn't verified yet
+    #[serde(skip)]
+    #[ts(skip)]
+    pub websub_attempts: i64,
+
+    /// Overrides how many seconds items everyone is done with are kept, 0 keeps them forever
+    #[ts(type = "number | null")]
+    #[serde(default)]
+    pub retain_done_for: Option<i64>,
+
+    /// Overrides how many seconds unfinished items are kept, 0 keeps them forever
+    #[ts(type = "number | null")]
+    #[serde(default)]
+    pub retain_unread_for: Option<i64>,
+
+    /// Extra headers sent when fetching the feed, a JSON object of names to values
+    #[serde(skip)]
+    #[ts(skip)]
+    pub headers: Option<String>,
+
+    /// Basic auth for fetching the feed
+    #[serde(skip_deserializing)]
+    pub auth_username: Option<String>,
+
+    #[serde(skip)]
+    #[ts(skip)]
+    pub auth_password: Option<String>,
+
+    /// Overrides `category_tags` from the config, one of `all`, `existing` or `ignore`
+    #[ts(as = "Option<CategoryTags>")]
+    #[serde(default)]
+    pub category_tags: Option<String>,
+}
+
+/// What happens to a source's items when it's deleted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnSourceDelete {
+    Delete,
+    /// Keep items someone favorited and delete the rest
+    #[default]
+    KeepFavorites,
+    /// Keep every item, they stay visible to users who are done with or favorited them
+    Keep,
 }
 
 impl Source {
+    /// How this source's categories become tags, `default` unless it overrides it
+    pub fn category_tags_or(&self, default: CategoryTags) -> CategoryTags {
+        self.category_tags
+            .as_deref()
+            .and_then(|category_tags| category_tags.parse().ok())
+            .unwrap_or(default)
+    }
+
+    /// Whether fetching the feed needs headers or basic auth
+    pub fn has_credentials(&self) -> bool {
+        self.headers.is_some() || self.auth_username.is_some()
+    }
+
     pub async fn get_all(
         executor: impl Executor<'_, Database = super::DB>,
     ) -> Result<Vec<Self>, Error> {
@@ -46,6 +125,74 @@ impl Source {
             .map_err(|e| Error::SelectError("sources", e))
     }
 
+    /// Sources that at least one user is subscribed to
+    pub async fn get_all_subscribed(
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Vec<Self>, Error> {
+        sqlx::query_as!(
+            Source,
+            r#"
+        SELECT *
+        FROM sources
+        WHERE id IN (SELECT source_id FROM subscriptions);
+        "#
+        )
+        .fetch_all(executor)
+        .await
+        .map_err(|e| Error::SelectError("sources", e))
+    }
+
+    pub async fn get_for_user(
+        user_id: i64,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Vec<Self>, Error> {
+        sqlx::query_as!(
+            Source,
+            r#"
+        SELECT s.*
+        FROM sources s
+        JOIN subscriptions sub ON s.id = sub.source_id
+        WHERE sub.user_id = ?;
+        "#,
+            user_id
+        )
+        .fetch_all(executor)
+        .await
+        .map_err(|e| Error::SelectError("sources", e))
+    }
+
+    /// Gets a source if `user_id` is subscribed to it
+    pub async fn get_by_id_for_user(
+        id: i64,
+        user_id: i64,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Option<Self>, Error> {
+        sqlx::query_as!(
+            Source,
+            r#"
+        SELECT s.*
+        FROM sources s
+        JOIN subscriptions sub ON s.id = sub.source_id
+        WHERE s.id = ? AND sub.user_id = ?;
+        "#,
+            id,
+            user_id
+        )
+        .fetch_optional(executor)
+        .await
+        .map_err(|e| Error::SelectError("sources", e))
+    }
+
+    pub async fn get_by_url(
+        url: &str,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Option<Self>, Error> {
+        sqlx::query_as!(Source, "SELECT * FROM sources WHERE url = ?1", url)
+            .fetch_optional(executor)
+            .await
+            .map_err(|e| Error::SelectError("sources", e))
+    }
+
     pub async fn get_by_id(
         id: i64,
         executor: impl Executor<'_, Database = super::DB>,
@@ -101,8 +248,8 @@ impl Source {
     ) -> Result<(), Error> {
         let id = sqlx::query!(
             r#"
-		INSERT INTO sources(name, url, last_pub, last_poll, ttl, favorite, min_date)
-		VALUES ($1, $2, $3, $4, $5, $6, $7)
+		INSERT INTO sources(name, url, last_pub, last_poll, ttl, favorite, min_date, retain_done_for, retain_unread_for, headers, auth_username, auth_password, category_tags)
+		VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
 		"#,
             self.name,
             self.url,
@@ -110,7 +257,13 @@ impl Source {
             self.last_poll,
             self.ttl,
             self.favorite,
-            self.min_date
+            self.min_date,
+            self.retain_done_for,
+            self.retain_unread_for,
+            self.headers,
+            self.auth_username,
+            self.auth_password,
+            self.category_tags
         )
         .execute(executor)
         .await
@@ -154,6 +307,129 @@ impl Source {
         Ok(())
     }
 
+    pub async fn set_retention(
+        id: i64,
+        done_for: Option<i64>,
+        unread_for: Option<i64>,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            "UPDATE sources SET retain_done_for = ?1, retain_unread_for = ?2 WHERE id = ?3",
+            done_for,
+            unread_for,
+            id
+        )
+        .execute(executor)
+        .await
+        .map_err(|e| Error::UpdateError("sources", e))
+        .map(|_| ())
+    }
+
+    pub async fn set_credentials(
+        id: i64,
+        headers: Option<&str>,
+        auth_username: Option<&str>,
+        auth_password: Option<&str>,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            "UPDATE sources SET headers = ?1, auth_username = ?2, auth_password = ?3 WHERE id = ?4",
+            headers,
+            auth_username,
+            auth_password,
+            id
+        )
+        .execute(executor)
+        .await
+        .map_err(|e| Error::UpdateError("sources", e))
+        .map(|_| ())
+    }
+
+    pub async fn set_category_tags(
+        id: i64,
+        category_tags: Option<CategoryTags>,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<(), Error> {
+        let category_tags = category_tags.map(CategoryTags::as_str);
+        sqlx::query!(
+            "UPDATE sources SET category_tags = ?1 WHERE id = ?2",
+            category_tags,
+            id
+        )
+        .execute(executor)
+        .await
+        .map_err(|e| Error::UpdateError("sources", e))
+        .map(|_| ())
+    }
+
+    /// Records a WebSub subscription request, the lease is set once the hub verifies it
+    pub async fn set_websub(
+        id: i64,
+        hub: Option<&str>,
+        topic: Option<&str>,
+        secret: Option<&str>,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+        UPDATE sources
+        SET
+            websub_hub = ?1,
+            websub_topic = ?2,
+            websub_secret = ?3,
+            websub_lease_expires_at = NULL,
+            websub_requested_at = NULL,
+            websub_attempts = 0
+        WHERE id = ?4
+        "#,
+            hub,
+            topic,
+            secret,
+            id
+        )
+        .execute(executor)
+        .await
+        .map_err(|e| Error::UpdateError("sources", e))
+        .map(|_| ())
+    }
+
+    /// Records that we sent the hub a subscription request
+    pub async fn websub_requested(
+        id: i64,
+        now: chrono::NaiveDateTime,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+        UPDATE sources
+        SET websub_requested_at = ?1, websub_attempts = websub_attempts + 1
+        WHERE id = ?2
+        "#,
+            now,
+            id
+        )
+        .execute(executor)
+        .await
+        .map_err(|e| Error::UpdateError("sources", e))
+        .map(|_| ())
+    }
+
+    pub async fn set_websub_lease(
+        id: i64,
+        expires_at: Option<chrono::NaiveDateTime>,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            "UPDATE sources SET websub_lease_expires_at = ?1, websub_attempts = 0 WHERE id = ?2",
+            expires_at,
+            id
+        )
+        .execute(executor)
+        .await
+        .map_err(|e| Error::UpdateError("sources", e))
+        .map(|_| ())
+    }
+
     pub async fn add_tags(
         id: i64,
         tags: &[&str],
@@ -221,14 +497,94 @@ impl Source {
         .map_err(|e| Error::SelectError("sources_to_tags", e))
     }
 
-    pub async fn delete(
+    /// Unsubscribes `user_id`, then deletes the source if nobody else is subscribed to it and
+    /// handles its items according to `items`. Returns whether the source was deleted.
+    pub async fn unsubscribe(
         id: i64,
-        executor: impl Executor<'_, Database = super::DB>,
-    ) -> Result<(), Error> {
-        sqlx::query!("DELETE FROM items WHERE id = ?", id)
-            .execute(executor)
+        user_id: i64,
+        items: OnSourceDelete,
+        sqlite: &sqlx::Pool<super::DB>,
+    ) -> Result<bool, Error> {
+        let mut tx = sqlite
+            .begin()
             .await
-            .map_err(|e| Error::DeleteError("tags", e))
-            .map(|_| ())
+            .map_err(|e| Error::DeleteError("sources", e))?;
+
+        User::unsubscribe(user_id, id, &mut *tx).await?;
+
+        // The source goes first so deleting its items doesn't leave tombstones
+        let deleted = sqlx::query!(
+            r#"
+        DELETE FROM sources
+        WHERE id = ?1 AND NOT EXISTS (SELECT 1 FROM subscriptions WHERE source_id = ?1)
+        "#,
+            id
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| Error::DeleteError("sources", e))?
+        .rows_affected()
+            > 0;
+        if !deleted {
+            tx.commit()
+                .await
+                .map_err(|e| Error::DeleteError("subscriptions", e))?;
+            return Ok(false);
+        }
+
+        match items {
+            OnSourceDelete::Delete => {
+                sqlx::query!("DELETE FROM items WHERE source_id = ?", id)
+                    .execute(&mut *tx)
+                    .await
+                    .map_err(|e| Error::DeleteError("items", e))?;
+            }
+            OnSourceDelete::KeepFavorites => {
+                sqlx::query!(
+                    r#"
+                DELETE FROM items
+                WHERE source_id = ?1
+                    AND id NOT IN (SELECT item_id FROM user_items WHERE favorite)
+                "#,
+                    id
+                )
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| Error::DeleteError("items", e))?;
+            }
+            OnSourceDelete::Keep => {}
+        }
+        // Whatever is left stays around like items added by hand, which are one per link. Items
+        // linking a page that's already kept are merged into it.
+        let kept = sqlx::query!(
+            "SELECT id, link FROM items WHERE source_id = ? ORDER BY id",
+            id
+        )
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(|e| Error::SelectError("items", e))?;
+        for item in kept {
+            let existing = sqlx::query_scalar!(
+                "SELECT id FROM items WHERE link = ? AND source_id IS NULL",
+                item.link
+            )
+            .fetch_optional(&mut *tx)
+            .await
+            .map_err(|e| Error::SelectError("items", e))?;
+            match existing {
+                Some(into) => Item::merge_into(item.id, into, &mut tx).await?,
+                None => {
+                    sqlx::query!("UPDATE items SET source_id = NULL WHERE id = ?", item.id)
+                        .execute(&mut *tx)
+                        .await
+                        .map_err(|e| Error::UpdateError("items", e))?;
+                }
+            }
+        }
+
+        tx.commit()
+            .await
+            .map_err(|e| Error::DeleteError("sources", e))?;
+        Ok(true)
     }
 }
@@ -0,0 +1,201 @@
+//! Counts over a user's items for dashboards. Everything is aggregated in SQLite, only the
+//! results come back.
+
+use chrono::{Days, NaiveDate, NaiveDateTime};
+use rustc_hash::FxHashMap;
+use serde::Serialize;
+use sqlx::prelude::*;
+
+use super::{item::visible_items, Error};
+
+/// Items `?1` can see and hasn't had archived, with their state for `?1`
+const VISIBLE_ITEMS: &str = concat!(
+    r#"
+    WITH visible AS (
+        SELECT
+            i.id,
+            i.source_id,
+            i.created_at,
+            MIN(COALESCE(i.published, i.created_at), i.created_at) AS published,
+            COALESCE(ui.done, FALSE) AS done,
+            COALESCE(ui.favorite, FALSE) AS favorite"#,
+    visible_items!(),
+    r#"
+        AND i.archived_at IS NULL
+    )
+"#
+);
+
+#[derive(Debug, Serialize, ts_rs::TS)]
+#[ts(export, export_to = "../web/src/types/Stats.ts")]
+pub struct Stats {
+    pub sources: Vec<SourceStats>,
+    pub tags: Vec<TagStats>,
+    /// Items fetched each day, oldest first, including days without any
+    pub per_day: Vec<DayStats>,
+}
+
+#[derive(Debug, Serialize, FromRow, ts_rs::TS)]
+#[ts(export, export_to = "../web/src/types/SourceStats.ts")]
+pub struct SourceStats {
+    #[ts(type = "number")]
+    pub source_id: i64,
+    pub name: String,
+    #[ts(type = "number")]
+    pub total: i64,
+    #[ts(type = "number")]
+    pub unread: i64,
+    #[ts(type = "number")]
+    pub favorite: i64,
+    /// Average items published per day between its oldest and newest item, none until there are
+    /// two items a while apart
+    pub posts_per_day: Option<f64>,
+}
+
+#[derive(Debug, Serialize, FromRow, ts_rs::TS)]
+#[ts(export, export_to = "../web/src/types/TagStats.ts")]
+pub struct TagStats {
+    pub tag: String,
+    #[ts(type = "number")]
+    pub total: i64,
+    #[ts(type = "number")]
+    pub unread: i64,
+    #[ts(type = "number")]
+    pub favorite: i64,
+}
+
+#[derive(Debug, Serialize, FromRow, ts_rs::TS)]
+#[ts(export, export_to = "../web/src/types/DayStats.ts")]
+pub struct DayStats {
+    #[ts(type = "string")]
+    pub day: NaiveDate,
+    #[ts(type = "number")]
+    pub items: i64,
+}
+
+impl Stats {
+    /// Stats for `user_id`, counting items fetched over the last `days` days (including today) in
+    /// UTC
+    pub async fn load(
+        user_id: i64,
+        days: u64,
+        now: NaiveDateTime,
+        sqlite: &sqlx::Pool<super::DB>,
+    ) -> Result<Self, Error> {
+        // Count everything from one snapshot so the parts add up
+        let mut tx = sqlite
+            .begin()
+            .await
+            .map_err(|e| Error::SelectError("items", e))?;
+
+        Ok(Self {
+            sources: SourceStats::load(user_id, &mut *tx).await?,
+            tags: TagStats::load(user_id, &mut *tx).await?,
+            per_day: DayStats::load(user_id, days, now, &mut *tx).await?,
+        })
+    }
+}
+
+impl SourceStats {
+    /// Every source `user_id` is subscribed to, even ones without items
+    pub async fn load(
+        user_id: i64,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Vec<Self>, Error> {
+        sqlx::query_as(&format!(
+            r#"
+            {VISIBLE_ITEMS}
+            SELECT
+                s.id AS source_id,
+                s.name,
+                COUNT(v.id) AS total,
+                COALESCE(SUM(NOT v.done), 0) AS unread,
+                COALESCE(SUM(v.favorite), 0) AS favorite,
+                (COUNT(v.id) - 1)
+                    / NULLIF(julianday(MAX(v.published)) - julianday(MIN(v.published)), 0)
+                    AS posts_per_day
+            FROM sources s
+            JOIN subscriptions sub ON s.id = sub.source_id AND sub.user_id = ?1
+            LEFT JOIN visible v ON s.id = v.source_id
+            GROUP BY s.id
+            ORDER BY s.name, s.id;
+            "#
+        ))
+        .bind(user_id)
+        .fetch_all(executor)
+        .await
+        .map_err(|e| Error::SelectError("items", e))
+    }
+}
+
+impl TagStats {
+    /// Tags on at least one of `user_id`'s items, counting the tags they added themselves
+    pub async fn load(
+        user_id: i64,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Vec<Self>, Error> {
+        sqlx::query_as(&format!(
+            r#"
+            {VISIBLE_ITEMS},
+            tagged AS (
+                SELECT item_id, tag_id FROM items_to_tags
+                UNION
+                SELECT item_id, tag_id FROM user_items_to_tags WHERE user_id = ?1
+            )
+            SELECT
+                t.tag_id AS tag,
+                COUNT(*) AS total,
+                COALESCE(SUM(NOT v.done), 0) AS unread,
+                COALESCE(SUM(v.favorite), 0) AS favorite
+            FROM tagged t
+            JOIN visible v ON t.item_id = v.id
+            GROUP BY t.tag_id
+            ORDER BY t.tag_id;
+            "#
+        ))
+        .bind(user_id)
+        .fetch_all(executor)
+        .await
+        .map_err(|e| Error::SelectError("items", e))
+    }
+}
+
+impl DayStats {
+    pub async fn load(
+        user_id: i64,
+        days: u64,
+        now: NaiveDateTime,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Vec<Self>, Error> {
+        let today = now.date();
+        let first_day = today
+            .checked_sub_days(Days::new(days.saturating_sub(1)))
+            .unwrap_or(NaiveDate::MIN);
+        let counts = sqlx::query_as::<_, Self>(&format!(
+            r#"
+            {VISIBLE_ITEMS}
+            SELECT date(created_at) AS day, COUNT(*) AS items
+            FROM visible
+            WHERE created_at >= ?2
+            GROUP BY date(created_at);
+            "#
+        ))
+        .bind(user_id)
+        .bind(first_day.and_time(Default::default()))
+        .fetch_all(executor)
+        .await
+        .map_err(|e| Error::SelectError("items", e))?
+        .into_iter()
+        .map(|day| (day.day, day.items))
+        .collect::<FxHashMap<_, _>>();
+
+        Ok(first_day
+            .iter_days()
+            .take_while(|day| *day <= today)
+            .map(|day| Self {
+                items: counts.get(&day).copied().unwrap_or_default(),
+                day,
+            })
+            .collect())
+    }
+}
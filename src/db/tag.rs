@@ -1,14 +1,19 @@
+use std::{collections::BTreeMap, str::FromStr};
+
 use futures::TryFutureExt;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use sqlx::prelude::*;
 
-use super::Error;
+use super::{Error, SmartFeed};
 
 #[derive(Debug, FromRow, Serialize, Deserialize, ts_rs::TS)]
 #[ts(export, export_to = "../web/src/types/Tag.ts")]
 pub struct Tag {
     pub name: String,
+    /// Tag this one is nested under, filtering by a tag includes everything under it
+    #[serde(default)]
+    pub parent: Option<String>,
     pub background_color: Option<String>,
     pub text_color: Option<String>,
     pub border_color: Option<String>,
@@ -18,6 +23,165 @@ pub struct Tag {
     pub updated_at: chrono::NaiveDateTime,
 }
 
+/// A tag with the tags nested under it, like an outline in OPML
+#[derive(Debug, Serialize, ts_rs::TS)]
+#[ts(export, export_to = "../web/src/types/TagNode.ts")]
+pub struct TagNode {
+    #[serde(flatten)]
+    #[ts(flatten)]
+    pub tag: Tag,
+    pub children: Vec<TagNode>,
+}
+
+impl TagNode {
+    /// Nests `tags` under their parents, top level tags first. Children are sorted by name.
+    pub fn tree(tags: Vec<Tag>) -> Vec<Self> {
+        let mut by_parent = BTreeMap::<Option<String>, Vec<Tag>>::new();
+        for tag in tags {
+            by_parent.entry(tag.parent.clone()).or_default().push(tag);
+        }
+
+        fn children(
+            parent: Option<String>,
+            by_parent: &mut BTreeMap<Option<String>, Vec<Tag>>,
+        ) -> Vec<TagNode> {
+            let mut tags = by_parent.remove(&parent).unwrap_or_default();
+            tags.sort_by(|a, b| a.name.cmp(&b.name));
+            tags.into_iter()
+                .map(|tag| TagNode {
+                    children: children(Some(tag.name.clone()), by_parent),
+                    tag,
+                })
+                .collect()
+        }
+        children(None, &mut by_parent)
+    }
+}
+
+/// A recursive `table(root, name)` for a `WITH RECURSIVE` clause, pairing each of the `roots` tags
+/// bound from `?{first_param}` on with itself and every tag nested under it
+pub(super) fn tag_tree_cte(table: &str, first_param: usize, roots: usize) -> String {
+    format!(
+        r#"
+        {table}(root, name) AS (
+            SELECT name, name FROM tags WHERE name IN ({})
+            UNION
+            SELECT {table}.root, tags.name FROM tags JOIN {table} ON tags.parent = {table}.name
+        )
+        "#,
+        (first_param..first_param + roots)
+            .map(|param| format!("?{param}"))
+            .join(", ")
+    )
+}
+
+/// How categories from feeds turn into tags on their items
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ts_rs::TS)]
+#[ts(export, export_to = "../web/src/types/CategoryTags.ts")]
+#[serde(rename_all = "snake_case")]
+pub enum CategoryTags {
+    /// Create a tag for every category
+    #[default]
+    All,
+    /// Only tag items with categories that already exist as tags (or aliases)
+    Existing,
+    /// Don't tag items with their categories at all
+    Ignore,
+}
+
+impl CategoryTags {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::All => "all",
+            Self::Existing => "existing",
+            Self::Ignore => "ignore",
+        }
+    }
+}
+
+impl FromStr for CategoryTags {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(Self::All),
+            "existing" => Ok(Self::Existing),
+            "ignore" => Ok(Self::Ignore),
+            _ => Err(format!("{s} isn't one of all, existing or ignore")),
+        }
+    }
+}
+
+/// A tag with how much it's used, for finding ones nobody needs
+#[derive(Debug, FromRow, Serialize, ts_rs::TS)]
+#[ts(export, export_to = "../web/src/types/TagUsage.ts")]
+pub struct TagUsage {
+    pub name: String,
+    /// Items tagged with it by their feed or by a user
+    #[ts(type = "number")]
+    pub items: i64,
+    #[ts(type = "number")]
+    pub sources: i64,
+    /// Whether categories are aliased to it
+    pub aliased: bool,
+    /// Tags nested directly under it
+    #[ts(type = "number")]
+    pub children: i64,
+    #[serde(serialize_with = "super::utc::serialize")]
+    #[ts(type = "string")]
+    pub created_at: chrono::NaiveDateTime,
+}
+
+/// Categories from feeds that are tagged with `tag_id` instead of getting their own tag
+#[derive(Debug, FromRow, Serialize, Deserialize, ts_rs::TS)]
+#[ts(export, export_to = "../web/src/types/TagAlias.ts")]
+pub struct TagAlias {
+    pub alias: String,
+    pub tag_id: String,
+    #[serde(skip_deserializing, serialize_with = "super::utc::serialize")]
+    #[ts(type = "string")]
+    pub created_at: chrono::NaiveDateTime,
+}
+
+impl TagAlias {
+    pub async fn get_all(
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Vec<Self>, Error> {
+        sqlx::query_as!(TagAlias, "SELECT * FROM tag_aliases ORDER BY alias")
+            .fetch_all(executor)
+            .await
+            .map_err(|e| Error::SelectError("tag_aliases", e))
+    }
+
+    /// Points `alias` at `tag_id`, replacing where it pointed before
+    pub async fn insert(
+        alias: &str,
+        tag_id: &str,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            "INSERT OR REPLACE INTO tag_aliases (alias, tag_id) VALUES (?1, ?2)",
+            alias,
+            tag_id
+        )
+        .execute(executor)
+        .await
+        .map_err(|e| Error::InsertError("tag_aliases", e))
+        .map(|_| ())
+    }
+
+    pub async fn delete(
+        alias: &str,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<(), Error> {
+        sqlx::query!("DELETE FROM tag_aliases WHERE alias = ?", alias)
+            .execute(executor)
+            .await
+            .map_err(|e| Error::DeleteError("tag_aliases", e))
+            .map(|_| ())
+    }
+}
+
 impl Tag {
     pub async fn get_all(
         executor: impl Executor<'_, Database = super::DB>,
@@ -51,13 +215,14 @@ impl Tag {
     ) -> Result<(), Error> {
         sqlx::query!(
             r#"
-		INSERT INTO tags(name, background_color, text_color, border_color)
-		VALUES (?1, ?2, ?3, ?4)
+		INSERT INTO tags(name, background_color, text_color, border_color, parent)
+		VALUES (?1, ?2, ?3, ?4, ?5)
 		"#,
             self.name,
             self.background_color,
             self.text_color,
             self.border_color,
+            self.parent,
         )
         .execute(executor)
         .await
@@ -76,12 +241,14 @@ impl Tag {
         SET
             background_color = ?1,
             text_color = ?2,
-            border_color = ?3
-        WHERE name = ?4
+            border_color = ?3,
+            parent = ?4
+        WHERE name = ?5
         "#,
             self.background_color,
             self.text_color,
             self.border_color,
+            self.parent,
             self.name
         )
         .execute(executor)
@@ -90,10 +257,96 @@ impl Tag {
         .map(|_| ())
     }
 
+    /// Names of every tag nested under `name`, however deep
+    pub async fn descendants(
+        name: &str,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Vec<String>, Error> {
+        sqlx::query_scalar!(
+            r#"
+        WITH RECURSIVE descendants(name) AS (
+            SELECT name FROM tags WHERE parent = ?1
+            UNION
+            SELECT tags.name FROM tags JOIN descendants ON tags.parent = descendants.name
+        )
+        SELECT name AS "name!: String" FROM descendants
+        "#,
+            name
+        )
+        .fetch_all(executor)
+        .await
+        .map_err(|e| Error::SelectError("tags", e))
+    }
+
+    /// Every tag with its usage, least used first
+    pub async fn usage(
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Vec<TagUsage>, Error> {
+        sqlx::query_as!(
+            TagUsage,
+            r#"
+        SELECT
+            t.name,
+            (
+                SELECT COUNT(*) FROM (
+                    SELECT item_id FROM items_to_tags WHERE tag_id = t.name
+                    UNION
+                    SELECT item_id FROM user_items_to_tags WHERE tag_id = t.name
+                )
+            ) AS "items!: i64",
+            (SELECT COUNT(*) FROM sources_to_tags WHERE tag_id = t.name) AS "sources!: i64",
+            EXISTS (SELECT 1 FROM tag_aliases WHERE tag_id = t.name) AS "aliased!: bool",
+            (SELECT COUNT(*) FROM tags c WHERE c.parent = t.name) AS "children!: i64",
+            t.created_at
+        FROM tags t
+        ORDER BY 2, 3, t.name
+        "#
+        )
+        .fetch_all(executor)
+        .await
+        .map_err(|e| Error::SelectError("tags", e))
+    }
+
+    /// Deletes tags on at most `max_items` items that no source is tagged with, no category is
+    /// aliased to and no tag is nested under. Returns the sorted names of the deleted tags.
+    pub async fn prune(
+        max_items: i64,
+        created_before: chrono::NaiveDateTime,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Vec<String>, Error> {
+        sqlx::query_scalar!(
+            r#"
+        DELETE FROM tags
+        WHERE created_at < ?2
+            AND name NOT IN (SELECT tag_id FROM sources_to_tags)
+            AND name NOT IN (SELECT tag_id FROM tag_aliases)
+            AND name NOT IN (SELECT parent FROM tags WHERE parent IS NOT NULL)
+            AND (
+                SELECT COUNT(*) FROM (
+                    SELECT item_id FROM items_to_tags WHERE tag_id = tags.name
+                    UNION
+                    SELECT item_id FROM user_items_to_tags WHERE tag_id = tags.name
+                )
+            ) <= ?1
+        RETURNING name
+        "#,
+            max_items,
+            created_before
+        )
+        .fetch_all(executor)
+        .await
+        .map_err(|e| Error::DeleteError("tags", e))
+        // RETURNING doesn't follow any order
+        .map(|names| names.into_iter().sorted().collect())
+    }
+
     pub async fn insert_many(
         tags: &[Self],
         executor: impl Executor<'_, Database = super::DB>,
     ) -> Result<(), Error> {
+        if tags.is_empty() {
+            return Ok(());
+        }
         let sql = format!(
             r#"
 		INSERT OR IGNORE INTO tags(name, background_color, text_color, border_color)
@@ -121,6 +374,130 @@ impl Tag {
             .map(|_| ())
     }
 
+    /// Renames a tag everywhere it's used. The old name becomes an alias so categories from feeds
+    /// keep ending up on it.
+    pub async fn rename(
+        name: &str,
+        new_name: &str,
+        sqlite: &sqlx::Pool<super::DB>,
+    ) -> Result<(), Error> {
+        let mut tx = sqlite
+            .begin()
+            .await
+            .map_err(|e| Error::UpdateError("tags", e))?;
+        sqlx::query!(
+            r#"
+        INSERT INTO tags (name, background_color, text_color, border_color, parent, created_at)
+        SELECT ?2, background_color, text_color, border_color, parent, created_at
+        FROM tags
+        WHERE name = ?1
+        "#,
+            name,
+            new_name
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| Error::InsertError("tags", e))?;
+        Self::move_to(name, new_name, &mut tx).await?;
+        tx.commit().await.map_err(|e| Error::UpdateError("tags", e))
+    }
+
+    /// Moves everything tagged with `names` onto `into` and deletes them. Their names become
+    /// aliases of `into`.
+    pub async fn merge(
+        names: &[&str],
+        into: &str,
+        sqlite: &sqlx::Pool<super::DB>,
+    ) -> Result<(), Error> {
+        let mut tx = sqlite
+            .begin()
+            .await
+            .map_err(|e| Error::UpdateError("tags", e))?;
+        for name in names.iter().filter(|name| **name != into) {
+            Self::move_to(name, into, &mut tx).await?;
+        }
+        tx.commit().await.map_err(|e| Error::UpdateError("tags", e))
+    }
+
+    /// Retags everything from `from` with `into`, which must exist, then deletes `from`
+    async fn move_to(
+        from: &str,
+        into: &str,
+        conn: &mut sqlx::SqliteConnection,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+        INSERT OR IGNORE INTO items_to_tags (item_id, tag_id, created_at)
+        SELECT item_id, ?2, created_at FROM items_to_tags WHERE tag_id = ?1
+        "#,
+            from,
+            into
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| Error::InsertError("items_to_tags", e))?;
+        sqlx::query!(
+            r#"
+        INSERT OR IGNORE INTO sources_to_tags (source_id, tag_id, created_at)
+        SELECT source_id, ?2, created_at FROM sources_to_tags WHERE tag_id = ?1
+        "#,
+            from,
+            into
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| Error::InsertError("sources_to_tags", e))?;
+        sqlx::query!(
+            r#"
+        INSERT OR IGNORE INTO user_items_to_tags (user_id, item_id, tag_id, created_at)
+        SELECT user_id, item_id, ?2, created_at FROM user_items_to_tags WHERE tag_id = ?1
+        "#,
+            from,
+            into
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| Error::InsertError("user_items_to_tags", e))?;
+        // Webhook filters are comma separated names
+        sqlx::query!(
+            r#"
+        UPDATE webhooks
+        SET tags = TRIM(REPLACE(',' || tags || ',', ',' || ?1 || ',', ',' || ?2 || ','), ',')
+        WHERE instr(',' || tags || ',', ',' || ?1 || ',') > 0
+        "#,
+            from,
+            into
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| Error::UpdateError("webhooks", e))?;
+        SmartFeed::retag(from, into, &mut *conn).await?;
+        sqlx::query!(
+            "UPDATE tag_aliases SET tag_id = ?2 WHERE tag_id = ?1",
+            from,
+            into
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| Error::UpdateError("tag_aliases", e))?;
+        sqlx::query!(
+            "UPDATE tags SET parent = ?2 WHERE parent = ?1 AND name != ?2",
+            from,
+            into
+        )
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| Error::UpdateError("tags", e))?;
+
+        // Deleting cascades to whatever still points at it
+        Self::delete(from, &mut *conn).await?;
+        sqlx::query!("DELETE FROM tag_aliases WHERE alias = ?", into)
+            .execute(&mut *conn)
+            .await
+            .map_err(|e| Error::DeleteError("tag_aliases", e))?;
+        TagAlias::insert(from, into, &mut *conn).await
+    }
+
     pub async fn delete(
         name: &str,
         executor: impl Executor<'_, Database = super::DB>,
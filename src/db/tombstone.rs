@@ -0,0 +1,56 @@
+use serde::Serialize;
+use sqlx::prelude::*;
+
+use super::Error;
+
+/// A deleted item's link and guid, which ingest skips until the tombstone expires
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct Tombstone {
+    pub source_id: i64,
+    pub link: String,
+    pub created_at: chrono::NaiveDateTime,
+    pub guid: Option<String>,
+}
+
+impl Tombstone {
+    /// Tombstones from `source_id` matching any of `links` or `guids`
+    pub async fn buried(
+        source_id: i64,
+        links: &[&str],
+        guids: &[&str],
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Vec<Self>, Error> {
+        // json_each lets us bind any number of values as a single parameter
+        let links = serde_json::to_string(links).expect("strings serialize");
+        let guids = serde_json::to_string(guids).expect("strings serialize");
+        sqlx::query_as!(
+            Tombstone,
+            r#"
+        SELECT *
+        FROM tombstones
+        WHERE source_id = ?1 AND (
+            link IN (SELECT value FROM json_each(?2))
+            OR guid IN (SELECT value FROM json_each(?3))
+        );
+        "#,
+            source_id,
+            links,
+            guids
+        )
+        .fetch_all(executor)
+        .await
+        .map_err(|e| Error::SelectError("tombstones", e))
+    }
+
+    /// Forgets tombstones created before `cutoff`, returns how many
+    pub async fn delete_before(
+        cutoff: chrono::NaiveDateTime,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<u64, Error> {
+        sqlx::query!("DELETE FROM tombstones WHERE created_at < ?1", cutoff)
+            .execute(executor)
+            .await
+            .map_err(|e| Error::DeleteError("tombstones", e))
+            .map(|result| result.rows_affected())
+    }
+}
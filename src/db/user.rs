@@ -0,0 +1,127 @@
+use serde::{Deserialize, Serialize};
+use sqlx::prelude::*;
+
+use super::Error;
+
+/// Id of the user that owns the instance and authenticates with `Config::password`
+pub const OWNER_ID: i64 = 1;
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, ts_rs::TS)]
+#[ts(export, export_to = "../web/src/types/User.ts")]
+pub struct User {
+    #[ts(type = "number")]
+    #[serde(skip_deserializing)]
+    pub id: i64,
+
+    pub name: String,
+
+    #[serde(skip_serializing, default)]
+    #[ts(skip)]
+    pub password_hash: Option<String>,
+
+    #[serde(default)]
+    pub admin: bool,
+
+    #[serde(skip_deserializing)]
+    pub created_at: chrono::NaiveDateTime,
+
+    #[serde(skip_deserializing)]
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+impl User {
+    pub async fn get_all(
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Vec<Self>, Error> {
+        sqlx::query_as!(User, "SELECT * FROM users")
+            .fetch_all(executor)
+            .await
+            .map_err(|e| Error::SelectError("users", e))
+    }
+
+    pub async fn get_by_id(
+        id: i64,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Option<Self>, Error> {
+        sqlx::query_as!(User, "SELECT * FROM users WHERE id = ?1", id)
+            .fetch_optional(executor)
+            .await
+            .map_err(|e| Error::SelectError("users", e))
+    }
+
+    pub async fn get_by_name(
+        name: &str,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Option<Self>, Error> {
+        sqlx::query_as!(User, "SELECT * FROM users WHERE name = ?1", name)
+            .fetch_optional(executor)
+            .await
+            .map_err(|e| Error::SelectError("users", e))
+    }
+
+    /// Inserts self into the database and populates its `id` field
+    pub async fn insert(
+        &mut self,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<(), Error> {
+        let id = sqlx::query!(
+            r#"
+		INSERT INTO users(name, password_hash, admin)
+		VALUES (?1, ?2, ?3)
+		"#,
+            self.name,
+            self.password_hash,
+            self.admin
+        )
+        .execute(executor)
+        .await
+        .map_err(|e| Error::InsertError("users", e))?
+        .last_insert_rowid();
+
+        self.id = id;
+        Ok(())
+    }
+
+    pub async fn delete(
+        id: i64,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<(), Error> {
+        sqlx::query!("DELETE FROM users WHERE id = ?", id)
+            .execute(executor)
+            .await
+            .map_err(|e| Error::DeleteError("users", e))
+            .map(|_| ())
+    }
+
+    pub async fn subscribe(
+        id: i64,
+        source_id: i64,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            "INSERT INTO subscriptions (user_id, source_id) VALUES (?, ?)",
+            id,
+            source_id
+        )
+        .execute(executor)
+        .await
+        .map_err(|e| Error::InsertError("subscriptions", e))
+        .map(|_| ())
+    }
+
+    pub async fn unsubscribe(
+        id: i64,
+        source_id: i64,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            "DELETE FROM subscriptions WHERE user_id = ? AND source_id = ?",
+            id,
+            source_id
+        )
+        .execute(executor)
+        .await
+        .map_err(|e| Error::DeleteError("subscriptions", e))
+        .map(|_| ())
+    }
+}
@@ -0,0 +1,39 @@
+//! Timestamps are stored as naive UTC, these (de)serialize them as RFC 3339 with an explicit `Z`
+//! so clients don't read them as local time. Deserializing also accepts other offsets (converted to
+//! UTC) and naive timestamps (assumed to be UTC already).
+
+use chrono::{DateTime, NaiveDateTime, SecondsFormat};
+use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+pub fn serialize<S: Serializer>(date: &NaiveDateTime, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&date.and_utc().to_rfc3339_opts(SecondsFormat::AutoSi, true))
+}
+
+fn parse(s: &str) -> Option<NaiveDateTime> {
+    DateTime::parse_from_rfc3339(s)
+        .map(|date| date.naive_utc())
+        .or_else(|_| s.parse::<NaiveDateTime>())
+        .ok()
+}
+
+pub mod option {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        date: &Option<NaiveDateTime>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match date {
+            Some(date) => super::serialize(date, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<NaiveDateTime>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|s| parse(&s).ok_or_else(|| D::Error::custom(format!("invalid timestamp {s:?}"))))
+            .transpose()
+    }
+}
@@ -0,0 +1,273 @@
+use serde::{Deserialize, Serialize};
+use sqlx::prelude::*;
+
+use super::Error;
+
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize, ts_rs::TS)]
+#[ts(export, export_to = "../web/src/types/Webhook.ts")]
+pub struct Webhook {
+    #[ts(type = "number")]
+    #[serde(skip_deserializing)]
+    pub id: i64,
+
+    #[ts(type = "number")]
+    #[serde(skip_deserializing)]
+    pub user_id: i64,
+
+    pub url: String,
+
+    /// Used to sign payloads with HMAC-SHA256
+    #[serde(skip_serializing, default)]
+    #[ts(skip)]
+    pub secret: Option<String>,
+
+    /// Comma separated tags, the item must have one of them
+    #[serde(default)]
+    pub tags: Option<String>,
+
+    /// Comma separated source ids, the item must be from one of them
+    #[serde(default)]
+    pub source_ids: Option<String>,
+
+    #[serde(skip_deserializing)]
+    pub created_at: chrono::NaiveDateTime,
+
+    #[serde(skip_deserializing)]
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Clone, FromRow, Serialize, ts_rs::TS)]
+#[ts(export, export_to = "../web/src/types/WebhookDelivery.ts")]
+pub struct WebhookDelivery {
+    #[ts(type = "number")]
+    pub id: i64,
+
+    #[ts(type = "number")]
+    pub webhook_id: i64,
+
+    #[ts(type = "number")]
+    pub item_id: i64,
+
+    #[ts(type = "number")]
+    pub attempts: i64,
+
+    #[ts(type = "number | null")]
+    pub status_code: Option<i64>,
+
+    pub error: Option<String>,
+
+    pub next_attempt_at: Option<chrono::NaiveDateTime>,
+
+    pub delivered_at: Option<chrono::NaiveDateTime>,
+
+    pub created_at: chrono::NaiveDateTime,
+
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+impl Webhook {
+    pub async fn get_by_id(
+        id: i64,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Option<Self>, Error> {
+        sqlx::query_as!(Webhook, "SELECT * FROM webhooks WHERE id = ?1", id)
+            .fetch_optional(executor)
+            .await
+            .map_err(|e| Error::SelectError("webhooks", e))
+    }
+
+    pub async fn get_for_user(
+        user_id: i64,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Vec<Self>, Error> {
+        sqlx::query_as!(Webhook, "SELECT * FROM webhooks WHERE user_id = ?1", user_id)
+            .fetch_all(executor)
+            .await
+            .map_err(|e| Error::SelectError("webhooks", e))
+    }
+
+    /// Webhooks of users that can see items from `source_id`
+    pub async fn get_subscribed_to(
+        source_id: i64,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Vec<Self>, Error> {
+        sqlx::query_as!(
+            Webhook,
+            r#"
+        SELECT w.*
+        FROM webhooks w
+        JOIN subscriptions sub ON w.user_id = sub.user_id
+        WHERE sub.source_id = ?1;
+        "#,
+            source_id
+        )
+        .fetch_all(executor)
+        .await
+        .map_err(|e| Error::SelectError("webhooks", e))
+    }
+
+    /// Whether an item from `source_id` with `tags` passes this webhook's filters
+    pub fn matches(&self, source_id: Option<i64>, tags: &[&str]) -> bool {
+        let matches_source = self.source_ids.as_deref().is_none_or(|source_ids| {
+            source_ids
+                .split(',')
+                .filter_map(|id| id.trim().parse::<i64>().ok())
+                .any(|id| Some(id) == source_id)
+        });
+        let matches_tags = self.tags.as_deref().is_none_or(|filter| {
+            filter
+                .split(',')
+                .map(str::trim)
+                .any(|tag| tags.contains(&tag))
+        });
+
+        matches_source && matches_tags
+    }
+
+    /// Inserts self into the database and populates its `id` field
+    pub async fn insert(
+        &mut self,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<(), Error> {
+        let id = sqlx::query!(
+            r#"
+		INSERT INTO webhooks(user_id, url, secret, tags, source_ids)
+		VALUES (?1, ?2, ?3, ?4, ?5)
+		"#,
+            self.user_id,
+            self.url,
+            self.secret,
+            self.tags,
+            self.source_ids
+        )
+        .execute(executor)
+        .await
+        .map_err(|e| Error::InsertError("webhooks", e))?
+        .last_insert_rowid();
+
+        self.id = id;
+        Ok(())
+    }
+
+    pub async fn delete(
+        id: i64,
+        user_id: i64,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            "DELETE FROM webhooks WHERE id = ? AND user_id = ?",
+            id,
+            user_id
+        )
+        .execute(executor)
+        .await
+        .map_err(|e| Error::DeleteError("webhooks", e))
+        .map(|_| ())
+    }
+}
+
+impl WebhookDelivery {
+    /// Schedules a delivery of `item_id` to `webhook_id` right away
+    pub async fn enqueue(
+        webhook_id: i64,
+        item_id: i64,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<(), Error> {
+        let now = chrono::Utc::now().naive_utc();
+        sqlx::query!(
+            r#"
+        INSERT INTO webhook_deliveries (webhook_id, item_id, next_attempt_at)
+        VALUES (?1, ?2, ?3)
+        "#,
+            webhook_id,
+            item_id,
+            now
+        )
+        .execute(executor)
+        .await
+        .map_err(|e| Error::InsertError("webhook_deliveries", e))
+        .map(|_| ())
+    }
+
+    pub async fn get_due(
+        now: chrono::NaiveDateTime,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Vec<Self>, Error> {
+        sqlx::query_as!(
+            WebhookDelivery,
+            r#"
+        SELECT *
+        FROM webhook_deliveries
+        WHERE next_attempt_at <= ?1
+        ORDER BY next_attempt_at;
+        "#,
+            now
+        )
+        .fetch_all(executor)
+        .await
+        .map_err(|e| Error::SelectError("webhook_deliveries", e))
+    }
+
+    /// When the next attempt should be, if any
+    pub async fn next_attempt_at(
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Option<chrono::NaiveDateTime>, Error> {
+        sqlx::query_scalar!(
+            r#"SELECT MIN(next_attempt_at) AS "next_attempt_at: chrono::NaiveDateTime" FROM webhook_deliveries"#
+        )
+        .fetch_one(executor)
+        .await
+        .map_err(|e| Error::SelectError("webhook_deliveries", e))
+    }
+
+    pub async fn recent_for_user(
+        user_id: i64,
+        limit: i64,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<Vec<Self>, Error> {
+        sqlx::query_as!(
+            WebhookDelivery,
+            r#"
+        SELECT d.*
+        FROM webhook_deliveries d
+        JOIN webhooks w ON d.webhook_id = w.id
+        WHERE w.user_id = ?1
+        ORDER BY d.updated_at DESC
+        LIMIT ?2;
+        "#,
+            user_id,
+            limit
+        )
+        .fetch_all(executor)
+        .await
+        .map_err(|e| Error::SelectError("webhook_deliveries", e))
+    }
+
+    pub async fn update(
+        &self,
+        executor: impl Executor<'_, Database = super::DB>,
+    ) -> Result<(), Error> {
+        sqlx::query!(
+            r#"
+        UPDATE webhook_deliveries
+        SET
+            attempts = ?1,
+            status_code = ?2,
+            error = ?3,
+            next_attempt_at = ?4,
+            delivered_at = ?5
+        WHERE id = ?6
+        "#,
+            self.attempts,
+            self.status_code,
+            self.error,
+            self.next_attempt_at,
+            self.delivered_at,
+            self.id
+        )
+        .execute(executor)
+        .await
+        .map_err(|e| Error::UpdateError("webhook_deliveries", e))
+        .map(|_| ())
+    }
+}
@@ -1,7 +1,7 @@
-use std::io;
+use std::{io, time::Duration};
 
 use axum::response::IntoResponse;
-use http::StatusCode;
+use http::{header::RETRY_AFTER, StatusCode};
 use thiserror::Error;
 
 use crate::db;
@@ -20,6 +20,12 @@ pub enum ApiError {
     NotFound,
     #[error("Unauthorized")]
     Unauthorized,
+    #[error("Forbidden")]
+    Forbidden,
+    #[error("Bad request: {0}")]
+    BadRequest(String),
+    #[error("Rate limited for {0:?}")]
+    RateLimited(Duration),
 }
 
 impl IntoResponse for ApiError {
@@ -31,6 +37,14 @@ impl IntoResponse for ApiError {
                 (StatusCode::INTERNAL_SERVER_ERROR, "internal server error").into_response()
             }
             Self::Unauthorized => (StatusCode::UNAUTHORIZED, "unauthorized").into_response(),
+            Self::Forbidden => (StatusCode::FORBIDDEN, "forbidden").into_response(),
+            Self::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg).into_response(),
+            Self::RateLimited(retry_after) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(RETRY_AFTER, retry_after.as_secs().max(1).to_string())],
+                "too many requests",
+            )
+                .into_response(),
             Self::NotFound => (StatusCode::NOT_FOUND, "not found").into_response(),
         }
     }
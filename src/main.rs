@@ -59,7 +59,7 @@ async fn server(config: Config) -> color_eyre::Result<()> {
         .pragma("journal_mode", "WAL")
         .pragma("synchronous", "NORMAL");
     let sqlite = sqlx::SqlitePool::connect_with(sqlite_options).await?;
-    sqlx::migrate!().run(&sqlite).await?;
+    db::migrate(&sqlite).await?;
 
     let base_path = config.domain.path().trim_end_matches("/");
 
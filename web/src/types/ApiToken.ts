@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type ApiToken = { id: number, user_id: number, name: string, 
+/**
+ * Comma separated list of scopes
+ */
+scopes: string, last_used_at: string | null, created_at: string, };
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * How categories from feeds turn into tags on their items
+ */
+export type CategoryTags = "all" | "existing" | "ignore";
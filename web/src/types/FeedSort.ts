@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * What the feed is ordered (and `from_last` measured) by
+ */
+export type FeedSort = "published" | "fetched";
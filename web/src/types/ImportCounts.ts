@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * How many rows an import created, existing ones aren't counted
+ */
+export type ImportCounts = { tags: bigint, sources: bigint, items: bigint, };
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * An item's content before its feed changed it
+ */
+export type ItemRevision = { id: number, item_id: number, link: string, title: string | null, description: string | null, author: string | null, created_at: string, };
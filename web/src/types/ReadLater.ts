@@ -0,0 +1,22 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * An item in a user's read later queue
+ */
+export type ReadLater = { user_id: number, item_id: number, 
+/**
+ * Lower comes first
+ */
+position: number, 
+/**
+ * Percent of the item read
+ */
+progress: number, 
+/**
+ * Words in the item's extracted content
+ */
+word_count: number | null, 
+/**
+ * Set once the item is finished
+ */
+archived_at: string | null, created_at: string, updated_at: string, };
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { SmartFeedQuery } from "./SmartFeedQuery";
+
+/**
+ * A saved view of a user's items
+ */
+export type SmartFeed = { id: number, user_id: number, name: string, query: SmartFeedQuery, created_at: string, updated_at: string, };
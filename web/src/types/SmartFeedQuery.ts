@@ -0,0 +1,36 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { FeedSort } from "./FeedSort";
+
+/**
+ * Which items are in a smart feed, every filter that's set has to match. Tags include the tags
+ * nested under them.
+ */
+export type SmartFeedQuery = { 
+/**
+ * Items need every one of these tags
+ */
+all_tags: Array<string>, 
+/**
+ * Items need at least one of these tags
+ */
+any_tags: Array<string>, 
+/**
+ * Items can't have any of these tags
+ */
+none_tags: Array<string>, 
+/**
+ * Only items from these sources
+ */
+source_ids: Array<number>, 
+/**
+ * Only items from sources marked as favorite
+ */
+favorite_sources: boolean, done: boolean | null, favorite: boolean | null, 
+/**
+ * Only items newer than this, like `3days`, measured by `sort`
+ */
+max_age: string | null, 
+/**
+ * Text the title, description or author has to contain, ignoring ASCII case
+ */
+text: string | null, sort: FeedSort, };
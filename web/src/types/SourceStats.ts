@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type SourceStats = { source_id: number, name: string, total: number, unread: number, favorite: number, 
+/**
+ * Average items published per day between its oldest and newest item, none until there are
+ * two items a while apart
+ */
+posts_per_day: number | null, };
@@ -0,0 +1,10 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { DayStats } from "./DayStats";
+import type { SourceStats } from "./SourceStats";
+import type { TagStats } from "./TagStats";
+
+export type Stats = { sources: Array<SourceStats>, tags: Array<TagStats>, 
+/**
+ * Items fetched each day, oldest first, including days without any
+ */
+per_day: Array<DayStats>, };
@@ -0,0 +1,6 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Categories from feeds that are tagged with `tag_id` instead of getting their own tag
+ */
+export type TagAlias = { alias: string, tag_id: string, created_at: string, };
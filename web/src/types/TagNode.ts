@@ -0,0 +1,10 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * A tag with the tags nested under it, like an outline in OPML
+ */
+export type TagNode = { children: Array<TagNode>, name: string, 
+/**
+ * Tag this one is nested under, filtering by a tag includes everything under it
+ */
+parent: string | null, background_color: string | null, text_color: string | null, border_color: string | null, created_at: string, updated_at: string, };
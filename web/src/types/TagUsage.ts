@@ -0,0 +1,18 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * A tag with how much it's used, for finding ones nobody needs
+ */
+export type TagUsage = { name: string, 
+/**
+ * Items tagged with it by their feed or by a user
+ */
+items: number, sources: number, 
+/**
+ * Whether categories are aliased to it
+ */
+aliased: boolean, 
+/**
+ * Tags nested directly under it
+ */
+children: number, created_at: string, };
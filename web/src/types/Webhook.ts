@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type Webhook = { id: number, user_id: number, url: string, 
+/**
+ * Comma separated tags, the item must have one of them
+ */
+tags: string | null, 
+/**
+ * Comma separated source ids, the item must be from one of them
+ */
+source_ids: string | null, created_at: string, updated_at: string, };
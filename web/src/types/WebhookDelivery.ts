@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type WebhookDelivery = { id: number, webhook_id: number, item_id: number, attempts: number, status_code: number | null, error: string | null, next_attempt_at: string | null, delivered_at: string | null, created_at: string, updated_at: string, };
@@ -1,3 +1,27 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 
-export type Item = { id: number, link: string, title: string | null, description: string | null, author: string | null, published: string | null, source_link: string | null, image: string | null, favorite: boolean, done: boolean, created_at: string, updated_at: string, source_id: bigint | null, };
+export type Item = { id: number, link: string, title: string | null, description: string | null, author: string | null, 
+/**
+ * When the feed says the item was published, in UTC
+ */
+published: string | null, source_link: string | null, image: string | null, 
+/**
+ * When we fetched the item
+ */
+created_at: string, updated_at: string, source_id: bigint | null, 
+/**
+ * Set when the janitor archived the item instead of deleting it
+ */
+archived_at: string | null, 
+/**
+ * The feed's id for the item, items from the same source with the same guid are the same item
+ */
+guid: string | null, 
+/**
+ * Whether `guid` is also a url to the item
+ */
+guid_is_permalink: boolean, 
+/**
+ * When the feed last changed the item after we ingested it
+ */
+content_updated_at: string | null, };
@@ -1,3 +1,28 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { CategoryTags } from "./CategoryTags";
 
-export type Source = { id: number, name: string, url: string, lastPub: string, lastPoll: string | null, ttl: number | null, favorite: boolean, minDate: string | null, createdAt: string, updatedAt: string, };
+export type Source = { id: number, name: string, url: string, lastPub: string, lastPoll: string | null, ttl: number | null, favorite: boolean, 
+/**
+ * Items published before this are ignored, in UTC
+ */
+minDate: string | null, createdAt: string, updatedAt: string, 
+/**
+ * WebSub hub the feed advertised, if we subscribed to it
+ */
+websubHub: string | null, websubTopic: string | null, websubLeaseExpiresAt: string | null, 
+/**
+ * Overrides how many seconds items everyone is done with are kept, 0 keeps them forever
+ */
+retainDoneFor: number | null, 
+/**
+ * Overrides how many seconds unfinished items are kept, 0 keeps them forever
+ */
+retainUnreadFor: number | null, 
+/**
+ * Basic auth for fetching the feed
+ */
+authUsername: string | null, 
+/**
+ * Overrides `category_tags` from the config, one of `all`, `existing` or `ignore`
+ */
+categoryTags: CategoryTags | null, };